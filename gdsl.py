@@ -185,6 +185,18 @@ pub struct Menu {
     pub opcode: &'static str,
     pub default: &'static str,
 }
+
+impl Menu {
+    /// The menu field for a string argument. `"random"` selects the random position in
+    /// the `goto` and `glide` menus, other strings are sprite names.
+    pub fn value(&self, arg: &str) -> String {
+        match (self.opcode, arg) {
+            ("motion_goto_menu" | "motion_glideto_menu", "random") => "_random_".into(),
+            _ => arg.to_string(),
+        }
+    }
+}
+
 """)
 f.write("#[derive(Debug, Copy, Clone)]\npub enum UnOp {")
 for un_op in un_ops:
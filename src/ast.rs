@@ -1,11 +1,20 @@
-use std::{cell::RefCell, path::Path, rc::Rc};
+use std::{
+    cell::RefCell,
+    fmt::{self, Display, Formatter},
+    path::Path,
+    rc::Rc,
+};
 
 use fxhash::{FxHashMap, FxHashSet};
 use logos::Span;
 use serde::Serialize;
 use smol_str::SmolStr;
 
-use crate::blocks::{BinOp, Block, Repr, UnOp};
+use crate::{
+    blocks::{BinOp, Block, Repr, UnOp},
+    diagnostic::DiagnosticDetail,
+    visitors::inline::{clone_expr, clone_stmts, deep_clone, Substitution},
+};
 
 pub type Rrc<T> = Rc<RefCell<T>>;
 
@@ -13,27 +22,410 @@ pub type Rrc<T> = Rc<RefCell<T>>;
 pub struct Project {
     pub stage: Sprite,
     pub sprites: FxHashMap<SmolStr, Sprite>,
+    pub monitors: Vec<Monitor>,
+    /// Names such as `__PROJECT_NAME`, which are replaced by their value.
+    pub constants: FxHashMap<SmolStr, SmolStr>,
+}
+
+/// A variable shown on the stage. `sprite` is `None` for variables of the stage.
+#[derive(Debug)]
+pub struct Monitor {
+    pub sprite: Option<SmolStr>,
+    pub name: SmolStr,
 }
 
 impl Project {
-    pub fn new(stage: Sprite, mut sprites: FxHashMap<SmolStr, Sprite>) -> Self {
+    pub fn new(mut stage: Sprite, mut sprites: FxHashMap<SmolStr, Sprite>) -> Self {
+        let payload_messages: Vec<SmolStr> = stage
+            .payload_messages
+            .iter()
+            .chain(sprites.values().flat_map(|sprite| &sprite.payload_messages))
+            .cloned()
+            .collect();
+        for message in payload_messages {
+            let name = payload_variable_name(&message);
+            let mut var = Var::new(name.clone(), 0..0, None);
+            var.used = true;
+            stage.vars.insert(name, var);
+        }
         for sprite in sprites.values_mut() {
             sprite.vars.retain(|name, _| !stage.vars.contains_key(name));
         }
-        Self { stage, sprites }
+        Self { stage, sprites, monitors: vec![], constants: Default::default() }
+    }
+
+    /// Declares the global clone counter, and resets it when the green flag is clicked.
+    pub fn add_clone_counter(&mut self) {
+        let name = SmolStr::from(CLONE_COUNTER);
+        let mut var = Var::new(name.clone(), 0..0, None);
+        var.used = true;
+        self.stage.vars.insert(name.clone(), var);
+        for sprite in self.sprites.values_mut() {
+            sprite.vars.remove(&name);
+        }
+        self.stage.events.push(EventDetail::OnFlag { priority: 0 }.to_event(
+            0..0,
+            vec![Stmt::SetVar {
+                name,
+                span: 0..0,
+                value: Expr::Int(0).into(),
+                is_local: false,
+            }],
+        ));
+    }
+
+    /// Shows the frame rate and the given variables on the stage. The frame rate is
+    /// measured by a stage script which compares the timer between frames. No variable
+    /// of the project may be named [`DEBUG_FPS`].
+    pub fn add_debug_overlay(&mut self, monitors: Vec<Monitor>) {
+        let fps = SmolStr::from(DEBUG_FPS);
+        let last = SmolStr::from(DEBUG_LAST_FRAME);
+        for name in [&fps, &last] {
+            let mut var = Var::new(name.clone(), 0..0, None);
+            var.used = true;
+            self.stage.vars.insert(name.clone(), var);
+        }
+        let timer = || -> Rrc<Expr> {
+            Expr::Repr { repr: Repr::Timer, span: 0..0, args: vec![] }.into()
+        };
+        let set = |name: &SmolStr, value: Rrc<Expr>| Stmt::SetVar {
+            name: name.clone(),
+            span: 0..0,
+            value,
+            is_local: false,
+        };
+        let elapsed = BinOp::Sub
+            .to_expr(timer(), Expr::Name { name: last.clone(), span: 0..0 }.into());
+        let rate = BinOp::Div.to_expr(Expr::Int(1).into(), elapsed.into());
+        self.stage.events.push(EventDetail::OnFlag { priority: 0 }.to_event(
+            0..0,
+            vec![
+                set(&last, timer()),
+                Stmt::Forever {
+                    body: vec![
+                        set(&fps, UnOp::Round.to_expr(rate.into()).into()),
+                        set(&last, timer()),
+                    ],
+                    span: 0..0,
+                },
+            ],
+        ));
+        self.monitors.push(Monitor { sprite: None, name: fps });
+        self.monitors.extend(monitors);
     }
 }
 
+/// Name of the global variable which holds the frame rate when the debug overlay is
+/// enabled.
+pub const DEBUG_FPS: &str = "fps";
+/// Name of the global variable which holds the timer at the last frame, used to measure
+/// the frame rate.
+const DEBUG_LAST_FRAME: &str = "__debug_last_frame";
+
+/// Prefix of the procedures which hold the body of an `on` script for several
+/// messages. A name cannot contain `#`, so no procedure written in goboscript can have
+/// it.
+const ON_MESSAGES_PROC: &str = "__on#";
+
+/// Whether a procedure was generated for an `on` script for several messages, so it is
+/// not offered for calls which are misspelt.
+pub fn is_on_messages_proc(name: &str) -> bool {
+    name.starts_with(ON_MESSAGES_PROC)
+}
+
+/// Whether statements use `stop_this_script`, outside of the procedures they call.
+fn stops_script(stmts: &Stmts) -> bool {
+    stmts.iter().any(|stmt| {
+        matches!(stmt, Stmt::Block { block: Block::StopThisScript, .. })
+            || stmt.bodies().into_iter().any(stops_script)
+    })
+}
+
+/// Name of the global variable which counts clones when `clone_counter` is enabled.
+pub const CLONE_COUNTER: &str = "clone_count";
+
 #[derive(Debug, Default)]
 pub struct Sprite {
-    pub costumes: FxHashMap<SmolStr, Costume>,
-    pub procs: FxHashMap<SmolStr, Proc>,
-    pub used_procs: FxHashSet<SmolStr>,
+    pub costumes: Vec<Costume>,
+    pub procs: FxHashMap<ProcKey, Proc>,
+    pub used_procs: FxHashSet<ProcKey>,
     pub enums: FxHashMap<SmolStr, Enum>,
     pub vars: FxHashMap<SmolStr, Var>,
     pub lists: FxHashMap<SmolStr, List>,
-    pub on_messages: FxHashMap<SmolStr, OnMessage>,
+    pub on_messages: Vec<OnMessage>,
+    pub payload_messages: FxHashSet<SmolStr>,
     pub events: Vec<Event>,
+    pub init: Stmts,
+    pub version: Option<(SmolStr, Span)>,
+    pub raw_json: Vec<RawJson>,
+    pub macros: FxHashMap<SmolStr, Macro>,
+    /// The macro whose body is being parsed, so that it cannot use itself.
+    pub defining_macro: Option<SmolStr>,
+    /// Number of statement macros used so far, which numbers the variables of the next.
+    pub macro_copies: usize,
+    /// Procedures which were declared again, reported by codegen.
+    pub duplicate_procs: Vec<(ProcKey, Span)>,
+}
+
+impl Sprite {
+    /// Declares a procedure. Procedures may share a name if they take different numbers
+    /// of arguments. A procedure which is declared again is left out, so that the rest
+    /// of the sprite is still checked.
+    pub fn add_proc(&mut self, proc: Proc) {
+        if self.procs.contains_key(&proc.key()) {
+            self.duplicate_procs.push((proc.key(), proc.span));
+            return;
+        }
+        self.procs.insert(proc.key(), proc);
+    }
+
+    /// Declares a script for each of `messages`. The body is moved into a generated
+    /// procedure which every script calls, so that it is only emitted once. A body
+    /// which stops its script is copied into each script instead, so that it stops the
+    /// script rather than only the procedure.
+    pub fn add_on_messages(&mut self, messages: Vec<SmolStr>, span: Span, body: Stmts) {
+        if messages.len() == 1 || stops_script(&body) {
+            for message in messages {
+                let body = deep_clone(&body);
+                self.on_messages.push(OnMessage::new(
+                    message,
+                    span.clone(),
+                    None,
+                    body,
+                ));
+            }
+            return;
+        }
+        let proc = (!body.is_empty()).then(|| {
+            let count =
+                self.procs.keys().filter(|(name, _)| is_on_messages_proc(name)).count();
+            let name = SmolStr::from(format!("{ON_MESSAGES_PROC}{count}"));
+            self.procs.insert(
+                (name.clone(), 0),
+                Proc::new(name.clone(), span.clone(), vec![], body, false, false),
+            );
+            name
+        });
+        for message in messages {
+            let body = match &proc {
+                Some(name) => {
+                    vec![Stmt::ProcCall {
+                        name: name.clone(),
+                        span: span.clone(),
+                        args: vec![],
+                    }]
+                }
+                None => vec![],
+            };
+            self.on_messages.push(OnMessage::new(message, span.clone(), None, body));
+        }
+    }
+
+    /// Declares a macro. `span` is where the declaration starts, variables which its body
+    /// creates by assigning to a parameter are removed, as the argument is assigned to
+    /// instead.
+    pub fn add_macro(
+        &mut self,
+        name: SmolStr,
+        span: Span,
+        params: Vec<SmolStr>,
+        body: MacroBody,
+    ) -> Result<(), DiagnosticDetail> {
+        self.defining_macro = None;
+        self.vars.retain(|var, it| !params.contains(var) || it.span.start < span.start);
+        // Reporters and blocks are looked up before macros, so the macro could never be
+        // used.
+        match body {
+            MacroBody::Expr(_) if Repr::from_shape(&name, params.len()).is_some() => {
+                return Err(DiagnosticDetail::MacroShadowsReporter(name));
+            }
+            MacroBody::Stmts(_) if Block::from_shape(&name, params.len()).is_some() => {
+                return Err(DiagnosticDetail::MacroShadowsBlock(name));
+            }
+            _ => {}
+        }
+        if self.macros.contains_key(&name) {
+            return Err(DiagnosticDetail::DuplicateMacro(name));
+        }
+        self.macros.insert(name.clone(), Macro { name, params, body });
+        Ok(())
+    }
+
+    /// Copies the statements of the macro `name` with `args`. The variables its body
+    /// declares with `local` are sprite variables of their own in each copy, so that
+    /// they do not overwrite the variables of the statements around it.
+    pub fn expand_macro(
+        &mut self,
+        name: &SmolStr,
+        span: &Span,
+        args: Exprs,
+    ) -> Result<Stmts, DiagnosticDetail> {
+        if self.defining_macro.as_ref() == Some(name) {
+            return Err(DiagnosticDetail::RecursiveMacro(name.clone()));
+        }
+        let m = &self.macros[name];
+        let MacroBody::Stmts(body) = &m.body else {
+            return Err(DiagnosticDetail::ExpressionMacroAsStatement(name.clone()));
+        };
+        let mut s = m.substitution(args)?;
+        let mut locals = vec![];
+        stmts_locals(body, &mut locals);
+        for local in locals {
+            let renamed =
+                SmolStr::from(format!("__macro_{name}_{}_{local}", self.macro_copies));
+            let mut var = Var::new(renamed.clone(), span.clone(), None);
+            var.used = true;
+            self.vars.insert(renamed.clone(), var);
+            s.exprs.remove(&local);
+            s.names.insert(local, renamed);
+        }
+        self.macro_copies += 1;
+        Ok(clone_stmts(body, &s))
+    }
+
+    /// Procedures with this name, by their number of arguments.
+    pub fn overloads(&self, name: &str) -> Vec<&Proc> {
+        let mut procs: Vec<_> =
+            self.procs.values().filter(|proc| proc.name == name).collect();
+        procs.sort_by_key(|proc| proc.args.len());
+        procs
+    }
+
+    /// A name for `proc` which no other procedure of this sprite has, used to name the
+    /// variables created for it. Overloads are told apart by their number of arguments.
+    pub fn proc_label(&self, proc: &Proc) -> SmolStr {
+        if self.overloads(&proc.name).len() > 1 {
+            format!("{}/{}", proc.name, proc.args.len()).into()
+        } else {
+            proc.name.clone()
+        }
+    }
+
+    /// The scripts of this sprite, each a hat block with its body: the events in the
+    /// order they are written, then the `on` scripts ordered by message, keeping the
+    /// order they are written among scripts for the same message.
+    pub fn scripts(&self) -> Vec<Script<'_>> {
+        let mut on_messages: Vec<_> = self.on_messages.iter().collect();
+        on_messages.sort_by_key(|on_message| &on_message.message);
+        self.events
+            .iter()
+            .map(Script::Event)
+            .chain(on_messages.into_iter().map(Script::OnMessage))
+            .collect()
+    }
+}
+
+/// A top-level script, see [`Sprite::scripts`].
+#[derive(Debug, Copy, Clone)]
+pub enum Script<'a> {
+    Event(&'a Event),
+    OnMessage(&'a OnMessage),
+}
+
+impl<'a> Script<'a> {
+    pub fn body(self) -> &'a Stmts {
+        match self {
+            Script::Event(event) => &event.body,
+            Script::OnMessage(on_message) => &on_message.body,
+        }
+    }
+}
+
+/// An expression or statements with parameters, which are copied into each use when
+/// the sprite is parsed, with the parameters replaced by the arguments.
+#[derive(Debug)]
+pub struct Macro {
+    pub name: SmolStr,
+    pub params: Vec<SmolStr>,
+    pub body: MacroBody,
+}
+
+#[derive(Debug)]
+pub enum MacroBody {
+    Expr(Rrc<Expr>),
+    Stmts(Stmts),
+}
+
+impl Macro {
+    pub fn expand(&self, args: Exprs) -> Result<Rrc<Expr>, DiagnosticDetail> {
+        let MacroBody::Expr(body) = &self.body else {
+            return Err(DiagnosticDetail::StatementMacroInExpression(
+                self.name.clone(),
+            ));
+        };
+        let s = self.substitution(args)?;
+        Ok(clone_expr(body, &s))
+    }
+
+    /// Replaces the parameters with copies of the arguments. A parameter which the body
+    /// assigns to must be given a variable, which is assigned to instead.
+    fn substitution(&self, args: Exprs) -> Result<Substitution, DiagnosticDetail> {
+        if args.len() != self.params.len() {
+            return Err(DiagnosticDetail::MacroArgsCountMismatch {
+                name: self.name.clone(),
+                expected: self.params.len(),
+                given: args.len(),
+            });
+        }
+        let mut assigned = vec![];
+        if let MacroBody::Stmts(body) = &self.body {
+            stmts_assigned(body, &mut assigned);
+        }
+        let mut s = Substitution::default();
+        for (param, arg) in self.params.iter().zip(args) {
+            match &*arg.borrow() {
+                Expr::Name { name, .. } => {
+                    s.names.insert(param.clone(), name.clone());
+                }
+                _ if assigned.contains(param) => {
+                    return Err(DiagnosticDetail::MacroArgumentNotVariable {
+                        name: self.name.clone(),
+                        param: param.clone(),
+                    });
+                }
+                _ => {}
+            }
+            s.exprs.insert(param.clone(), arg.clone());
+        }
+        Ok(s)
+    }
+}
+
+/// Variables which `stmts` assign to, including with `local`.
+fn stmts_assigned(stmts: &Stmts, assigned: &mut Vec<SmolStr>) {
+    for stmt in stmts {
+        if let Stmt::ForEach { name, .. }
+        | Stmt::SetVar { name, .. }
+        | Stmt::ChangeVar { name, .. }
+        | Stmt::Ask { name, .. } = stmt
+        {
+            assigned.push(name.clone());
+        }
+        for body in stmt.bodies() {
+            stmts_assigned(body, assigned);
+        }
+    }
+}
+
+/// Variables which `stmts` declare with `local`.
+fn stmts_locals(stmts: &Stmts, locals: &mut Vec<SmolStr>) {
+    for stmt in stmts {
+        if let Stmt::SetVar { name, is_local: true, .. } = stmt {
+            if !locals.contains(name) {
+                locals.push(name.clone());
+            }
+        }
+        for body in stmt.bodies() {
+            stmts_locals(body, locals);
+        }
+    }
+}
+
+/// A `raw_json` declaration, a file with a JSON fragment merged into the sprite.
+#[derive(Debug)]
+pub struct RawJson {
+    pub path: SmolStr,
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -41,6 +433,16 @@ pub struct Costume {
     pub name: SmolStr,
     pub path: SmolStr,
     pub span: Span,
+    pub frame: Option<Frame>,
+}
+
+/// A frame cut out of a sprite sheet. Frames are read from left to right, then from
+/// top to bottom.
+#[derive(Debug, Copy, Clone)]
+pub struct Frame {
+    pub index: usize,
+    pub width: usize,
+    pub height: usize,
 }
 
 impl Costume {
@@ -49,7 +451,35 @@ impl Costume {
         let name = alias.unwrap_or_else(|| {
             Path::new(path.as_str()).file_stem().unwrap().to_str().unwrap().into()
         });
-        Self { name, path, span }
+        Self { name, path, span, frame: None }
+    }
+
+    pub fn frames(
+        path: SmolStr,
+        span: Span,
+        count: usize,
+        width: usize,
+        height: usize,
+    ) -> Vec<Self> {
+        let stem = Path::new(path.as_str()).file_stem().unwrap().to_str().unwrap();
+        (0..count)
+            .map(|index| Self {
+                name: format!("{stem}_{}", index + 1).into(),
+                path: path.clone(),
+                span: span.clone(),
+                frame: Some(Frame { index, width, height }),
+            })
+            .collect()
+    }
+
+    /// Identifies the image data of this costume. Ends with the file extension.
+    pub fn key(&self) -> SmolStr {
+        match self.frame {
+            Some(Frame { index, width, height }) => {
+                format!("{}#{index}@{width}x{height}.png", self.path).into()
+            }
+            None => self.path.clone(),
+        }
     }
 }
 
@@ -57,14 +487,33 @@ impl Costume {
 pub struct Enum {
     pub name: SmolStr,
     pub span: Span,
-    pub variants: Vec<(SmolStr, Span)>,
+    /// Names and values of the variants, in the order they are declared.
+    pub variants: Vec<(SmolStr, Span, i64)>,
     pub used_variants: FxHashSet<SmolStr>,
 }
 
+/// A variant in an `enum` declaration, with its value if it is given one.
+pub type VariantDeclr = (SmolStr, Span, Option<i64>);
+
 impl Enum {
-    pub fn new(name: SmolStr, span: Span, variants: Vec<(SmolStr, Span)>) -> Self {
+    /// Variants without a value are numbered from the variant before them, starting
+    /// at 0.
+    pub fn new(name: SmolStr, span: Span, variants: Vec<VariantDeclr>) -> Self {
+        let mut next = 0;
+        let variants = variants
+            .into_iter()
+            .map(|(variant, span, value)| {
+                let value = value.unwrap_or(next);
+                next = value + 1;
+                (variant, span, value)
+            })
+            .collect();
         Self { name, span, variants, used_variants: Default::default() }
     }
+
+    pub fn value(&self, variant: &str) -> Option<i64> {
+        self.variants.iter().find(|(name, _, _)| name == variant).map(|it| it.2)
+    }
 }
 
 #[derive(Debug)]
@@ -73,36 +522,118 @@ pub struct Var {
     pub span: Span,
     pub default: Literal,
     pub used: bool,
+    pub cloud: bool,
+    pub ty: Option<VarType>,
+    /// Whether the variable is declared with `var` or `cloud`, rather than only by being
+    /// set.
+    pub declared: bool,
 }
 
 impl Var {
     pub fn new(name: SmolStr, span: Span, default: Option<Literal>) -> Self {
-        Self { name, span, default: default.unwrap_or(Literal::Int(0)), used: false }
+        Self {
+            name,
+            span,
+            default: default.unwrap_or(Literal::Int(0)),
+            used: false,
+            cloud: false,
+            ty: None,
+            declared: true,
+        }
+    }
+
+    /// The name shown in Scratch, cloud variables are marked with a cloud.
+    pub fn scratch_name(&self) -> SmolStr {
+        if self.cloud {
+            format!("{CLOUD_PREFIX}{}", self.name).into()
+        } else {
+            self.name.clone()
+        }
     }
 }
 
+/// The type a variable is declared to hold. Scratch variables can hold any value, so
+/// this is only used to warn about values of the wrong type.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VarType {
+    Number,
+    Text,
+}
+
+impl Display for VarType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            VarType::Number => write!(f, "number"),
+            VarType::Text => write!(f, "text"),
+        }
+    }
+}
+
+/// Scratch recognises cloud variables by this prefix on their names.
+pub const CLOUD_PREFIX: &str = "☁ ";
+/// Most cloud variables a project can have.
+pub const MAX_CLOUD_VARIABLES: usize = 10;
+/// Longest name a cloud variable can have, including its prefix.
+pub const MAX_CLOUD_NAME: usize = 256;
+/// Longest name a variable, list or sprite can have.
+pub const MAX_NAME: usize = 256;
+
+/// Why Scratch would not accept `name` for a variable, list or sprite, if it would not.
+/// The Scratch editor removes whitespace around names, so a name with whitespace around
+/// it could not be found by the name it is shown with.
+pub fn scratch_name_problem(name: &str) -> Option<String> {
+    if name.trim().is_empty() {
+        Some("it is empty".to_string())
+    } else if name.chars().count() > MAX_NAME {
+        Some(format!("it is longer than {MAX_NAME} characters"))
+    } else if name.trim() != name {
+        Some("it starts or ends with whitespace".to_string())
+    } else if name.chars().any(char::is_control) {
+        Some("it contains a control character".to_string())
+    } else {
+        None
+    }
+}
+
+/// Names which Scratch uses in menus, such as `_mouse_` in `go to`, so no sprite can
+/// have them.
+pub const RESERVED_SPRITE_NAMES: &[&str] =
+    &["_mouse_", "_stage_", "_edge_", "_myself_", "_random_"];
+/// Path of a blank costume which is built into goboscript, for the Stage and sprite of
+/// a single file, which have no project directory to read a costume from. No file name
+/// can contain a NUL character, so it is never the path of a file of the project.
+pub const BLANK_COSTUME: &str = "\0blank.svg";
+
 #[derive(Debug)]
 pub struct List {
     pub name: SmolStr,
     pub span: Span,
     pub default: Literals,
     pub used: bool,
+    /// File which the initial items are read from, declared with
+    /// `list name from "file";`.
+    pub source: Option<SmolStr>,
 }
 
 impl List {
     pub fn new(name: SmolStr, span: Span, default: Literals) -> Self {
-        Self { name, span, default, used: false }
+        Self { name, span, default, used: false, source: None }
     }
 }
 
 #[derive(Debug, Default)]
 pub struct References {
-    pub procs: FxHashSet<SmolStr>,
+    pub procs: FxHashSet<ProcKey>,
     pub vars: FxHashSet<SmolStr>,
     pub lists: FxHashSet<SmolStr>,
     pub enum_variants: FxHashSet<(SmolStr, SmolStr)>,
+    pub sprite_vars: FxHashSet<(SmolStr, SmolStr)>,
 }
 
+/// Procedures are identified by their name and number of arguments, so that a name can
+/// be overloaded with different numbers of arguments.
+pub type ProcKey = (SmolStr, usize);
+
 #[derive(Debug)]
 pub struct Proc {
     pub name: SmolStr,
@@ -112,6 +643,7 @@ pub struct Proc {
     pub locals: FxHashMap<SmolStr, Var>,
     pub body: Stmts,
     pub warp: bool,
+    pub inline: bool,
     pub references: References,
 }
 
@@ -122,6 +654,7 @@ impl Proc {
         args: Vec<(SmolStr, Span)>,
         body: Stmts,
         warp: bool,
+        inline: bool,
     ) -> Self {
         let used_args = args.iter().map(|(name, _)| (name.clone(), false)).collect();
         Self {
@@ -131,24 +664,80 @@ impl Proc {
             used_args,
             body,
             warp,
+            inline,
             references: Default::default(),
             locals: Default::default(),
         }
     }
+
+    pub fn key(&self) -> ProcKey {
+        (self.name.clone(), self.args.len())
+    }
 }
 
 #[derive(Debug)]
 pub struct OnMessage {
     pub message: SmolStr,
     pub span: Span,
+    pub payload: Option<(SmolStr, Span)>,
     pub body: Stmts,
     pub used: bool,
     pub references: References,
 }
 
 impl OnMessage {
-    pub fn new(message: SmolStr, span: Span, body: Stmts) -> Self {
-        Self { message, span, body, used: false, references: Default::default() }
+    pub fn new(
+        message: SmolStr,
+        span: Span,
+        payload: Option<(SmolStr, Span)>,
+        body: Stmts,
+    ) -> Self {
+        Self {
+            message,
+            span,
+            payload,
+            body,
+            used: false,
+            references: Default::default(),
+        }
+    }
+}
+
+/// Scratch broadcasts cannot carry data, so a payload is passed through a hidden global
+/// variable which is set right before broadcasting.
+pub fn payload_variable_name(message: &str) -> SmolStr {
+    SmolStr::from(format!("__msg_{message}"))
+}
+
+/// `measure` stores the time its body took in a hidden variable of the sprite.
+pub fn measure_variable_name(name: &str) -> SmolStr {
+    SmolStr::from(format!("__measure_{name}"))
+}
+
+/// `for item in list` counts through the list with a hidden variable, and sets `item`
+/// to the list item at that index at the start of each iteration. Loops inside `body`
+/// with the same item have their own index, so each loop takes a name which none of
+/// them use.
+pub fn for_each_index_name(name: &str, body: &Stmts) -> SmolStr {
+    let mut nested = FxHashSet::default();
+    loop_names(body, &mut nested);
+    (1..)
+        .map(|depth| match depth {
+            1 => SmolStr::from(format!("__index_{name}")),
+            _ => SmolStr::from(format!("__index_{name}_{depth}")),
+        })
+        .find(|index| !nested.contains(index))
+        .unwrap()
+}
+
+fn loop_names(stmts: &Stmts, names: &mut FxHashSet<SmolStr>) {
+    for stmt in stmts {
+        if let Stmt::ForEach { name, .. } = stmt {
+            names.insert(name.clone());
+        }
+        for body in stmt.bodies() {
+            loop_names(body, names);
+        }
     }
 }
 
@@ -162,12 +751,25 @@ pub struct Event {
 
 #[derive(Debug)]
 pub enum EventDetail {
-    OnFlag,
-    OnKey { key: SmolStr, span: Span },
+    /// Scripts with a higher `priority` are written first, see `Event::priority`.
+    OnFlag {
+        priority: i64,
+    },
+    OnKey {
+        key: SmolStr,
+        span: Span,
+    },
     OnClick,
-    OnBackdrop { backdrop: SmolStr, span: Span },
-    OnLoudnessGt { value: Rrc<Expr> },
-    OnTimerGt { value: Rrc<Expr> },
+    OnBackdrop {
+        backdrop: SmolStr,
+        span: Span,
+    },
+    OnLoudnessGt {
+        value: Rrc<Expr>,
+    },
+    OnTimerGt {
+        value: Rrc<Expr>,
+    },
     OnClone,
 }
 
@@ -183,6 +785,7 @@ pub type Stmts = Vec<Stmt>;
 pub enum Stmt {
     Repeat {
         times: Rrc<Expr>,
+        span: Span,
         body: Stmts,
     },
     Forever {
@@ -196,6 +799,41 @@ pub enum Stmt {
     },
     Until {
         cond: Rrc<Expr>,
+        span: Span,
+        body: Stmts,
+    },
+    /// Runs forever, unless left with `break`. Lowered to `forever` or `until` before
+    /// codegen.
+    Loop {
+        body: Stmts,
+        span: Span,
+    },
+    Break {
+        span: Span,
+    },
+    Continue {
+        span: Span,
+    },
+    /// Does nothing, marks a body which is empty on purpose.
+    Pass {
+        span: Span,
+    },
+    /// Times its body, replaced by its body and timer reads before any other pass.
+    Measure {
+        name: SmolStr,
+        span: Span,
+        body: Stmts,
+    },
+    /// Runs its body when the enclosing body ends, lowered to copies of its body at each
+    /// way out of the enclosing body after `measure`.
+    Defer {
+        span: Span,
+        body: Stmts,
+    },
+    ForEach {
+        name: SmolStr,
+        span: Span,
+        times: Rrc<Expr>,
         body: Stmts,
     },
     SetVar {
@@ -260,6 +898,20 @@ pub enum Stmt {
         span: Span,
         args: Exprs,
     },
+    Broadcast {
+        message: SmolStr,
+        span: Span,
+        payload: Rrc<Expr>,
+        and_wait: bool,
+    },
+    /// Asks a question and stores the answer in a variable. If `number` is set, the
+    /// question is asked again until the answer is a number.
+    Ask {
+        name: SmolStr,
+        span: Span,
+        prompt: Rrc<Expr>,
+        number: bool,
+    },
 }
 
 pub type Exprs = Vec<Rrc<Expr>>;
@@ -269,6 +921,7 @@ pub enum Expr {
     Int(i64),
     Float(f64),
     Str(SmolStr),
+    Bool(bool),
     Name {
         name: SmolStr,
         span: Span,
@@ -297,6 +950,13 @@ pub enum Expr {
         variant_name: SmolStr,
         variant_span: Span,
     },
+    /// A variable of another sprite, read with `sensing_of`.
+    SpriteVar {
+        sprite: SmolStr,
+        sprite_span: Span,
+        name: SmolStr,
+        span: Span,
+    },
 }
 
 impl Expr {
@@ -320,6 +980,48 @@ impl Expr {
             _ => None,
         }
     }
+
+    /// Number of nested reporter blocks this expression compiles to.
+    pub fn depth(&self) -> usize {
+        match self {
+            Expr::Repr { args, .. } => {
+                1 + args.iter().map(|arg| arg.borrow().depth()).max().unwrap_or(0)
+            }
+            Expr::UnOp { val, .. } => 1 + val.borrow().depth(),
+            Expr::BinOp { lhs, rhs, .. } => {
+                1 + lhs.borrow().depth().max(rhs.borrow().depth())
+            }
+            _ => 0,
+        }
+    }
+
+    /// Span covering every part of this expression which has a span.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Expr::Int(_) | Expr::Float(_) | Expr::Str(_) | Expr::Bool(_) => None,
+            Expr::Name { span, .. } | Expr::Arg { span, .. } => Some(span.clone()),
+            Expr::Repr { span, args, .. } => Some(
+                args.iter()
+                    .filter_map(|arg| arg.borrow().span())
+                    .fold(span.clone(), |acc, it| {
+                        acc.start.min(it.start)..acc.end.max(it.end)
+                    }),
+            ),
+            Expr::UnOp { val, .. } => val.borrow().span(),
+            Expr::BinOp { lhs, rhs, .. } => {
+                match (lhs.borrow().span(), rhs.borrow().span()) {
+                    (Some(lhs), Some(rhs)) => Some(lhs.start..rhs.end),
+                    (lhs, rhs) => lhs.or(rhs),
+                }
+            }
+            Expr::EnumVariant { enum_span, variant_span, .. } => {
+                Some(enum_span.start..variant_span.end)
+            }
+            Expr::SpriteVar { sprite_span, span, .. } => {
+                Some(sprite_span.start..span.end)
+            }
+        }
+    }
 }
 
 impl From<Expr> for Rc<RefCell<Expr>> {
@@ -363,7 +1065,7 @@ impl BinOp {
 impl Event {
     pub fn opcode(&self) -> &'static str {
         match &self.kind {
-            EventDetail::OnFlag => "event_whenflagclicked",
+            EventDetail::OnFlag { .. } => "event_whenflagclicked",
             EventDetail::OnKey { .. } => "event_whenkeypressed",
             EventDetail::OnClick => "event_whenthisspriteclicked",
             EventDetail::OnBackdrop { .. } => "event_whenbackdropswitchesto",
@@ -373,12 +1075,32 @@ impl Event {
             EventDetail::OnClone => "control_start_as_clone",
         }
     }
+
+    /// Scratch starts the green flag scripts of a sprite in the order they are in
+    /// `project.json`, so the scripts of a sprite are written by descending priority,
+    /// keeping the order of the source among scripts with the same priority. Only
+    /// `onflag(priority = n)` has a priority other than 0.
+    pub fn priority(&self) -> i64 {
+        match &self.kind {
+            EventDetail::OnFlag { priority } => *priority,
+            _ => 0,
+        }
+    }
 }
 
 impl Stmt {
     pub fn span(&self) -> &Span {
         match self {
+            Stmt::Repeat { span, .. } => span,
             Stmt::Forever { span, .. } => span,
+            Stmt::Until { span, .. } => span,
+            Stmt::Loop { span, .. } => span,
+            Stmt::Break { span } => span,
+            Stmt::Continue { span } => span,
+            Stmt::Pass { span } => span,
+            Stmt::Measure { span, .. } => span,
+            Stmt::Defer { span, .. } => span,
+            Stmt::ForEach { span, .. } => span,
             Stmt::SetVar { span, .. } => span,
             Stmt::ChangeVar { span, .. } => span,
             Stmt::Show { span, .. } => span,
@@ -391,7 +1113,59 @@ impl Stmt {
             Stmt::ListChange { span, .. } => span,
             Stmt::Block { span, .. } => span,
             Stmt::ProcCall { span, .. } => span,
+            Stmt::Broadcast { span, .. } => span,
+            Stmt::Ask { span, .. } => span,
             _ => unreachable!(),
         }
     }
+
+    /// The expressions of this statement, without those in its bodies.
+    pub fn exprs(&self) -> Vec<&Rrc<Expr>> {
+        match self {
+            Stmt::Repeat { times, .. } | Stmt::ForEach { times, .. } => vec![times],
+            Stmt::Branch { cond, .. } | Stmt::Until { cond, .. } => vec![cond],
+            Stmt::SetVar { value, .. }
+            | Stmt::ChangeVar { value, .. }
+            | Stmt::ListAdd { value, .. } => vec![value],
+            Stmt::ListDelete { index, .. } => vec![index],
+            Stmt::ListInsert { index, value, .. }
+            | Stmt::ListSet { index, value, .. }
+            | Stmt::ListChange { index, value, .. } => vec![index, value],
+            Stmt::Block { args, .. } | Stmt::ProcCall { args, .. } => {
+                args.iter().collect()
+            }
+            Stmt::Broadcast { payload, .. } => vec![payload],
+            Stmt::Ask { prompt, .. } => vec![prompt],
+            _ => vec![],
+        }
+    }
+
+    /// The bodies of this statement, if it is a loop or a branch.
+    pub fn bodies(&self) -> Vec<&Stmts> {
+        match self {
+            Stmt::Repeat { body, .. }
+            | Stmt::Forever { body, .. }
+            | Stmt::Until { body, .. }
+            | Stmt::Loop { body, .. }
+            | Stmt::Measure { body, .. }
+            | Stmt::Defer { body, .. }
+            | Stmt::ForEach { body, .. } => vec![body],
+            Stmt::Branch { if_body, else_body, .. } => vec![if_body, else_body],
+            _ => vec![],
+        }
+    }
+
+    pub fn bodies_mut(&mut self) -> Vec<&mut Stmts> {
+        match self {
+            Stmt::Repeat { body, .. }
+            | Stmt::Forever { body, .. }
+            | Stmt::Until { body, .. }
+            | Stmt::Loop { body, .. }
+            | Stmt::Measure { body, .. }
+            | Stmt::Defer { body, .. }
+            | Stmt::ForEach { body, .. } => vec![body],
+            Stmt::Branch { if_body, else_body, .. } => vec![if_body, else_body],
+            _ => vec![],
+        }
+    }
 }
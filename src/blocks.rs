@@ -3,6 +3,18 @@ pub struct Menu {
     pub opcode: &'static str,
     pub default: &'static str,
 }
+
+impl Menu {
+    /// The menu field for a string argument. `"random"` selects the random position in
+    /// the `goto` and `glide` menus, other strings are sprite names.
+    pub fn value(&self, arg: &str) -> String {
+        match (self.opcode, arg) {
+            ("motion_goto_menu" | "motion_glideto_menu", "random") => "_random_".into(),
+            _ => arg.to_string(),
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum UnOp {
     Not,
@@ -111,9 +123,11 @@ pub enum BinOp {
     Join,
     In,
     Of,
+    Contains,
     Le,
     Ge,
     Ne,
+    FloorDiv,
 }
 
 impl BinOp {
@@ -244,10 +258,13 @@ pub enum Block {
     BroadcastAndWait,
     Wait,
     WaitUntil,
+    Yield,
     StopAll,
     StopThisScript,
     StopOtherScripts,
     DeleteThisClone,
+    ClearCounter,
+    IncrCounter,
     Clone0,
     Clone1,
     Ask,
@@ -441,10 +458,13 @@ impl Block {
             ("broadcast_and_wait", _) => Some(Self::BroadcastAndWait),
             ("wait", _) => Some(Self::Wait),
             ("wait_until", _) => Some(Self::WaitUntil),
+            ("yield", _) => Some(Self::Yield),
             ("stop_all", _) => Some(Self::StopAll),
             ("stop_this_script", _) => Some(Self::StopThisScript),
             ("stop_other_scripts", _) => Some(Self::StopOtherScripts),
             ("delete_this_clone", _) => Some(Self::DeleteThisClone),
+            ("clear_counter", _) => Some(Self::ClearCounter),
+            ("incr_counter", _) => Some(Self::IncrCounter),
             ("clone", 0) => Some(Self::Clone0),
             ("clone", 1) => Some(Self::Clone1),
             ("clone", _) => Some(Self::Clone0),
@@ -536,10 +556,13 @@ impl Block {
             Self::BroadcastAndWait => "broadcast_and_wait",
             Self::Wait => "wait",
             Self::WaitUntil => "wait_until",
+            Self::Yield => "yield",
             Self::StopAll => "stop_all",
             Self::StopThisScript => "stop_this_script",
             Self::StopOtherScripts => "stop_other_scripts",
             Self::DeleteThisClone => "delete_this_clone",
+            Self::ClearCounter => "clear_counter",
+            Self::IncrCounter => "incr_counter",
             Self::Clone0 => "clone",
             Self::Clone1 => "clone",
             Self::Ask => "ask",
@@ -625,10 +648,13 @@ impl Block {
             "broadcast_and_wait",
             "wait",
             "wait_until",
+            "yield",
             "stop_all",
             "stop_this_script",
             "stop_other_scripts",
             "delete_this_clone",
+            "clear_counter",
+            "incr_counter",
             "clone",
             "ask",
             "set_drag_mode_draggable",
@@ -717,10 +743,13 @@ impl Block {
             Self::BroadcastAndWait => "event_broadcastandwait",
             Self::Wait => "control_wait",
             Self::WaitUntil => "control_wait_until",
+            Self::Yield => "control_wait",
             Self::StopAll => "control_stop",
             Self::StopThisScript => "control_stop",
             Self::StopOtherScripts => "control_stop",
             Self::DeleteThisClone => "control_delete_this_clone",
+            Self::ClearCounter => "control_clear_counter",
+            Self::IncrCounter => "control_incr_counter",
             Self::Clone0 => "control_create_clone_of",
             Self::Clone1 => "control_create_clone_of",
             Self::Ask => "sensing_askandwait",
@@ -810,10 +839,13 @@ impl Block {
             Self::BroadcastAndWait => &["BROADCAST_INPUT"],
             Self::Wait => &["DURATION"],
             Self::WaitUntil => &["CONDITION"],
+            Self::Yield => &[],
             Self::StopAll => &[],
             Self::StopThisScript => &[],
             Self::StopOtherScripts => &[],
             Self::DeleteThisClone => &[],
+            Self::ClearCounter => &[],
+            Self::IncrCounter => &[],
             Self::Clone0 => &[],
             Self::Clone1 => &["CLONE_OPTION"],
             Self::Ask => &["QUESTION"],
@@ -911,12 +943,15 @@ impl Block {
             Self::BroadcastAndWait => None,
             Self::Wait => None,
             Self::WaitUntil => None,
+            Self::Yield => None,
             Self::StopAll => Some("{\"STOP_OPTION\": [\"all\", null]}"),
             Self::StopThisScript => Some("{\"STOP_OPTION\": [\"this script\", null]}"),
             Self::StopOtherScripts => {
                 Some("{\"STOP_OPTION\": [\"other scripts in sprite\", null]}")
             }
             Self::DeleteThisClone => None,
+            Self::ClearCounter => None,
+            Self::IncrCounter => None,
             Self::Clone0 => None,
             Self::Clone1 => None,
             Self::Ask => None,
@@ -951,6 +986,7 @@ pub enum Repr {
     BackdropNumber,
     BackdropName,
     Volume,
+    Counter,
     TouchingMousePointer,
     TouchingEdge,
     Touching,
@@ -972,6 +1008,7 @@ pub enum Repr {
     TouchingColor,
     ColorIsTouchingColor,
     Answer,
+    Tempo,
     Random,
 }
 
@@ -1019,6 +1056,7 @@ impl Repr {
             ("backdrop_number", _) => Some(Self::BackdropNumber),
             ("backdrop_name", _) => Some(Self::BackdropName),
             ("volume", _) => Some(Self::Volume),
+            ("counter", _) => Some(Self::Counter),
             ("touching_mouse_pointer", _) => Some(Self::TouchingMousePointer),
             ("touching_edge", _) => Some(Self::TouchingEdge),
             ("touching", _) => Some(Self::Touching),
@@ -1040,6 +1078,7 @@ impl Repr {
             ("touching_color", _) => Some(Self::TouchingColor),
             ("color_is_touching_color", _) => Some(Self::ColorIsTouchingColor),
             ("answer", _) => Some(Self::Answer),
+            ("tempo", _) => Some(Self::Tempo),
             ("random", _) => Some(Self::Random),
             _ => None,
         }
@@ -1056,6 +1095,7 @@ impl Repr {
             Self::BackdropNumber => "backdrop_number",
             Self::BackdropName => "backdrop_name",
             Self::Volume => "volume",
+            Self::Counter => "counter",
             Self::TouchingMousePointer => "touching_mouse_pointer",
             Self::TouchingEdge => "touching_edge",
             Self::Touching => "touching",
@@ -1077,6 +1117,7 @@ impl Repr {
             Self::TouchingColor => "touching_color",
             Self::ColorIsTouchingColor => "color_is_touching_color",
             Self::Answer => "answer",
+            Self::Tempo => "tempo",
             Self::Random => "random",
         }
     }
@@ -1092,6 +1133,7 @@ impl Repr {
             "backdrop_number",
             "backdrop_name",
             "volume",
+            "counter",
             "touching_mouse_pointer",
             "touching_edge",
             "touching",
@@ -1113,6 +1155,7 @@ impl Repr {
             "touching_color",
             "color_is_touching_color",
             "answer",
+            "tempo",
             "random",
         ]
     }
@@ -1128,6 +1171,7 @@ impl Repr {
             Self::BackdropNumber => "looks_backdropnumbername",
             Self::BackdropName => "looks_backdropnumbername",
             Self::Volume => "sound_volume",
+            Self::Counter => "control_get_counter",
             Self::TouchingMousePointer => "sensing_touchingobject",
             Self::TouchingEdge => "sensing_touchingobject",
             Self::Touching => "sensing_touchingobject",
@@ -1149,6 +1193,7 @@ impl Repr {
             Self::TouchingColor => "sensing_touchingcolor",
             Self::ColorIsTouchingColor => "sensing_coloristouchingcolor",
             Self::Answer => "sensing_answer",
+            Self::Tempo => "music_getTempo",
             Self::Random => "operator_random",
         }
     }
@@ -1164,6 +1209,7 @@ impl Repr {
             Self::BackdropNumber => &[],
             Self::BackdropName => &[],
             Self::Volume => &[],
+            Self::Counter => &[],
             Self::TouchingMousePointer => &[],
             Self::TouchingEdge => &[],
             Self::Touching => &["TOUCHINGOBJECTMENU"],
@@ -1185,6 +1231,7 @@ impl Repr {
             Self::TouchingColor => &["COLOR"],
             Self::ColorIsTouchingColor => &["COLOR", "COLOR2"],
             Self::Answer => &[],
+            Self::Tempo => &[],
             Self::Random => &["FROM", "TO"],
         }
     }
@@ -1200,6 +1247,7 @@ impl Repr {
             Self::BackdropNumber => Some("{\"NUMBER_NAME\": [\"number\", null]}"),
             Self::BackdropName => Some("{\"NUMBER_NAME\": [\"name\", null]}"),
             Self::Volume => None,
+            Self::Counter => None,
             Self::TouchingMousePointer => None,
             Self::TouchingEdge => None,
             Self::Touching => None,
@@ -1221,6 +1269,7 @@ impl Repr {
             Self::TouchingColor => None,
             Self::ColorIsTouchingColor => None,
             Self::Answer => None,
+            Self::Tempo => None,
             Self::Random => None,
         }
     }
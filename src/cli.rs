@@ -1,6 +1,8 @@
-use std::path::PathBuf;
+use std::{env, path::PathBuf};
 
-use clap_derive::{Parser, Subcommand};
+use clap_derive::{Parser, Subcommand, ValueEnum};
+
+use crate::config::Target;
 
 #[derive(Debug, Parser)]
 #[command(
@@ -14,16 +16,132 @@ pub struct Cli {
     pub command: Commands,
 }
 
+/// What the `.sb3` is packaged into after it is written, by the command set with
+/// `packager` in `goboscript.toml`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, ValueEnum)]
+pub enum Package {
+    Html,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, ValueEnum)]
+pub enum Emit {
+    #[default]
+    Sb3,
+    Symbols,
+    ProjectJson,
+    /// Nothing is written, used by `goboscript lint`.
+    #[value(skip)]
+    Lint,
+    /// A Graphviz graph of the project, used by `goboscript graph`.
+    #[value(skip)]
+    Graph,
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     /// Compile a goboscript project to `.sb3`
     #[command()]
     Build {
         #[arg(short, long)]
-        /// Project directory, if not given, the current directory is used.
+        /// Project directory, if not given, `GOBOSCRIPT_INPUT` is used, otherwise the
+        /// current directory is used.
+        input: Option<PathBuf>,
+        #[arg(long, conflicts_with = "input")]
+        /// Build a single `.gs` file as a project with one sprite and an empty Stage,
+        /// without a project directory or `goboscript.toml`. The output is named after
+        /// the file.
+        single: Option<PathBuf>,
+        #[arg(short, long)]
+        /// Output file, if not given, `GOBOSCRIPT_OUTPUT` is used, otherwise it will be
+        /// the project directory's name + `.sb3`. `-` writes to standard output.
+        output: Option<PathBuf>,
+        #[arg(short, long, value_enum, default_value_t)]
+        /// Runtime to compile for, `turbowarp` allows blocks which are hidden in Scratch.
+        target: Target,
+        #[arg(long)]
+        /// Print how long each phase of the build took.
+        timings: bool,
+        #[arg(long, value_enum, default_value_t)]
+        /// What to output, `symbols` prints the variables, lists, broadcasts and
+        /// procedures of each sprite instead of writing a `.sb3` file. `project-json`
+        /// writes only the project's `project.json`, without its assets, to the
+        /// project directory's name + `.project.json` by default.
+        emit: Emit,
+        #[arg(long, default_value_t = 2)]
+        /// Number of spaces to indent the output of `--emit project-json` by, 0 writes
+        /// it on one line.
+        indent: usize,
+        #[arg(long, default_value_t = 20)]
+        /// Maximum number of errors to print, the rest are only counted.
+        max_errors: usize,
+        #[arg(long)]
+        /// Also write a JSON manifest next to the output file, listing its sprites,
+        /// their block counts and its assets.
+        manifest: bool,
+        #[arg(long)]
+        /// Show the frame rate, and the variables listed in `debug_variables` in
+        /// `goboscript.toml`, on the stage.
+        debug_overlay: bool,
+        #[arg(long)]
+        /// Leave out the timing of `measure` blocks, keeping only their bodies.
+        release: bool,
+        #[arg(long)]
+        /// Warn about variables which are only ever set once, to a constant value.
+        warn_constants: bool,
+        #[arg(long)]
+        /// Warn about global variables which are set by scripts in more than one sprite.
+        warn_shared_globals: bool,
+        #[arg(long)]
+        /// Enable the checks of strict mode, as if `strict = true` was set in
+        /// `goboscript.toml`.
+        strict: bool,
+        #[arg(long)]
+        /// Leave out sprites with syntax errors, and still compile and write the rest of
+        /// the project. The build fails afterwards, naming the sprites which were left
+        /// out. Otherwise the first syntax error stops the build.
+        keep_going: bool,
+        #[arg(long)]
+        /// Leave out the shadows which inputs holding a reporter would cover, as if
+        /// `compact = true` was set in `goboscript.toml`.
+        compact: bool,
+        #[arg(long, value_enum)]
+        /// Also package the `.sb3` into a standalone file, with the command set with
+        /// `packager` in `goboscript.toml`.
+        package: Option<Package>,
+    },
+
+    /// Report the warnings and errors of a goboscript project without writing anything.
+    #[command()]
+    Lint {
+        #[arg(short, long)]
+        /// Project directory, if not given, `GOBOSCRIPT_INPUT` is used, otherwise the
+        /// current directory is used.
+        input: Option<PathBuf>,
+        #[arg(short, long, value_enum, default_value_t)]
+        /// Runtime to compile for, `turbowarp` allows blocks which are hidden in Scratch.
+        target: Target,
+        #[arg(long, default_value_t = 20)]
+        /// Maximum number of errors to print, the rest are only counted.
+        max_errors: usize,
+        #[arg(long)]
+        /// Fail if there are any warnings.
+        deny_warnings: bool,
+        #[arg(long)]
+        /// Enable the checks of strict mode, as if `strict = true` was set in
+        /// `goboscript.toml`.
+        strict: bool,
+    },
+
+    /// Write a Graphviz graph of which procedures each script calls, and which scripts
+    /// broadcast and receive each message.
+    #[command()]
+    Graph {
+        #[arg(short, long)]
+        /// Project directory, if not given, `GOBOSCRIPT_INPUT` is used, otherwise the
+        /// current directory is used.
         input: Option<PathBuf>,
         #[arg(short, long)]
-        /// Output file, if not given, it will be the project directory's name + `.sb3`
+        /// Output file, if not given or `-`, the graph is written to standard output.
         output: Option<PathBuf>,
     },
 
@@ -68,6 +186,38 @@ pub enum Commands {
         /// (alias: --height) Custom stage height, used by TurboWarp.
         #[arg(short = 'H', long, alias = "height")]
         stage_height: Option<u64>,
+
+        /// Enable the checks of strict mode for the project.
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Add a sprite to an existing goboscript project, with a blank costume.
+    #[command()]
+    NewSprite {
+        /// Name of the sprite, its code is written to `<name>.gs`.
+        name: String,
+        #[arg(short, long)]
+        /// Project directory, if not given, `GOBOSCRIPT_INPUT` is used, otherwise the
+        /// current directory is used.
+        input: Option<PathBuf>,
+    },
+    /// Check that a `.sb3` file is a valid Scratch project.
+    #[command()]
+    Check {
+        /// The `.sb3` file to check.
+        file: PathBuf,
+        #[arg(long)]
+        /// Also check that every block reference resolves, and that blocks agree on
+        /// which block is their parent.
+        deep: bool,
+    },
+    /// Show which scripts were added, removed or changed between two `.sb3` files.
+    #[command()]
+    Diff {
+        /// The `.sb3` file to compare from.
+        old: PathBuf,
+        /// The `.sb3` file to compare to.
+        new: PathBuf,
     },
     Completions {
         /// The shell to generate the completions for.
@@ -75,3 +225,20 @@ pub enum Commands {
         shell: clap_complete_command::Shell,
     },
 }
+
+impl Commands {
+    /// Whether the command writes its result to standard output, so that nothing else
+    /// may be printed there.
+    pub fn writes_to_stdout(&self) -> bool {
+        match self {
+            Commands::Build { output, .. } => output
+                .clone()
+                .or_else(|| env::var_os("GOBOSCRIPT_OUTPUT").map(PathBuf::from))
+                .is_some_and(|output| output.as_os_str() == "-"),
+            Commands::Graph { output, .. } => {
+                output.as_ref().is_none_or(|output| output.as_os_str() == "-")
+            }
+            _ => false,
+        }
+    }
+}
@@ -1,42 +1,96 @@
 use std::{
-    fs::File,
+    cmp::Reverse,
+    collections::BTreeSet,
+    fs::{self, File},
     io::{self, Seek, Write},
     path::Path,
 };
 
 use anyhow::{bail, Result};
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHashSet};
 use logos::Span;
 use md5::{Digest, Md5};
 use serde_json::json;
 use smol_str::SmolStr;
 use zip::{
     write::{FileOptions, ZipWriter},
-    CompressionMethod,
+    CompressionMethod, DateTime,
 };
 
 use self::{
     node::Node,
     node_id::{NodeID, NodeIDFactory},
+    png::Png,
+    raw_json::Fragment,
 };
 use crate::{
-    ast::{Costume, Event, EventDetail, Expr, Proc, Project, Sprite, Stmt, Stmts},
+    ast::{
+        payload_variable_name, scratch_name_problem, Costume, Event, EventDetail, Expr,
+        Exprs, Frame, List, Literal, Monitor, OnMessage, Proc, ProcKey, Project,
+        RawJson, Sprite, Stmt, Stmts, Var, VarType, BLANK_COSTUME, CLOUD_PREFIX,
+        MAX_CLOUD_NAME, MAX_CLOUD_VARIABLES,
+    },
     blocks::{BinOp, Block, UnOp},
-    config::Config,
+    config::{Config, Mangle},
     diagnostic::{keys::is_key, Diagnostic, DiagnosticDetail},
 };
 
 pub mod node;
 pub mod node_id;
+mod png;
+mod raw_json;
+
+/// Extensions which goboscript has blocks for, the prefix of their opcodes.
+const EXTENSIONS: &[&str] = &["pen", "music"];
+
+/// Where the project is written, a whole `.sb3` archive, or only its `project.json`.
+enum Sink<T>
+where T: Write + Seek
+{
+    Sb3(ZipWriter<T>),
+    ProjectJson(Vec<u8>),
+}
+
+impl<T> Sink<T>
+where T: Write + Seek
+{
+    fn zip(&mut self) -> &mut ZipWriter<T> {
+        match self {
+            Sink::Sb3(zip) => zip,
+            Sink::ProjectJson(_) => panic!("only a `.sb3` archive has other files"),
+        }
+    }
+}
 
 pub struct Sb3<T>
 where T: Write + Seek
 {
-    zip: ZipWriter<T>,
+    sink: Sink<T>,
     id: NodeIDFactory,
     costumes: FxHashMap<SmolStr, SmolStr>,
+    /// Sprite sheets by path, `None` if they could not be decoded.
+    sheets: FxHashMap<SmolStr, Option<Png>>,
+    /// Images of costumes which are not read from a file: frames cut out of sprite
+    /// sheets, and the blank costume of a single file.
+    frames: FxHashMap<SmolStr, Vec<u8>>,
+    local_names: FxHashMap<String, String>,
+    max_expression_depth: usize,
+    /// Whether the checks of `strict` mode are enabled.
+    strict: bool,
+    /// Whether inputs which hold a reporter are written without the shadow it covers.
+    compact: bool,
+    expression_depth: usize,
     blocks_comma: bool,
     inputs_comma: bool,
+    /// Number of blocks written for the current sprite.
+    block_count: usize,
+    /// Names and block counts of the sprites written so far, for the manifest.
+    sprite_blocks: Vec<(String, usize)>,
+    /// File names, paths in the project directory and sizes of the assets written so
+    /// far, for the manifest.
+    asset_sizes: Vec<(String, SmolStr, u64)>,
+    /// Extensions whose blocks were written, listed in `project.json`.
+    extensions: BTreeSet<&'static str>,
 }
 
 type D<'a> = &'a mut Vec<Diagnostic>;
@@ -44,8 +98,12 @@ type D<'a> = &'a mut Vec<Diagnostic>;
 #[derive(Copy, Clone)]
 struct S<'a> {
     stage: Option<&'a Sprite>,
+    sprites: &'a FxHashMap<SmolStr, Sprite>,
     sprite: &'a Sprite,
     proc: Option<&'a Proc>,
+    /// The innermost `forever` loop around the statement being written, if it cannot be
+    /// escaped.
+    forever: Option<&'a Span>,
 }
 
 impl<'a> S<'a> {
@@ -62,10 +120,31 @@ impl<'a> S<'a> {
             || self.stage.is_some_and(|it| it.vars.contains_key(name))
     }
 
+    fn get_var(self, name: &str) -> Option<&'a Var> {
+        self.sprite.vars.get(name).or_else(|| self.stage?.vars.get(name))
+    }
+
     fn is_list(self, name: &str) -> bool {
         self.sprite.lists.contains_key(name)
             || self.stage.is_some_and(|it| it.lists.contains_key(name))
     }
+
+    /// Number of sprites, including the stage, which receive `message`.
+    fn receivers(self, message: &str) -> usize {
+        std::iter::once(self.stage.unwrap_or(self.sprite))
+            .chain(self.sprites.values())
+            .filter(|sprite| {
+                sprite
+                    .on_messages
+                    .iter()
+                    .any(|on_message| on_message.message == message)
+            })
+            .count()
+    }
+
+    fn get_list(self, name: &str) -> Option<&'a List> {
+        self.sprite.lists.get(name).or_else(|| self.stage?.lists.get(name))
+    }
 }
 
 impl Stmt {
@@ -94,6 +173,7 @@ impl Stmt {
                 }
             }
             Stmt::Until { .. } => "control_repeat_until",
+            Stmt::ForEach { .. } => "control_for_each",
             Stmt::SetVar { .. } => "data_setvariableto",
             Stmt::ChangeVar { .. } => "data_changevariableby",
             Stmt::Show { name, .. } | Stmt::Hide { name, .. } => {
@@ -110,6 +190,14 @@ impl Stmt {
             Stmt::ListSet { .. } | Stmt::ListChange { .. } => "data_replaceitemoflist",
             Stmt::Block { block, .. } => block.opcode(),
             Stmt::ProcCall { .. } => "procedures_call",
+            Stmt::Broadcast { .. }
+            | Stmt::Ask { .. }
+            | Stmt::Loop { .. }
+            | Stmt::Break { .. }
+            | Stmt::Continue { .. }
+            | Stmt::Pass { .. }
+            | Stmt::Measure { .. }
+            | Stmt::Defer { .. } => unreachable!(),
         }
     }
 }
@@ -118,16 +206,37 @@ impl<T> Sb3<T>
 where T: Write + Seek
 {
     pub fn new(file: T) -> Self {
+        Self::with_sink(Sink::Sb3(ZipWriter::new(file)))
+    }
+
+    /// Writes only `project.json`, without bundling any assets. Costumes are still read
+    /// to find their hashes. Get the result with `into_project_json`.
+    pub fn new_project_json() -> Self {
+        Self::with_sink(Sink::ProjectJson(vec![]))
+    }
+
+    fn with_sink(sink: Sink<T>) -> Self {
         Self {
-            zip: ZipWriter::new(file),
+            sink,
             id: Default::default(),
             costumes: Default::default(),
+            sheets: Default::default(),
+            frames: Default::default(),
+            local_names: Default::default(),
+            max_expression_depth: 0,
+            strict: false,
+            compact: false,
+            expression_depth: 0,
             blocks_comma: false,
             inputs_comma: false,
+            block_count: 0,
+            sprite_blocks: vec![],
+            asset_sizes: vec![],
+            extensions: Default::default(),
         }
     }
 
-    pub fn package(
+    pub fn project(
         &mut self,
         project: &Project,
         config: &Config,
@@ -135,18 +244,30 @@ where T: Write + Seek
         stage_diags: D,
         diags: &mut FxHashMap<SmolStr, Vec<Diagnostic>>,
     ) -> Result<()> {
-        self.zip.start_file(
-            "project.json",
-            FileOptions::default()
-                .compression_method(CompressionMethod::Deflated)
-                .compression_level(Some(6)),
-        )?;
+        if let Sink::Sb3(zip) = &mut self.sink {
+            zip.start_file(
+                "project.json",
+                file_options()
+                    .compression_method(CompressionMethod::Deflated)
+                    .compression_level(Some(6)),
+            )?;
+        }
+        self.max_expression_depth = config.max_expression_depth.unwrap_or(15);
+        self.strict = config.strict == Some(true);
+        self.compact = config.compact == Some(true);
         self.write_all(br#"{"targets":["#)?;
-        self.sprite(None, &project.stage, stage_diags, "Stage", config, input)?;
-        for (name, sprite) in project.sprites.iter() {
+        self.sprite(project, &project.stage, stage_diags, "Stage", config, input)?;
+        // The editor selects the first sprite when the project is opened.
+        let selected = config.selected_sprite.as_deref();
+        let mut sprites: Vec<_> = project.sprites.iter().collect();
+        sprites.sort_by_key(|(name, _)| {
+            let position = startup_position(config, name).unwrap_or(usize::MAX);
+            (Some(name.as_str()) != selected, position, *name)
+        });
+        for (name, sprite) in sprites {
             self.write_all(b",")?;
             self.sprite(
-                Some(&project.stage),
+                project,
                 sprite,
                 diags.get_mut(name).unwrap(),
                 name.as_str(),
@@ -154,26 +275,160 @@ where T: Write + Seek
                 input,
             )?;
         }
-        self.write_all(br#"],"monitors":[],"extensions":[],"meta":{"semver":"3.0.0","vm":"0.2.0","agent":"goboscript"}}"#)?;
-        self.assets(input)?;
-        self.zip.finish()?;
+        self.write_all(br#"],"monitors":["#)?;
+        let mut comma = false;
+        for (i, monitor) in project.monitors.iter().enumerate() {
+            self.comma(&mut comma)?;
+            self.monitor(project, monitor, i)?;
+        }
+        write!(
+            self,
+            r#"],"extensions":{},"meta":{{"semver":"3.0.0","vm":"0.2.0","agent":"goboscript"}}}}"#,
+            json!(self.extensions)
+        )?;
+        Ok(())
+    }
+
+    /// Writes a monitor for a variable, the `index`th one is placed below the ones
+    /// before it in the top left corner of the stage.
+    fn monitor(
+        &mut self,
+        project: &Project,
+        monitor: &Monitor,
+        index: usize,
+    ) -> Result<()> {
+        let owner = match &monitor.sprite {
+            Some(sprite) => &project.sprites[sprite],
+            None => &project.stage,
+        };
+        let name = owner
+            .vars
+            .get(&monitor.name)
+            .map_or(monitor.name.clone(), |var| var.scratch_name());
+        write!(
+            self,
+            "{}",
+            json!({
+                "id": monitor.name.as_str(),
+                "mode": "default",
+                "opcode": "data_variable",
+                "params": {"VARIABLE": name.as_str()},
+                "spriteName": monitor.sprite.as_deref(),
+                "value": 0,
+                "width": 0,
+                "height": 0,
+                "x": 5,
+                "y": 5 + 27 * index,
+                "visible": true,
+                "sliderMin": 0,
+                "sliderMax": 100,
+                "isDiscrete": true,
+            })
+        )?;
         Ok(())
     }
 
+    pub fn finish(&mut self, input: &Path, thumbnail: Option<&Path>) -> Result<T> {
+        self.assets(input)?;
+        if let Some(thumbnail) = thumbnail {
+            self.thumbnail(thumbnail)?;
+        }
+        Ok(self.sink.zip().finish()?)
+    }
+
+    pub fn into_project_json(self) -> Vec<u8> {
+        match self.sink {
+            Sink::ProjectJson(json) => json,
+            Sink::Sb3(_) => panic!("the project was written to a `.sb3` archive"),
+        }
+    }
+
+    fn mangle_locals(&mut self, sprite: &Sprite, mangle: Mangle) {
+        self.local_names.clear();
+        let mut procs: Vec<_> = sprite.procs.values().collect();
+        procs.sort_by_key(|proc| &proc.name);
+        for proc in procs {
+            let mut locals: Vec<_> = proc.locals.keys().collect();
+            locals.sort();
+            for name in locals {
+                let resolved = local_variable_resolved_name(proc, name);
+                let mangled = match mangle {
+                    Mangle::Short => format!("_{}", self.local_names.len()),
+                    Mangle::Readable => format!("{}_{}", sprite.proc_label(proc), name),
+                    Mangle::Hashed => format!("{:x}", Md5::digest(resolved.as_bytes()))
+                        [..8]
+                        .to_string(),
+                };
+                self.local_names.insert(resolved, mangled);
+            }
+        }
+    }
+
     fn assets(&mut self, input: &Path) -> Result<()> {
-        for (path, hash) in &self.costumes {
-            let (_, extension) = path.rsplit_once('.').unwrap();
-            self.zip
-                .start_file(format!("{hash}.{extension}"), FileOptions::default())?;
-            let file = File::open(input.join(path.as_str()));
-            io::copy(&mut file?, &mut self.zip)?;
+        let mut assets: Vec<_> = self
+            .costumes
+            .iter()
+            .map(|(path, hash)| {
+                let (_, extension) = path.rsplit_once('.').unwrap();
+                (format!("{hash}.{extension}"), path)
+            })
+            .collect();
+        // Sorted so that the same project always produces the same archive. Costumes
+        // with the same contents share a single file.
+        assets.sort();
+        assets.dedup_by(|(a, _), (b, _)| a == b);
+        for (name, path) in assets {
+            self.sink.zip().start_file(&name, file_options())?;
+            let size = if let Some(data) = self.frames.get(path) {
+                self.sink.zip().write_all(data)?;
+                data.len() as u64
+            } else {
+                let file = File::open(input.join(path.as_str()));
+                io::copy(&mut file?, self.sink.zip())?
+            };
+            self.asset_sizes.push((name, path.clone(), size));
         }
         Ok(())
     }
 
+    /// Describes the written project, so that tools can check a build without reading
+    /// the archive.
+    pub fn manifest(&self, output: &Path) -> serde_json::Value {
+        json!({
+            "output": output,
+            "goboscript": env!("CARGO_PKG_VERSION"),
+            "sprites": self.sprite_blocks.iter().map(|(name, blocks)| {
+                json!({"name": name, "blocks": blocks})
+            }).collect::<Vec<_>>(),
+            "assets": self.asset_sizes.iter().map(|(name, _, size)| {
+                json!({"name": name, "size": size})
+            }).collect::<Vec<_>>(),
+        })
+    }
+
+    /// The `count` largest assets written, by their path in the project directory,
+    /// largest first.
+    pub fn largest_assets(&self, count: usize) -> Vec<(&str, u64)> {
+        let mut assets: Vec<_> = self
+            .asset_sizes
+            .iter()
+            .map(|(_, path, size)| (path.as_str(), *size))
+            .collect();
+        assets.sort_by(|(a_path, a), (b_path, b)| b.cmp(a).then(a_path.cmp(b_path)));
+        assets.truncate(count);
+        assets
+    }
+
+    fn thumbnail(&mut self, path: &Path) -> Result<()> {
+        let extension = path.extension().unwrap().to_str().unwrap();
+        self.sink.zip().start_file(format!("thumbnail.{extension}"), file_options())?;
+        io::copy(&mut File::open(path)?, self.sink.zip())?;
+        Ok(())
+    }
+
     fn sprite(
         &mut self,
-        stage: Option<&Sprite>,
+        project: &Project,
         sprite: &Sprite,
         diags: D,
         name: &str,
@@ -181,21 +436,124 @@ where T: Write + Seek
         input: &Path,
     ) -> Result<()> {
         self.id.reset();
+        self.block_count = 0;
+        let stage = (name != "Stage").then_some(&project.stage);
+        let sprites = &project.sprites;
+        let mut fragment = Fragment::default();
+        for (i, raw) in sprite.raw_json.iter().enumerate() {
+            if let Some(raw) = self.raw_json(diags, raw, i, input)? {
+                fragment.blocks.extend(raw.blocks);
+                fragment.comments.extend(raw.comments);
+                fragment.broadcasts.extend(raw.broadcasts);
+            }
+        }
+        let mut comments = serde_json::Map::new();
         if name == "Stage" {
             self.write_all(br#"{"isStage":true"#)?;
+            if let Some(backdrop) = &config.initial_backdrop {
+                let index = sprite
+                    .costumes
+                    .iter()
+                    .position(|costume| costume.name == backdrop)
+                    .unwrap_or(0);
+                write!(self, r#","currentCostume":{index}"#)?;
+            }
+            if !config.startup_order.is_empty() {
+                self.write_all(br#","layerOrder":0"#)?;
+            }
+            if let Some(tempo) = config.tempo {
+                write!(self, r#","tempo":{}"#, json!(tempo))?;
+            }
             if !config.is_default() {
-                write!(
-                    self,
-                    r#","comments":{{"a":{{"blockId":null,"x":0,"y":0,"width":350,"height":170,"minimized":false,"text":{}}}}}"#,
-                    json!(config.to_string())
-                )?;
+                comments.insert(
+                    "a".to_string(),
+                    json!({
+                        "blockId": null,
+                        "x": 0,
+                        "y": 0,
+                        "width": 350,
+                        "height": 170,
+                        "minimized": false,
+                        "text": config.to_string(),
+                    }),
+                );
+            }
+            // `project.json` has no place for these, Scratch keeps them on its website.
+            if let Some(text) = notes_comment(config) {
+                comments.insert(
+                    "b".to_string(),
+                    json!({
+                        "blockId": null,
+                        "x": 0,
+                        "y": if comments.is_empty() { 0 } else { 200 },
+                        "width": 350,
+                        "height": 200,
+                        "minimized": false,
+                        "text": text,
+                    }),
+                );
             }
         } else {
             self.write_all(br#"{"isStage":false"#)?;
+            if config.draggable_sprites.iter().any(|draggable| draggable == name) {
+                self.write_all(br#","draggable":true"#)?;
+            }
+            if config.hidden_sprites.iter().any(|hidden| hidden == name) {
+                self.write_all(br#","visible":false"#)?;
+            }
+            if let Some(costume) = config.initial_costumes.get(name) {
+                let index = sprite
+                    .costumes
+                    .iter()
+                    .position(|it| it.name == costume)
+                    .unwrap_or(0);
+                write!(self, r#","currentCostume":{index}"#)?;
+            }
+            if let Some(layer) = layer_order(project, config, name) {
+                write!(self, r#","layerOrder":{layer}"#)?;
+            }
+            if let Some(volume) = config.volume.get(name) {
+                write!(self, r#","volume":{}"#, json!(volume))?;
+            }
+        }
+        comments.extend(fragment.comments);
+        if !comments.is_empty() {
+            write!(self, r#","comments":{}"#, json!(comments))?;
         }
         write!(self, r#","name":{},"blocks":{{"#, json!(name))?;
         self.blocks_comma = false;
+        self.mangle_locals(sprite, config.mangle);
+        for ((name, args), span) in &sprite.duplicate_procs {
+            diags.push(
+                DiagnosticDetail::DuplicateProcedure {
+                    name: name.clone(),
+                    args: *args,
+                }
+                .to_diagnostic(span.clone()),
+            );
+        }
         for proc in sprite.procs.values() {
+            if proc.body.is_empty() {
+                diags.push(
+                    DiagnosticDetail::EmptyProcedure(proc.name.clone())
+                        .to_diagnostic(proc.span.clone()),
+                );
+            }
+            for (i, (name, span)) in proc.args.iter().enumerate() {
+                if let Some((_, first)) =
+                    proc.args[..i].iter().find(|(arg, _)| arg == name)
+                {
+                    diags.push(
+                        DiagnosticDetail::DuplicateArgument(name.clone())
+                            .to_diagnostic(first.start..span.end),
+                    );
+                } else if stage.unwrap_or(sprite).vars.contains_key(name) {
+                    diags.push(
+                        DiagnosticDetail::ArgumentShadowsVariable(name.clone())
+                            .to_diagnostic(span.clone()),
+                    );
+                }
+            }
             for (name, is_used) in &proc.used_args {
                 let span =
                     proc.args.iter().find(|(arg, _)| arg == name).unwrap().1.clone();
@@ -206,31 +564,78 @@ where T: Write + Seek
                     );
                 }
             }
-            if !sprite.used_procs.contains(&proc.name) {
+            if !sprite.used_procs.contains(&proc.key()) {
                 diags.push(
                     DiagnosticDetail::UnusedProcedure(proc.name.clone())
                         .to_diagnostic(proc.span.clone()),
                 );
             }
-            self.proc(S { stage, sprite, proc: Some(proc) }, diags, proc)?;
+            if !proc.inline {
+                self.proc(
+                    S { stage, sprites, sprite, proc: Some(proc), forever: None },
+                    diags,
+                    proc,
+                )?;
+            }
         }
-        for event in &sprite.events {
-            self.event(S { stage, sprite, proc: None }, diags, event)?;
+        let mut events: Vec<_> = sprite.events.iter().collect();
+        events.sort_by_key(|event| Reverse(event.priority()));
+        for event in events {
+            self.event(
+                S { stage, sprites, sprite, proc: None, forever: None },
+                diags,
+                event,
+            )?;
+        }
+        for on_message in sprite.on_messages.iter() {
+            self.on_message(
+                S { stage, sprites, sprite, proc: None, forever: None },
+                diags,
+                on_message,
+            )?;
+        }
+        for (id, block) in fragment.blocks {
+            if self.blocks_comma {
+                self.write_all(b",")?;
+            }
+            self.blocks_comma = true;
+            self.block_count += 1;
+            if let Some(opcode) = block.get("opcode").and_then(|it| it.as_str()) {
+                self.use_extension(opcode);
+            }
+            write!(self, "{}:{block}", json!(id))?;
         }
-        // TODO: sprite.on_messages
         self.write_all(br#"},"costumes":["#)?;
+        self.sprite_blocks.push((name.to_string(), self.block_count));
         let mut comma = false;
-        for costume in sprite.costumes.values() {
+        for (i, costume) in sprite.costumes.iter().enumerate() {
+            let duplicate = sprite
+                .costumes
+                .iter()
+                .enumerate()
+                .any(|(j, it)| i != j && it.name == costume.name);
+            if costume.frame.is_some() && duplicate {
+                diags.push(
+                    DiagnosticDetail::DuplicateCostume(costume.name.clone())
+                        .to_diagnostic(costume.span.clone()),
+                );
+            }
             self.comma(&mut comma)?;
             self.costume(diags, costume, input)?;
         }
         self.write_all(br#"],"variables":{"#)?;
         let mut comma = false;
-        for proc in sprite.procs.values() {
+        for proc in sprite.procs.values().filter(|proc| !proc.inline) {
             for var in proc.locals.values() {
-                let resolved = json!(local_variable_resolved_name(proc, &var.name));
+                let resolved = local_variable_resolved_name(proc, &var.name);
                 self.comma(&mut comma)?;
-                write!(self, r#"{}:[{},{}]"#, resolved, resolved, json!(var.default))?;
+                write!(
+                    self,
+                    r#"{}:[{},{}]"#,
+                    json!(resolved),
+                    json!(self.local_names[&resolved]),
+                    json!(var.default)
+                )?;
             }
         }
         for var in sprite.vars.values() {
@@ -240,15 +645,36 @@ where T: Write + Seek
                         .to_diagnostic(var.span.clone()),
                 );
             }
+            if self.strict && !var.declared {
+                diags.push(
+                    DiagnosticDetail::UndeclaredVariable(var.name.clone())
+                        .to_diagnostic(var.span.clone()),
+                );
+            }
+            // A default is checked like a value the variable is set to.
+            if matches!(var.ty, Some(VarType::Number))
+                && matches!(&var.default, Literal::Str(value) if value.parse::<f64>().is_err())
+            {
+                diags.push(
+                    DiagnosticDetail::VariableTypeMismatch {
+                        name: var.name.clone(),
+                        ty: VarType::Number,
+                    }
+                    .to_diagnostic(var.span.clone()),
+                );
+            }
             self.comma(&mut comma)?;
             write!(
                 self,
-                r#"{}:[{},{}]"#,
-                json!(*var.name),
+                r#"{}:[{},{}{}]"#,
                 json!(*var.name),
-                json!(var.default)
+                json!(*var.scratch_name()),
+                json!(var.default),
+                if var.cloud { ",true" } else { "" }
             )?;
         }
+        check_cloud_variables(sprite, name == "Stage", diags);
+        check_scratch_names(sprite, stage, &self.local_names, diags);
         self.write_all(br#"},"lists":{"#)?;
         let mut comma = false;
         for list in sprite.lists.values() {
@@ -267,10 +693,14 @@ where T: Write + Seek
                 json!(list.default)
             )?;
         }
+        self.write_all(b"}")?;
+        if !fragment.broadcasts.is_empty() {
+            write!(self, r#","broadcasts":{}"#, json!(fragment.broadcasts))?;
+        }
         // FIXME: Can you please fucking implement sounds this time?
-        self.write_all(br#"},"sounds":[]}"#)?;
+        self.write_all(br#","sounds":[]}"#)?;
         for enum_ in sprite.enums.values() {
-            for (variant, span) in &enum_.variants {
+            for (variant, span, _) in &enum_.variants {
                 if !enum_.used_variants.contains(variant) {
                     diags.push(
                         DiagnosticDetail::UnusedEnumVariant {
@@ -288,16 +718,64 @@ where T: Write + Seek
         Ok(())
     }
 
+    /// Reads a `raw_json` fragment, its ids are prefixed with its index in the sprite.
+    fn raw_json(
+        &mut self,
+        d: D,
+        raw: &RawJson,
+        index: usize,
+        input: &Path,
+    ) -> Result<Option<Fragment>> {
+        let src = match fs::read_to_string(input.join(raw.path.as_str())) {
+            Ok(src) => src,
+            Err(err) if matches!(err.kind(), io::ErrorKind::NotFound) => {
+                d.push(
+                    DiagnosticDetail::FileNotFound(raw.path.clone())
+                        .to_diagnostic(raw.span.clone()),
+                );
+                return Ok(None);
+            }
+            Err(err) => bail!(err),
+        };
+        match Fragment::parse(&src, &format!("raw{index}_")) {
+            Ok(fragment) => Ok(Some(fragment)),
+            Err(reason) => {
+                d.push(
+                    DiagnosticDetail::InvalidRawJson(reason.into())
+                        .to_diagnostic(raw.span.clone()),
+                );
+                Ok(None)
+            }
+        }
+    }
+
     fn costume(&mut self, d: D, costume: &Costume, input: &Path) -> Result<()> {
-        if let Some(hash) = self.costumes.get(&costume.path) {
-            let (_, extension) = costume.path.rsplit_once('.').unwrap();
+        let key = costume.key();
+        if let Some(hash) = self.costumes.get(&key) {
+            let (_, extension) = key.rsplit_once('.').unwrap();
             write!(
-                self.zip,
+                self.sink,
                 r#"{{"name":{},"assetId":"{hash}","dataFormat":"{extension}","md5ext":"{hash}.{extension}"}}"#,
                 json!(*costume.name),
             )?;
             return Ok(());
         }
+        if let Some(frame) = costume.frame {
+            let Some(data) = self.frame(d, costume, frame, input)? else {
+                return Ok(());
+            };
+            let hash = format!("{:x}", Md5::digest(&data));
+            self.frames.insert(key.clone(), data);
+            self.costumes.insert(key, hash.into());
+            return self.costume(d, costume, input);
+        }
+        if costume.path == BLANK_COSTUME {
+            let data = include_bytes!("frontend/templates/blank.svg").to_vec();
+            let hash = format!("{:x}", Md5::digest(&data));
+            self.frames.insert(key.clone(), data);
+            self.costumes.insert(key, hash.into());
+            return self.costume(d, costume, input);
+        }
         let path = input.join(costume.path.as_str());
         let mut file = match File::open(path) {
             Ok(file) => file,
@@ -319,13 +797,66 @@ where T: Write + Seek
         self.costume(d, costume, input)
     }
 
+    /// Cuts a frame out of a sprite sheet, returns the frame as a PNG file.
+    fn frame(
+        &mut self,
+        d: D,
+        costume: &Costume,
+        frame: Frame,
+        input: &Path,
+    ) -> Result<Option<Vec<u8>>> {
+        if !self.sheets.contains_key(&costume.path) {
+            let sheet = match fs::read(input.join(costume.path.as_str())) {
+                Ok(data) => match Png::decode(&data) {
+                    Ok(sheet) => Some(sheet),
+                    Err(reason) => {
+                        d.push(
+                            DiagnosticDetail::InvalidSpriteSheet(reason.into())
+                                .to_diagnostic(costume.span.clone()),
+                        );
+                        None
+                    }
+                },
+                Err(err) if matches!(err.kind(), io::ErrorKind::NotFound) => {
+                    d.push(
+                        DiagnosticDetail::FileNotFound(costume.path.clone())
+                            .to_diagnostic(costume.span.clone()),
+                    );
+                    None
+                }
+                Err(err) => bail!(err),
+            };
+            self.sheets.insert(costume.path.clone(), sheet);
+        }
+        let Some(sheet) = &self.sheets[&costume.path] else { return Ok(None) };
+        let Frame { index, width, height } = frame;
+        let fits = if width == 0 || height == 0 {
+            0
+        } else {
+            (sheet.width() / width) * (sheet.height() / height)
+        };
+        if index >= fits {
+            // Only reported for the first frame which does not fit.
+            if index == fits {
+                d.push(
+                    DiagnosticDetail::SpriteSheetTooSmall(fits)
+                        .to_diagnostic(costume.span.clone()),
+                );
+            }
+            return Ok(None);
+        }
+        let columns = sheet.width() / width;
+        let (x, y) = (index % columns * width, index / columns * height);
+        Ok(Some(sheet.crop(x, y, width, height)))
+    }
+
     fn proc(&mut self, s: S, d: D, proc: &Proc) -> Result<()> {
         let this_id = self.id.new_id();
         let prototype_id = self.id.new_id();
         let next_id = self.id.new_id();
         self.node(
             Node::new("procedures_definition", this_id)
-                .some_next_id((!proc.body.is_empty()).then_some(next_id))
+                .some_next_id((!is_blank(&proc.body)).then_some(next_id))
                 .top_level(true),
         )?;
         self.inputs()?;
@@ -372,7 +903,7 @@ where T: Write + Seek
         self.warp(proc.warp)?;
         self.end_obj()?;
         self.end_obj()?;
-        self.stmts(s, d, &proc.body, next_id, Some(next_id))
+        self.stmts(s, d, &proc.body, next_id, Some(this_id))
     }
 
     fn event(&mut self, s: S, d: D, event: &Event) -> Result<()> {
@@ -380,7 +911,7 @@ where T: Write + Seek
         let next_id = self.id.new_id();
         self.node(
             Node::new(event.opcode(), this_id)
-                .some_next_id((!event.body.is_empty()).then_some(next_id))
+                .some_next_id((!is_blank(&event.body)).then_some(next_id))
                 .top_level(true),
         )?;
         match &event.kind {
@@ -422,7 +953,27 @@ where T: Write + Seek
                 self.end_obj()?;
             }
         }
-        self.stmts(s, d, &event.body, next_id, Some(next_id))
+        if !matches!(
+            event.kind,
+            EventDetail::OnFlag { .. } | EventDetail::OnClick | EventDetail::OnClone
+        ) {
+            check_clones_multiply(s, d, &event.body);
+        }
+        self.stmts(s, d, &event.body, next_id, Some(this_id))
+    }
+
+    fn on_message(&mut self, s: S, d: D, on_message: &OnMessage) -> Result<()> {
+        let this_id = self.id.new_id();
+        let next_id = self.id.new_id();
+        self.node(
+            Node::new("event_whenbroadcastreceived", this_id)
+                .some_next_id((!is_blank(&on_message.body)).then_some(next_id))
+                .top_level(true),
+        )?;
+        self.single_field_id("BROADCAST_OPTION", &on_message.message)?;
+        self.end_obj()?;
+        check_clones_multiply(s, d, &on_message.body);
+        self.stmts(s, d, &on_message.body, next_id, Some(this_id))
     }
 
     fn stmts(
@@ -433,7 +984,14 @@ where T: Write + Seek
         mut this_id: NodeID,
         mut parent_id: Option<NodeID>,
     ) -> Result<()> {
+        // `pass` writes no blocks.
+        let stmts: Vec<&Stmt> =
+            stmts.iter().filter(|stmt| !matches!(stmt, Stmt::Pass { .. })).collect();
         for (i, stmt) in stmts.iter().enumerate() {
+            if let Stmt::Break { span } | Stmt::Continue { span } = stmt {
+                d.push(DiagnosticDetail::NotInLoop.to_diagnostic(span.clone()));
+                continue;
+            }
             let is_last = i == stmts.len() - 1;
             if is_last || stmt.is_terminator() {
                 self.stmt(s, d, stmt, this_id, None, parent_id)?;
@@ -469,22 +1027,34 @@ where T: Write + Seek
         )?;
         self.inputs()?;
         match stmt {
-            Stmt::Forever { body, .. } => {
+            Stmt::Forever { body, span } => {
+                if body.is_empty() {
+                    d.push(DiagnosticDetail::EmptyLoop.to_diagnostic(span.clone()));
+                }
+                if let Some(outer) = s.forever {
+                    d.push(
+                        DiagnosticDetail::NestedForever { outer: outer.clone() }
+                            .to_diagnostic(span.clone()),
+                    );
+                }
                 let body_id = self.id.new_id();
-                self.substack("SUBSTACK", (!body.is_empty()).then_some(body_id))?;
+                self.substack("SUBSTACK", (!is_blank(body)).then_some(body_id))?;
                 self.end_obj()?;
                 self.end_obj()?;
-                self.stmts(s, d, body, body_id, Some(this_id))?;
+                let escapes =
+                    has_escape(s.sprite, body, false, &mut Default::default());
+                let forever = if escapes { s.forever } else { Some(span) };
+                self.stmts(S { forever, ..s }, d, body, body_id, Some(this_id))?;
             }
             Stmt::Branch { cond, if_body, else_body } => {
                 let cond_id = self.id.new_id();
                 let if_body_id = self.id.new_id();
                 let else_body_id = self.id.new_id();
                 self.input(s, d, "CONDITION", &cond.borrow(), cond_id)?;
-                self.substack("SUBSTACK", (!if_body.is_empty()).then_some(if_body_id))?;
+                self.substack("SUBSTACK", (!is_blank(if_body)).then_some(if_body_id))?;
                 self.substack(
                     "SUBSTACK2",
-                    (!else_body.is_empty()).then_some(else_body_id),
+                    (!is_blank(else_body)).then_some(else_body_id),
                 )?;
                 self.end_obj()?;
                 self.end_obj()?;
@@ -492,7 +1062,11 @@ where T: Write + Seek
                 self.stmts(s, d, if_body, if_body_id, Some(this_id))?;
                 self.stmts(s, d, else_body, else_body_id, Some(this_id))?;
             }
-            Stmt::Repeat { times: input, body } | Stmt::Until { cond: input, body } => {
+            Stmt::Repeat { times: input, span, body }
+            | Stmt::Until { cond: input, span, body } => {
+                if body.is_empty() {
+                    d.push(DiagnosticDetail::EmptyLoop.to_diagnostic(span.clone()));
+                }
                 let input_id = self.id.new_id();
                 let body_id = self.id.new_id();
                 self.input(
@@ -506,14 +1080,57 @@ where T: Write + Seek
                     &input.borrow(),
                     input_id,
                 )?;
-                self.substack("SUBSTACK", (!body.is_empty()).then_some(body_id))?;
+                self.substack("SUBSTACK", (!is_blank(body)).then_some(body_id))?;
                 self.end_obj()?;
                 self.end_obj()?;
                 self.expr(s, d, &input.borrow(), input_id, this_id)?;
                 self.stmts(s, d, body, body_id, Some(this_id))?;
             }
+            Stmt::ForEach { name, span, times, body } => {
+                let times_id = self.id.new_id();
+                let body_id = self.id.new_id();
+                self.input(s, d, "VALUE", &times.borrow(), times_id)?;
+                self.substack("SUBSTACK", (!is_blank(body)).then_some(body_id))?;
+                self.end_obj()?;
+                self.resolve_variable(s, d, name, span)?;
+                self.end_obj()?;
+                self.expr(s, d, &times.borrow(), times_id, this_id)?;
+                self.stmts(s, d, body, body_id, Some(this_id))?;
+            }
             | Stmt::SetVar { name, span, value, .. }
             | Stmt::ChangeVar { name, span, value } => {
+                if s.is_arg(name) && !s.is_local_var(name) {
+                    d.push(
+                        DiagnosticDetail::AssignToArgument(name.clone())
+                            .to_diagnostic(span.clone()),
+                    );
+                }
+                if let Some(var) = s.get_var(name) {
+                    let value = &*value.borrow();
+                    if var.cloud && is_text(value) {
+                        d.push(
+                            DiagnosticDetail::CloudVariableNotNumber(name.clone())
+                                .to_diagnostic(span.clone()),
+                        );
+                    }
+                    let mismatch = match var.ty {
+                        Some(VarType::Number) => {
+                            is_text(value)
+                                || matches!(value, Expr::BinOp { op: BinOp::Join, .. })
+                        }
+                        Some(VarType::Text) => matches!(stmt, Stmt::ChangeVar { .. }),
+                        None => false,
+                    };
+                    if mismatch {
+                        d.push(
+                            DiagnosticDetail::VariableTypeMismatch {
+                                name: name.clone(),
+                                ty: var.ty.unwrap(),
+                            }
+                            .to_diagnostic(span.clone()),
+                        );
+                    }
+                }
                 let value_id = self.id.new_id();
                 self.input(s, d, "VALUE", &value.borrow(), value_id)?;
                 self.end_obj()?;
@@ -530,6 +1147,9 @@ where T: Write + Seek
             | Stmt::ListDelete { name, span, index: input } => {
                 let input_id = self.id.new_id();
                 self.list(s, d, name, span);
+                if matches!(stmt, Stmt::ListDelete { .. }) {
+                    check_list_index(s, d, name, &input.borrow(), span);
+                }
                 self.input(
                     s,
                     d,
@@ -553,6 +1173,9 @@ where T: Write + Seek
                 let index_id = self.id.new_id();
                 let value_id = self.id.new_id();
                 self.list(s, d, name, span);
+                if matches!(stmt, Stmt::ListSet { .. }) {
+                    check_list_index(s, d, name, &index.borrow(), span);
+                }
                 self.input(s, d, "INDEX", &index.borrow(), index_id)?;
                 self.input(s, d, "ITEM", &value.borrow(), value_id)?;
                 self.end_obj()?;
@@ -561,7 +1184,15 @@ where T: Write + Seek
                 self.expr(s, d, &index.borrow(), index_id, this_id)?;
                 self.expr(s, d, &value.borrow(), value_id, this_id)?;
             }
-            Stmt::ListChange { .. } => unreachable!(),
+            Stmt::ListChange { .. }
+            | Stmt::Broadcast { .. }
+            | Stmt::Ask { .. }
+            | Stmt::Loop { .. }
+            | Stmt::Break { .. }
+            | Stmt::Continue { .. }
+            | Stmt::Pass { .. }
+            | Stmt::Measure { .. }
+            | Stmt::Defer { .. } => unreachable!(),
             Stmt::Block { block, span, args } => {
                 if args.len() != block.args().len() {
                     d.push(
@@ -572,16 +1203,74 @@ where T: Write + Seek
                         .to_diagnostic(span.clone()),
                     );
                 }
+                check_colors(d, block.args(), args, span);
+                if let Some(proc) = s.proc.filter(|proc| proc.warp) {
+                    if matches!(
+                        block,
+                        Block::Wait | Block::WaitUntil | Block::Yield | Block::Ask
+                    ) {
+                        d.push(
+                            DiagnosticDetail::WaitInWarp(proc.name.clone())
+                                .to_diagnostic(span.clone()),
+                        );
+                    }
+                }
+                if s.stage.is_none() && matches!(block, Block::Show | Block::Hide) {
+                    d.push(
+                        DiagnosticDetail::BlockNotOnStage(*block)
+                            .to_diagnostic(span.clone()),
+                    );
+                }
+                if let (Block::Broadcast, Some(message)) = (block, args.first()) {
+                    check_payload_race(s, d, &message.borrow(), span);
+                }
+                if let (Block::Broadcast | Block::BroadcastAndWait, Some(message)) =
+                    (block, args.first())
+                {
+                    match &*message.borrow() {
+                        Expr::Str(message) => {
+                            if self.strict && s.receivers(message) == 0 {
+                                d.push(
+                                    DiagnosticDetail::UnreceivedBroadcast(
+                                        message.clone(),
+                                    )
+                                    .to_diagnostic(span.clone()),
+                                );
+                            }
+                        }
+                        _ => d.push(
+                            DiagnosticDetail::DynamicBroadcast
+                                .to_diagnostic(span.clone()),
+                        ),
+                    }
+                }
+                if let (Block::Wait, Some(duration)) = (block, args.first()) {
+                    // Not `is_zero`, which truncates, `wait 0.5;` is not a yield.
+                    let duration = &*duration.borrow();
+                    if matches!(duration, Expr::Int(0))
+                        || matches!(duration, Expr::Float(value) if *value == 0.0)
+                    {
+                        d.push(DiagnosticDetail::WaitZero.to_diagnostic(span.clone()));
+                    }
+                }
+                // `yield` is `wait 0`, which waits until the next frame.
+                let yield_args: Exprs;
+                let (names, args) = if matches!(block, Block::Yield) {
+                    yield_args = vec![Expr::Int(0).into()];
+                    (&["DURATION"][..], &yield_args)
+                } else {
+                    (block.args(), args)
+                };
                 let arg_ids: Vec<_> = (&mut self.id).take(args.len()).collect();
                 let menu_id = block.menu().map(|_| self.id.new_id());
                 let mut menu_value = None;
                 let mut menu_is_default = menu_id.is_some();
-                for ((&name, arg), arg_id) in
-                    block.args().iter().zip(args).zip(&arg_ids)
-                {
+                for ((&name, arg), arg_id) in names.iter().zip(args).zip(&arg_ids) {
                     if block.menu().is_some_and(|it| it.input == name) {
-                        if let Some(arg) = arg.borrow().try_to_string() {
-                            menu_value = Some(arg);
+                        // Numbers are passed as a value, which selects by index
+                        // instead of by name.
+                        if let Expr::Str(arg) = &*arg.borrow() {
+                            menu_value = Some(block.menu().unwrap().value(arg));
                             continue;
                         } else {
                             menu_is_default = false;
@@ -618,7 +1307,9 @@ where T: Write + Seek
                 for (arg, arg_id) in args.iter().zip(arg_ids) {
                     self.expr(s, d, &arg.borrow(), arg_id, this_id)?;
                 }
-                if let Some(menu) = block.menu() {
+                if let Some(menu) =
+                    block.menu().filter(|_| menu_is_default || !self.compact)
+                {
                     self.node(
                         Node::new(menu.opcode, menu_id.unwrap())
                             .parent_id(this_id)
@@ -632,20 +1323,22 @@ where T: Write + Seek
                 }
             }
             Stmt::ProcCall { name, span, args } => {
-                let Some(proc) = s.sprite.procs.get(name) else {
-                    d.push(
+                let Some(proc) = s.sprite.procs.get(&(name.clone(), args.len())) else {
+                    let detail = if s.sprite.overloads(name).is_empty() {
                         DiagnosticDetail::UnrecognizedProcedure(name.clone())
-                            .to_diagnostic(span.clone()),
-                    );
-                    return Ok(());
-                };
-                if args.len() != proc.args.len() {
-                    d.push(
+                    } else {
                         DiagnosticDetail::ProcArgsCountMismatch {
                             proc: name.clone(),
                             given: args.len(),
                         }
-                        .to_diagnostic(span.clone()),
+                    };
+                    d.push(detail.to_diagnostic(span.clone()));
+                    return Ok(());
+                };
+                if proc.inline {
+                    d.push(
+                        DiagnosticDetail::RecursiveInline(name.clone())
+                            .to_diagnostic(span.clone()),
                     );
                 }
                 let arg_ids: Vec<_> = (&mut self.id).take(args.len()).collect();
@@ -676,6 +1369,32 @@ where T: Write + Seek
         expr: &Expr,
         this_id: NodeID,
         parent_id: NodeID,
+    ) -> Result<()> {
+        if self.expression_depth == 0 {
+            let depth = expr.depth();
+            if depth > self.max_expression_depth {
+                d.push(
+                    DiagnosticDetail::ExpressionTooDeep {
+                        depth,
+                        max: self.max_expression_depth,
+                    }
+                    .to_diagnostic(expr.span().unwrap_or(0..0)),
+                );
+            }
+        }
+        self.expression_depth += 1;
+        let result = self._expr(s, d, expr, this_id, parent_id);
+        self.expression_depth -= 1;
+        result
+    }
+
+    fn _expr(
+        &mut self,
+        s: S,
+        d: D,
+        expr: &Expr,
+        this_id: NodeID,
+        parent_id: NodeID,
     ) -> Result<()> {
         match expr {
             | Expr::Int(_)
@@ -683,12 +1402,40 @@ where T: Write + Seek
             | Expr::Str(_)
             | Expr::Name { .. }
             | Expr::EnumVariant { .. } => {}
+            Expr::Bool(_) => unreachable!(),
+            Expr::SpriteVar { sprite, sprite_span, name, span } => {
+                let Some(target) = s.sprites.get(sprite) else {
+                    d.push(
+                        DiagnosticDetail::UnrecognizedSprite(sprite.clone())
+                            .to_diagnostic(sprite_span.clone()),
+                    );
+                    return Ok(());
+                };
+                if !target.vars.contains_key(name) {
+                    d.push(
+                        DiagnosticDetail::UnrecognizedVariable(name.clone())
+                            .to_diagnostic(span.clone()),
+                    );
+                }
+                let menu_id = self.id.new_id();
+                self.node(Node::new("sensing_of", this_id).parent_id(parent_id))?;
+                write!(self, r#","inputs":{{"OBJECT":[1,{menu_id}]}}"#)?;
+                self.single_field("PROPERTY", name)?;
+                self.end_obj()?;
+                self.node(
+                    Node::new("sensing_of_object_menu", menu_id)
+                        .parent_id(this_id)
+                        .shadow(true),
+                )?;
+                self.single_field("OBJECT", sprite)?;
+                self.end_obj()?;
+            }
             Expr::Arg { name, span } => {
                 if !s.is_arg(name) {
                     d.push(
                         DiagnosticDetail::UnrecognizedArgument {
                             name: name.clone(),
-                            proc: s.proc.map(|proc| proc.name.clone()),
+                            proc: s.proc.map(Proc::key),
                         }
                         .to_diagnostic(span.clone()),
                     );
@@ -709,6 +1456,7 @@ where T: Write + Seek
                         .to_diagnostic(span.clone()),
                     );
                 }
+                check_colors(d, repr.args(), args, span);
                 let arg_ids: Vec<_> = (&mut self.id).take(args.len()).collect();
                 self.node(Node::new(repr.opcode(), this_id).parent_id(parent_id))?;
                 self.inputs()?;
@@ -756,7 +1504,9 @@ where T: Write + Seek
                 for (arg, arg_id) in args.iter().zip(arg_ids) {
                     self.expr(s, d, &arg.borrow(), arg_id, this_id)?;
                 }
-                if let Some(menu) = repr.menu() {
+                if let Some(menu) =
+                    repr.menu().filter(|_| menu_is_default || !self.compact)
+                {
                     self.node(
                         Node::new(menu.opcode, menu_id.unwrap())
                             .parent_id(this_id)
@@ -798,12 +1548,14 @@ where T: Write + Seek
                 self.expr(s, d, &val.borrow(), val_id, this_id)?;
             }
             Expr::BinOp { op, lhs, rhs } => {
+                check_chained_comparison(d, expr, *op, &lhs.borrow(), &rhs.borrow());
                 let right_id = self.id.new_id();
                 if matches!(op, BinOp::Of) {
-                    if let Expr::Name { name, .. } = &*lhs.borrow() {
+                    if let Expr::Name { name, span } = &*lhs.borrow() {
                         if s.sprite.lists.contains_key(name)
                             || s.stage.is_some_and(|it| it.lists.contains_key(name))
                         {
+                            check_list_index(s, d, name, &rhs.borrow(), span);
                             self.node(
                                 Node::new("data_itemoflist", this_id)
                                     .parent_id(parent_id),
@@ -818,6 +1570,23 @@ where T: Write + Seek
                         }
                     }
                 }
+                if matches!(op, BinOp::Contains) {
+                    if let Expr::Name { name, span } = &*rhs.borrow() {
+                        self.list(s, d, name, span);
+                        let item_id = self.id.new_id();
+                        self.node(
+                            Node::new("data_listcontainsitem", this_id)
+                                .parent_id(parent_id),
+                        )?;
+                        self.inputs()?;
+                        self.input(s, d, "ITEM", &lhs.borrow(), item_id)?;
+                        self.end_obj()?;
+                        self.single_field_id("LIST", name)?;
+                        self.end_obj()?;
+                        self.expr(s, d, &lhs.borrow(), item_id, this_id)?;
+                        return Ok(());
+                    }
+                }
                 let left_id = self.id.new_id();
                 self.node(Node::new(op.opcode(), this_id).parent_id(parent_id))?;
                 self.inputs()?;
@@ -869,7 +1638,8 @@ where T: Write + Seek
         this_id: NodeID,
         shadow_id: NodeID,
     ) -> io::Result<()> {
-        self._input(s, d, name, expr, this_id, Some(shadow_id))
+        let shadow_id = (!self.compact).then_some(shadow_id);
+        self._input(s, d, name, expr, this_id, shadow_id)
     }
 
     fn substack(
@@ -902,18 +1672,11 @@ where T: Write + Seek
         self.inputs_comma = true;
         write!(self, r#""{name}":"#)?;
         match expr {
-            Expr::Int(value) => {
-                write!(self, r#"[1,[4,{}]]"#, json!(value))
-            }
-            Expr::Float(value) => {
-                write!(self, r#"[1,[4,{}]]"#, json!(value))
-            }
+            Expr::Int(value) => self.number_input(json!(value), shadow_id),
+            Expr::Float(value) => self.number_input(json!(value), shadow_id),
             Expr::Str(value) => {
-                let color = if name == "COLOR" || name == "COLOR2" {
-                    csscolorparser::parse(value).ok().filter(|color| color.a == 1.0)
-                } else {
-                    None
-                };
+                let color =
+                    if is_color_input(name) { parse_color(value) } else { None };
                 if name == "BROADCAST_INPUT" {
                     write!(self, r#"[1,[11,{},{}]]"#, json!(**value), json!(**value))
                 } else if let Some(color) = color {
@@ -924,12 +1687,8 @@ where T: Write + Seek
             }
             Expr::EnumVariant { enum_name, enum_span, variant_name, variant_span } => {
                 if let Some(enum_) = s.sprite.enums.get(enum_name) {
-                    let index = enum_
-                        .variants
-                        .iter()
-                        .position(|(variant, _)| variant == variant_name);
-                    if let Some(index) = index {
-                        write!(self, r#"[1,[10,{index}]]"#)
+                    if let Some(value) = enum_.value(variant_name) {
+                        write!(self, r#"[1,[10,{value}]]"#)
                     } else {
                         d.push(
                             DiagnosticDetail::UnrecognizedEnumVariant {
@@ -952,14 +1711,30 @@ where T: Write + Seek
                 }
             }
             Expr::Name { name: var, span } => {
-                if let Some(resolved) =
-                    self.resolve_local_variable(s, var).map(|it| json!(it))
-                {
-                    write!(self, "[3,[12,{},{}],", resolved, resolved)?;
-                } else if s.is_var(var) {
-                    write!(self, "[3,[12,{},{}],", json!(**var), json!(**var))?;
+                if let Some(resolved) = self.resolve_local_variable(s, var) {
+                    write!(
+                        self,
+                        "[{},[12,{},{}]",
+                        self.covered(),
+                        json!(self.local_names[&resolved]),
+                        json!(resolved)
+                    )?;
+                } else if let Some(var) = s.get_var(var) {
+                    write!(
+                        self,
+                        "[{},[12,{},{}]",
+                        self.covered(),
+                        json!(*var.scratch_name()),
+                        json!(*var.name)
+                    )?;
                 } else if s.is_list(var) {
-                    write!(self, "[3,[13,{},{}],", json!(**var), json!(**var))?;
+                    write!(
+                        self,
+                        "[{},[13,{},{}]",
+                        self.covered(),
+                        json!(**var),
+                        json!(**var)
+                    )?;
                 } else {
                     d.push(
                         DiagnosticDetail::UnrecognizedVariable(var.clone())
@@ -972,23 +1747,61 @@ where T: Write + Seek
                 if name == "CONDITION" || name == "CONDITION2" {
                     return write!(self, r#"[2,{this_id}]"#);
                 }
-                write!(self, r#"[3,{this_id},"#)?;
+                write!(self, r#"[{},{this_id}"#, self.covered())?;
                 self.input_shadow(shadow_id, name)
             }
         }
     }
 
+    fn number_input(
+        &mut self,
+        value: serde_json::Value,
+        shadow_id: Option<NodeID>,
+    ) -> io::Result<()> {
+        if let Some(shadow_id) = shadow_id {
+            write!(self, r#"[3,[4,{value}],{shadow_id}]"#)
+        } else {
+            write!(self, r#"[1,[4,{value}]]"#)
+        }
+    }
+
     fn input_shadow(
         &mut self,
         shadow_id: Option<NodeID>,
         name: &str,
     ) -> io::Result<()> {
         if let Some(shadow_id) = shadow_id {
-            write!(self, "{shadow_id}]")
+            write!(self, ",{shadow_id}]")
+        } else if self.compact {
+            // The input of kind 2 has no shadow, see `covered`.
+            self.write_all(b"]")
         } else if name == "BROADCAST_INPUT" {
-            self.write_all(br#"[11,"message1","message1"]]"#)
+            self.write_all(br#",[11,"message1","message1"]]"#)
+        } else if is_color_input(name) {
+            self.write_all(br##",[9,"#000000"]]"##)
+        } else {
+            self.write_all(br#",[10,""]]"#)
+        }
+    }
+
+    /// Kind of an input which holds a reporter or variable: 3 when it covers a shadow,
+    /// which the editor shows when the reporter is dragged out, 2 when it has none. The
+    /// shadow is never run, so leaving it out in `compact` builds does not change what
+    /// the project does.
+    fn covered(&self) -> u8 {
+        if self.compact {
+            2
         } else {
-            self.write_all(br#"[10,""]]"#)
+            3
+        }
+    }
+
+    /// Records the extension of the block with `opcode`, if it is from one.
+    pub fn use_extension(&mut self, opcode: &str) {
+        if let Some(extension) = EXTENSIONS.iter().find(|extension| {
+            opcode.strip_prefix(**extension).is_some_and(|rest| rest.starts_with('_'))
+        }) {
+            self.extensions.insert(extension);
         }
     }
 
@@ -1011,14 +1824,16 @@ where T: Write + Seek
         name: &SmolStr,
         span: &Span,
     ) -> io::Result<()> {
-        if s.is_local_var(name) {
-            return self.single_field(
-                "VARIABLE",
-                &local_variable_resolved_name(s.proc.unwrap(), name),
+        if let Some(resolved) = self.resolve_local_variable(s, name) {
+            return write!(
+                self,
+                r#","fields":{{"VARIABLE":[{},{}]}}"#,
+                json!(self.local_names[&resolved]),
+                json!(resolved)
             );
         }
-        if s.is_var(name) {
-            return self.single_field_id("VARIABLE", name);
+        if let Some(var) = s.get_var(name) {
+            return self.variable_field(var);
         }
         d.push(
             DiagnosticDetail::UnrecognizedVariable(name.clone())
@@ -1034,14 +1849,16 @@ where T: Write + Seek
         name: &SmolStr,
         span: &Span,
     ) -> io::Result<()> {
-        if s.is_local_var(name) {
-            return self.single_field(
-                "VARIABLE",
-                &local_variable_resolved_name(s.proc.unwrap(), name),
+        if let Some(resolved) = self.resolve_local_variable(s, name) {
+            return write!(
+                self,
+                r#","fields":{{"VARIABLE":[{},{}]}}"#,
+                json!(self.local_names[&resolved]),
+                json!(resolved)
             );
         }
-        if s.is_var(name) {
-            return self.single_field_id("VARIABLE", name);
+        if let Some(var) = s.get_var(name) {
+            return self.variable_field(var);
         }
         if s.is_list(name) {
             return self.single_field_id("LIST", name);
@@ -1053,6 +1870,15 @@ where T: Write + Seek
         Ok(())
     }
 
+    fn variable_field(&mut self, var: &Var) -> io::Result<()> {
+        write!(
+            self,
+            r#","fields":{{"VARIABLE":[{},{}]}}"#,
+            json!(*var.scratch_name()),
+            json!(*var.name)
+        )
+    }
+
     fn single_field_id(&mut self, name: &'static str, value: &str) -> io::Result<()> {
         write!(self, r#","fields":{{"{name}":[{},{}]}}"#, json!(value), json!(value))
     }
@@ -1108,14 +1934,352 @@ impl<T> Write for Sb3<T>
 where T: Write + Seek
 {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.zip.write(buf)
+        self.sink.write(buf)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.zip.flush()
+        self.sink.flush()
+    }
+}
+
+impl<T> Write for Sink<T>
+where T: Write + Seek
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Sb3(zip) => zip.write(buf),
+            Sink::ProjectJson(json) => json.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Sb3(zip) => zip.flush(),
+            Sink::ProjectJson(json) => json.flush(),
+        }
+    }
+}
+
+fn is_color_input(name: &str) -> bool {
+    name == "COLOR" || name == "COLOR2"
+}
+
+/// Scratch color inputs only store opaque colors, as a `#rrggbb` string.
+fn parse_color(value: &str) -> Option<csscolorparser::Color> {
+    csscolorparser::parse(value).ok().filter(|color| color.a == 1.0)
+}
+
+fn check_colors(d: D, names: &[&str], args: &Exprs, span: &Span) {
+    for (&name, arg) in names.iter().zip(args) {
+        if let Expr::Str(value) = &*arg.borrow() {
+            if is_color_input(name) && parse_color(value).is_none() {
+                d.push(
+                    DiagnosticDetail::InvalidColor(value.clone())
+                        .to_diagnostic(span.clone()),
+                );
+            }
+        }
+    }
+}
+
+/// Lists can only be given items before the project runs in `goboscript.toml`, so a
+/// literal index is only checked against lists which have initial items.
+fn check_list_index(s: S, d: D, name: &str, index: &Expr, span: &Span) {
+    let Some(list) = s.get_list(name) else { return };
+    let Expr::Int(index) = *index else { return };
+    let len = list.default.len();
+    if len > 0 && (index < 1 || index as usize > len) {
+        d.push(
+            DiagnosticDetail::ListIndexOutOfBounds { index, len }
+                .to_diagnostic(span.clone()),
+        );
+    }
+}
+
+/// Receivers of a broadcast only start running after the broadcasting script yields,
+/// so they may read a payload which was already set by a later broadcast.
+fn check_payload_race(s: S, d: D, message: &Expr, span: &Span) {
+    let Expr::Str(message) = message else { return };
+    let stage = s.stage.unwrap_or(s.sprite);
+    if !stage.vars.contains_key(&payload_variable_name(message)) {
+        return;
+    }
+    let receivers = s.receivers(message);
+    if receivers > 1 {
+        d.push(
+            DiagnosticDetail::PayloadRace { message: message.clone(), receivers }
+                .to_diagnostic(span.clone()),
+        );
+    }
+}
+
+/// Clones run the scripts of their sprite too, except for `onflag`, which deletes them
+/// first, and `onclick`, which only the clicked one runs. A script which other clones
+/// also run and which clones its own sprite makes the clones multiply.
+fn check_clones_multiply(s: S, d: D, body: &Stmts) {
+    // The stage cannot be cloned.
+    if s.stage.is_none() {
+        return;
+    }
+    let Some(span) = find_clone_myself(s.sprite, body, &mut Default::default()) else {
+        return;
+    };
+    // A procedure may be called from several scripts, it is only reported once.
+    if !d.iter().any(|diag| {
+        matches!(diag.detail, DiagnosticDetail::ClonesMultiply) && diag.span == span
+    }) {
+        d.push(DiagnosticDetail::ClonesMultiply.to_diagnostic(span));
+    }
+}
+
+/// Whether running `stmts` may stop the script or delete the clone, which ends a
+/// `forever` loop. In a procedure it calls, `stop_this_script` only returns from the
+/// procedure, but `stop_all` and `delete_this_clone` still end the loop.
+fn has_escape(
+    sprite: &Sprite,
+    stmts: &Stmts,
+    in_proc: bool,
+    visited: &mut FxHashSet<ProcKey>,
+) -> bool {
+    stmts.iter().any(|stmt| match stmt {
+        Stmt::Repeat { body, .. }
+        | Stmt::Forever { body, .. }
+        | Stmt::Until { body, .. }
+        | Stmt::ForEach { body, .. } => has_escape(sprite, body, in_proc, visited),
+        Stmt::Branch { if_body, else_body, .. } => {
+            has_escape(sprite, if_body, in_proc, visited)
+                || has_escape(sprite, else_body, in_proc, visited)
+        }
+        Stmt::Block { block: Block::DeleteThisClone | Block::StopAll, .. } => true,
+        Stmt::Block { block: Block::StopThisScript, .. } => !in_proc,
+        Stmt::ProcCall { name, args, .. } => {
+            let key = (name.clone(), args.len());
+            match sprite.procs.get(&key) {
+                Some(proc) if visited.insert(key) => {
+                    has_escape(sprite, &proc.body, true, visited)
+                }
+                _ => false,
+            }
+        }
+        _ => false,
+    })
+}
+
+/// Finds a `clone` of the sprite itself in `stmts`, or in the procedures it calls.
+fn find_clone_myself(
+    sprite: &Sprite,
+    stmts: &Stmts,
+    visited: &mut FxHashSet<ProcKey>,
+) -> Option<Span> {
+    stmts.iter().find_map(|stmt| match stmt {
+        Stmt::Repeat { body, .. }
+        | Stmt::Forever { body, .. }
+        | Stmt::Until { body, .. }
+        | Stmt::ForEach { body, .. } => find_clone_myself(sprite, body, visited),
+        Stmt::Branch { if_body, else_body, .. } => {
+            find_clone_myself(sprite, if_body, visited)
+                .or_else(|| find_clone_myself(sprite, else_body, visited))
+        }
+        Stmt::Block { block: Block::Clone0, span, .. } => Some(span.clone()),
+        Stmt::Block { block: Block::Clone1, span, args } => {
+            let myself = args.first().is_some_and(
+                |arg| matches!(&*arg.borrow(), Expr::Str(name) if name == "_myself_"),
+            );
+            myself.then(|| span.clone())
+        }
+        Stmt::ProcCall { name, args, .. } => {
+            let key = (name.clone(), args.len());
+            let proc = sprite.procs.get(&key)?;
+            visited.insert(key).then_some(())?;
+            find_clone_myself(sprite, &proc.body, visited)
+        }
+        _ => None,
+    })
+}
+
+/// `a < b < c` parses as `(a < b) < c`, which compares true or false with `c`.
+/// Comparing two comparisons with each other is allowed, as it is sometimes intended.
+fn check_chained_comparison(d: D, expr: &Expr, op: BinOp, lhs: &Expr, rhs: &Expr) {
+    if matches!(op, BinOp::Lt | BinOp::Gt | BinOp::Eq)
+        && is_comparison(lhs) != is_comparison(rhs)
+    {
+        d.push(
+            DiagnosticDetail::ChainedComparison
+                .to_diagnostic(expr.span().unwrap_or(0..0)),
+        );
+    }
+}
+
+/// `<=`, `>=` and `!=` have already been turned into `not` of another comparison.
+fn is_comparison(expr: &Expr) -> bool {
+    match expr {
+        Expr::BinOp { op, .. } => matches!(op, BinOp::Lt | BinOp::Gt | BinOp::Eq),
+        Expr::UnOp { op: UnOp::Not, val } => is_comparison(&val.borrow()),
+        _ => false,
     }
 }
 
 fn local_variable_resolved_name(proc: &Proc, name: &SmolStr) -> String {
-    format!("{}.{}", proc.name, name)
+    format!("{}/{}.{}", proc.name, proc.args.len(), name)
+}
+
+fn startup_position(config: &Config, name: &str) -> Option<usize> {
+    config.startup_order.iter().position(|sprite| sprite == name)
+}
+
+/// Whether statements write no blocks, because they are empty or only `pass`.
+fn is_blank(stmts: &Stmts) -> bool {
+    stmts.iter().all(|stmt| matches!(stmt, Stmt::Pass { .. }))
+}
+
+/// Text of the stage comment which holds the instructions and credits of the project.
+fn notes_comment(config: &Config) -> Option<String> {
+    let sections = [
+        ("Instructions", &config.instructions),
+        ("Notes and Credits", &config.credits),
+    ];
+    let sections: Vec<_> = sections
+        .into_iter()
+        .filter_map(|(title, text)| Some(format!("{title}\n{}", text.as_ref()?)))
+        .collect();
+    (!sections.is_empty()).then(|| sections.join("\n\n"))
+}
+
+/// Whether an expression is a string literal which is not a number.
+fn is_text(expr: &Expr) -> bool {
+    matches!(expr, Expr::Str(value) if value.parse::<f64>().is_err())
+}
+
+/// Cloud variables are shared by everyone running the project, so Scratch only allows a
+/// few, in the stage, with short names.
+fn check_cloud_variables(sprite: &Sprite, is_stage: bool, diags: D) {
+    let mut cloud: Vec<&Var> = sprite.vars.values().filter(|var| var.cloud).collect();
+    cloud.sort_by_key(|var| var.span.start);
+    for (i, var) in cloud.into_iter().enumerate() {
+        let reason = if !is_stage {
+            format!(
+                "declare `cloud {};` in the stage, sprites can use it from there",
+                var.name
+            )
+        } else if var.scratch_name().chars().count() > MAX_CLOUD_NAME {
+            format!(
+                "the name of a cloud variable can be at most {MAX_CLOUD_NAME} \
+                 characters long, including the `{CLOUD_PREFIX}` before it"
+            )
+        } else if i >= MAX_CLOUD_VARIABLES {
+            format!("a project can have at most {MAX_CLOUD_VARIABLES} cloud variables")
+        } else {
+            continue;
+        };
+        diags.push(
+            DiagnosticDetail::InvalidCloudVariable(reason.into())
+                .to_diagnostic(var.span.clone()),
+        );
+    }
+}
+
+/// Checks the names variables and lists are given in Scratch. Scratch finds variables by
+/// name in some places, so two variables must not be given the same name, which
+/// mangling the names of local variables could do.
+fn check_scratch_names(
+    sprite: &Sprite,
+    stage: Option<&Sprite>,
+    local_names: &FxHashMap<String, String>,
+    diags: D,
+) {
+    let invalid = |diags: D, what: String, span: &Span| {
+        diags.push(
+            DiagnosticDetail::InvalidScratchName(what.into())
+                .to_diagnostic(span.clone()),
+        );
+    };
+    let mut vars: Vec<&Var> = sprite.vars.values().collect();
+    vars.sort_by_key(|var| var.span.start);
+    for var in vars {
+        // The length of cloud variables is checked with the other rules for them.
+        let name = if var.cloud { var.name.clone() } else { var.scratch_name() };
+        if let Some(problem) = scratch_name_problem(&name) {
+            invalid(
+                diags,
+                format!("`{name}` cannot be a variable name, as {problem}"),
+                &var.span,
+            );
+        }
+    }
+    let mut lists: Vec<&List> = sprite.lists.values().collect();
+    lists.sort_by_key(|list| list.span.start);
+    for list in lists {
+        if let Some(problem) = scratch_name_problem(&list.name) {
+            invalid(
+                diags,
+                format!("`{}` cannot be a list name, as {problem}", list.name),
+                &list.span,
+            );
+        }
+    }
+    let mut procs: Vec<_> = sprite.procs.values().filter(|proc| !proc.inline).collect();
+    procs.sort_by_key(|proc| &proc.name);
+    let mut seen: FxHashMap<&str, &SmolStr> = Default::default();
+    for proc in procs {
+        let mut locals: Vec<_> = proc.locals.iter().collect();
+        locals.sort_by_key(|(local, _)| *local);
+        for (local, var) in locals {
+            let mangled = &local_names[&local_variable_resolved_name(proc, local)];
+            if let Some(problem) = scratch_name_problem(mangled) {
+                invalid(
+                    diags,
+                    format!("`{local}` is named `{mangled}` in Scratch, which is not valid as {problem}"),
+                    &var.span,
+                );
+                continue;
+            }
+            let other = sprite
+                .vars
+                .get(mangled.as_str())
+                .or_else(|| stage.and_then(|stage| stage.vars.get(mangled.as_str())))
+                .map(|other| format!("the variable `{}`", other.name))
+                .or_else(|| {
+                    seen.get(mangled.as_str()).map(|other| {
+                        format!("the local variable `{other}` of another procedure")
+                    })
+                });
+            if let Some(other) = other {
+                invalid(
+                    diags,
+                    format!(
+                        "`{local}` is named `{mangled}` in Scratch, which is also the name \
+                         of {other}, rename one of them"
+                    ),
+                    &var.span,
+                );
+            }
+            seen.insert(mangled, local);
+        }
+    }
+}
+
+/// Scratch starts the scripts of the sprite in front first, so the sprites in
+/// `startup_order` are put in front of the others, with the first one in front. The
+/// others keep their alphabetical order behind them.
+fn layer_order(project: &Project, config: &Config, name: &str) -> Option<usize> {
+    if config.startup_order.is_empty() {
+        return None;
+    }
+    if let Some(position) = startup_position(config, name) {
+        return Some(project.sprites.len() - position);
+    }
+    let behind = project
+        .sprites
+        .keys()
+        .filter(|other| {
+            other.as_str() < name && startup_position(config, other).is_none()
+        })
+        .count();
+    Some(behind + 1)
+}
+
+/// Entries are written with a fixed modification time, so that building the same
+/// project twice produces the same archive.
+fn file_options() -> FileOptions {
+    FileOptions::default().last_modified_time(DateTime::default())
 }
@@ -63,6 +63,8 @@ where T: Write + Seek
             self.write_all(b",")?;
         }
         self.blocks_comma = true;
+        self.block_count += 1;
+        self.use_extension(node.opcode);
         write!(self, r#"{}:{{"opcode":"{}""#, node.this_id, node.opcode)?;
         if let Some(next_id) = node.next_id {
             write!(self, r#","next":{next_id}"#)?;
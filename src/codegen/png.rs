@@ -0,0 +1,194 @@
+use std::io::{Read, Write};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// Largest width or height of a sprite sheet.
+const MAX_SIDE: usize = 16384;
+/// Largest size of the decompressed image data of a sprite sheet, in bytes.
+const MAX_IMAGE_BYTES: usize = 256 << 20;
+
+/// A decoded PNG image, with its scanlines unfiltered. Only images with 8 or 16 bits
+/// per channel which are not interlaced are supported, so that frames can be cut out
+/// by copying bytes.
+pub struct Png {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+    bytes_per_pixel: usize,
+    /// Palette and transparency chunks, copied into every frame.
+    ancillary: Vec<([u8; 4], Vec<u8>)>,
+    pixels: Vec<u8>,
+}
+
+impl Png {
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let mut data =
+            data.strip_prefix(SIGNATURE).ok_or_else(|| "not a PNG file".to_string())?;
+        let mut header = None;
+        let mut ancillary = vec![];
+        let mut compressed = vec![];
+        while data.len() >= 12 {
+            let len = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+            let kind: [u8; 4] = data[4..8].try_into().unwrap();
+            let body = data.get(8..8 + len).ok_or("truncated PNG file")?;
+            match &kind {
+                b"IHDR" => header = Some(body),
+                b"PLTE" | b"tRNS" => ancillary.push((kind, body.to_vec())),
+                b"IDAT" => compressed.extend_from_slice(body),
+                b"IEND" => break,
+                _ => {}
+            }
+            data = &data[(12 + len).min(data.len())..];
+        }
+        let header = header.filter(|it| it.len() == 13).ok_or("missing PNG header")?;
+        let width = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+        let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+        let (bit_depth, color_type, interlace) = (header[8], header[9], header[12]);
+        if bit_depth < 8 {
+            return Err(format!("{bit_depth}-bit images are not supported"));
+        }
+        if interlace != 0 {
+            return Err("interlaced images are not supported".to_string());
+        }
+        let channels = match color_type {
+            0 | 3 => 1,
+            4 => 2,
+            2 => 3,
+            6 => 4,
+            _ => return Err(format!("unknown color type {color_type}")),
+        };
+        let bytes_per_pixel = channels * bit_depth as usize / 8;
+        if width == 0 || height == 0 {
+            return Err("image is empty".to_string());
+        }
+        if width > MAX_SIDE || height > MAX_SIDE {
+            return Err(format!(
+                "{width}x{height} images are too large, the largest width and height is \
+                 {MAX_SIDE}"
+            ));
+        }
+        let stride = width * bytes_per_pixel;
+        let size = (stride + 1)
+            .checked_mul(height)
+            .filter(|size| *size <= MAX_IMAGE_BYTES)
+            .ok_or_else(|| format!("{width}x{height} images are too large"))?;
+        let mut filtered = vec![];
+        ZlibDecoder::new(compressed.as_slice())
+            .take(size as u64)
+            .read_to_end(&mut filtered)
+            .map_err(|err| err.to_string())?;
+        if filtered.len() < size {
+            return Err("truncated PNG image data".to_string());
+        }
+        let mut pixels = vec![0; height * stride];
+        for y in 0..height {
+            let row = &filtered[y * (stride + 1)..(y + 1) * (stride + 1)];
+            let (above, current) = pixels.split_at_mut(y * stride);
+            let above = above.get(above.len().wrapping_sub(stride)..).unwrap_or(&[]);
+            unfilter(
+                row[0],
+                &row[1..],
+                above,
+                &mut current[..stride],
+                bytes_per_pixel,
+            )?;
+        }
+        Ok(Self {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            bytes_per_pixel,
+            ancillary,
+            pixels,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Encodes the `width` by `height` area whose top left corner is at `x`, `y` as a
+    /// new PNG file.
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Vec<u8> {
+        let stride = self.width * self.bytes_per_pixel;
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        for row in y..y + height {
+            let start = row * stride + x * self.bytes_per_pixel;
+            encoder.write_all(&[0]).unwrap();
+            encoder
+                .write_all(&self.pixels[start..start + width * self.bytes_per_pixel])
+                .unwrap();
+        }
+        let mut header = vec![];
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        header.extend_from_slice(&(height as u32).to_be_bytes());
+        header.extend_from_slice(&[self.bit_depth, self.color_type, 0, 0, 0]);
+        let mut png = SIGNATURE.to_vec();
+        write_chunk(&mut png, b"IHDR", &header);
+        for (kind, body) in &self.ancillary {
+            write_chunk(&mut png, kind, body);
+        }
+        write_chunk(&mut png, b"IDAT", &encoder.finish().unwrap());
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+fn unfilter(
+    filter: u8,
+    row: &[u8],
+    above: &[u8],
+    out: &mut [u8],
+    bytes_per_pixel: usize,
+) -> Result<(), String> {
+    for i in 0..row.len() {
+        let a = if i >= bytes_per_pixel { out[i - bytes_per_pixel] } else { 0 };
+        let b = above.get(i).copied().unwrap_or(0);
+        let c = if i >= bytes_per_pixel {
+            above.get(i - bytes_per_pixel).copied().unwrap_or(0)
+        } else {
+            0
+        };
+        let predicted = match filter {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) / 2) as u8,
+            4 => paeth(a, b, c),
+            _ => return Err(format!("unknown filter type {filter}")),
+        };
+        out[i] = row[i].wrapping_add(predicted);
+    }
+    Ok(())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    png.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(body);
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(body);
+    png.extend_from_slice(&hasher.finalize().to_be_bytes());
+}
@@ -0,0 +1,76 @@
+use serde_json::{Map, Value};
+
+/// Keys of a target which a fragment may add to.
+const KEYS: &[&str] = &["blocks", "comments", "broadcasts"];
+
+/// A hand-written fragment of a target, merged into a sprite by `raw_json`. The ids of
+/// its blocks and comments are prefixed, so that they cannot collide with the numbered
+/// ids of generated blocks or with other fragments.
+#[derive(Default)]
+pub struct Fragment {
+    pub blocks: Map<String, Value>,
+    pub comments: Map<String, Value>,
+    pub broadcasts: Map<String, Value>,
+}
+
+impl Fragment {
+    pub fn parse(src: &str, prefix: &str) -> Result<Self, String> {
+        let value: Value = serde_json::from_str(src).map_err(|err| err.to_string())?;
+        let Value::Object(mut target) = value else {
+            return Err("a fragment must be a JSON object".to_string());
+        };
+        if let Some(key) = target.keys().find(|key| !KEYS.contains(&key.as_str())) {
+            return Err(format!(
+                "`{key}` cannot be merged, a fragment can only contain {}",
+                KEYS.iter()
+                    .map(|key| format!("`{key}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        let mut take = |key: &str| match target.remove(key) {
+            None => Ok(Map::new()),
+            Some(Value::Object(map)) => Ok(map),
+            Some(_) => Err(format!("`{key}` must be a JSON object")),
+        };
+        let (blocks, comments, broadcasts) =
+            (take("blocks")?, take("comments")?, take("broadcasts")?);
+        let id = |id: &str| format!("{prefix}{id}");
+        let blocks = blocks
+            .into_iter()
+            .map(|(key, mut block)| {
+                // Top-level reporters are stored as arrays, and cannot reference blocks.
+                if let Value::Object(block) = &mut block {
+                    for field in ["parent", "next", "comment"] {
+                        prefix_id(block.get_mut(field), prefix);
+                    }
+                    if let Some(Value::Object(inputs)) = block.get_mut("inputs") {
+                        for input in inputs.values_mut().filter_map(Value::as_array_mut)
+                        {
+                            for value in input.iter_mut().skip(1) {
+                                prefix_id(Some(value), prefix);
+                            }
+                        }
+                    }
+                }
+                (id(&key), block)
+            })
+            .collect();
+        let comments = comments
+            .into_iter()
+            .map(|(key, mut comment)| {
+                if let Value::Object(comment) = &mut comment {
+                    prefix_id(comment.get_mut("blockId"), prefix);
+                }
+                (id(&key), comment)
+            })
+            .collect();
+        Ok(Self { blocks, comments, broadcasts })
+    }
+}
+
+fn prefix_id(value: Option<&mut Value>, prefix: &str) {
+    if let Some(Value::String(id)) = value {
+        id.insert_str(0, prefix);
+    }
+}
@@ -1,8 +1,12 @@
-use std::fmt::{self, Display, Formatter};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+};
 
+use clap_derive::ValueEnum;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Default, PartialEq, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq, Clone)]
 pub struct Config {
     #[serde(default)]
     pub frame_rate: Option<u64>,
@@ -20,6 +24,117 @@ pub struct Config {
     pub stage_width: Option<u64>,
     #[serde(default)]
     pub stage_height: Option<u64>,
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub max_size_mb: Option<f64>,
+    #[serde(default)]
+    pub strict: Option<bool>,
+    #[serde(default)]
+    pub compact: Option<bool>,
+    #[serde(default)]
+    pub packager: Vec<String>,
+    #[serde(default)]
+    pub instructions: Option<String>,
+    #[serde(default)]
+    pub credits: Option<String>,
+    #[serde(default)]
+    pub mangle: Mangle,
+    #[serde(default)]
+    pub max_expression_depth: Option<usize>,
+    #[serde(default)]
+    pub clone_counter: Option<bool>,
+    #[serde(default)]
+    pub native_counter: Option<bool>,
+    #[serde(default)]
+    pub selected_sprite: Option<String>,
+    #[serde(default)]
+    pub draggable_sprites: Vec<String>,
+    #[serde(default)]
+    pub initial_backdrop: Option<String>,
+    #[serde(default)]
+    pub initial_costumes: BTreeMap<String, String>,
+    #[serde(default)]
+    pub hidden_sprites: Vec<String>,
+    #[serde(default)]
+    pub startup_order: Vec<String>,
+    #[serde(default)]
+    pub tempo: Option<f64>,
+    #[serde(default)]
+    pub volume: BTreeMap<String, f64>,
+    #[serde(default)]
+    pub debug_variables: Vec<String>,
+    #[serde(default)]
+    pub variables: BTreeMap<String, Value>,
+    #[serde(default)]
+    pub lists: BTreeMap<String, Vec<Value>>,
+    #[serde(default)]
+    pub lints: Lints,
+}
+
+/// Initial value of a variable or list item.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(untagged)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+/// The `[lints]` table, which sets the level of lints by name, and the options of lints
+/// which check for a policy.
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq, Clone)]
+pub struct Lints {
+    /// Which character lines must be indented with, checked by the `indentation` lint.
+    #[serde(default)]
+    pub indent_style: Option<Indentation>,
+    #[serde(flatten)]
+    pub levels: BTreeMap<String, LintLevel>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Indentation {
+    Spaces,
+    Tabs,
+}
+
+impl Display for Indentation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Spaces => write!(f, "spaces"),
+            Self::Tabs => write!(f, "tabs"),
+        }
+    }
+}
+
+/// How a lint is reported, overriding its default level.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// The runtime the project is compiled for. TurboWarp supports some blocks which are
+/// hidden in Scratch.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, ValueEnum)]
+pub enum Target {
+    #[default]
+    Scratch,
+    Turbowarp,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq, Eq, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Mangle {
+    Short,
+    #[default]
+    Readable,
+    Hashed,
 }
 
 impl Config {
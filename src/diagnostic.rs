@@ -9,8 +9,9 @@ use smol_str::SmolStr;
 
 use self::keys::all_keys;
 use crate::{
-    ast::Sprite,
+    ast::{is_on_messages_proc, ProcKey, Sprite, VarType},
     blocks::{Block, Repr},
+    config::Indentation,
     lexer::token::Token,
 };
 
@@ -27,13 +28,18 @@ pub enum DiagnosticDetail {
     UnrecognizedToken(Token, Vec<String>),
     ExtraToken(Token),
     FileNotFound(SmolStr),
+    EmptyGlob(SmolStr),
+    WrongIndentation(Indentation),
     FollowedByUnreachableCode,
     UnrecognizedReporter(SmolStr),
     UnrecognizedVariable(SmolStr),
+    UndeclaredVariable(SmolStr),
+    UnreceivedBroadcast(SmolStr),
     UnrecognizedProcedure(SmolStr),
     UnrecognizedList(SmolStr),
+    UnrecognizedSprite(SmolStr),
     UnrecognizedKey(SmolStr),
-    UnrecognizedArgument { name: SmolStr, proc: Option<SmolStr> },
+    UnrecognizedArgument { name: SmolStr, proc: Option<ProcKey> },
     UnrecognizedEnum { enum_name: SmolStr, variant_name: SmolStr },
     UnrecognizedEnumVariant { enum_name: SmolStr, variant_name: SmolStr },
     UnusedVariable(SmolStr),
@@ -44,7 +50,89 @@ pub enum DiagnosticDetail {
     BlockArgsCountMismatch { block: Block, given: usize },
     ReprArgsCountMismatch { repr: Repr, given: usize },
     ProcArgsCountMismatch { proc: SmolStr, given: usize },
+    DuplicateProcedure { name: SmolStr, args: usize },
     NoCostumes,
+    DuplicateArgument(SmolStr),
+    ArgumentShadowsVariable(SmolStr),
+    AssignToArgument(SmolStr),
+    InvalidColor(SmolStr),
+    ExpressionTooDeep { depth: usize, max: usize },
+    RecursiveInline(SmolStr),
+    InlineWarp(SmolStr),
+    InlineCapture { name: SmolStr, proc: SmolStr },
+    RecursiveMacro(SmolStr),
+    DuplicateMacro(SmolStr),
+    MacroShadowsReporter(SmolStr),
+    MacroShadowsBlock(SmolStr),
+    MacroArgsCountMismatch { name: SmolStr, expected: usize, given: usize },
+    MacroArgumentNotVariable { name: SmolStr, param: SmolStr },
+    StatementMacroInExpression(SmolStr),
+    ExpressionMacroAsStatement(SmolStr),
+    InvalidVersion(SmolStr),
+    IncompatibleVersion(SmolStr),
+    WaitInWarp(SmolStr),
+    WaitZero,
+    InvalidDragMode,
+    SpriteSheetNotPng(SmolStr),
+    InvalidSpriteSheet(SmolStr),
+    DuplicateCostume(SmolStr),
+    InvalidListFile(SmolStr),
+    InvalidRawJson(SmolStr),
+    InvalidCloudVariable(SmolStr),
+    InvalidScratchName(SmolStr),
+    CloudVariableNotNumber(SmolStr),
+    VariableTypeMismatch { name: SmolStr, ty: VarType },
+    ConstantVariable(SmolStr),
+    SharedGlobal { name: SmolStr, writers: Vec<SmolStr> },
+    DynamicBroadcast,
+    SpriteSheetTooSmall(usize),
+    ListIndexOutOfBounds { index: i64, len: usize },
+    NotInLoop,
+    BlockNotOnStage(Block),
+    ChainedComparison,
+    EmptyLoop,
+    EmptyProcedure(SmolStr),
+    PayloadRace { message: SmolStr, receivers: usize },
+    ClonesMultiply,
+    NestedForever { outer: Span },
+    UnclosedBracket(char),
+    UnopenedBracket(char),
+    MismatchedBracket { found: char, expected: char },
+}
+
+/// Names of the diagnostics which are lints, see [`DiagnosticDetail::lint`].
+pub const LINTS: &[&str] = &[
+    "unreachable_code",
+    "unused_variable",
+    "unused_procedure",
+    "unused_list",
+    "unused_argument",
+    "unused_enum_variant",
+    "argument_shadows_variable",
+    "assign_to_argument",
+    "expression_too_deep",
+    "wait_in_warp",
+    "wait_zero",
+    "payload_race",
+    "clones_multiply",
+    "nested_forever",
+    "chained_comparison",
+    "empty_loop",
+    "empty_procedure",
+    "constant_variable",
+    "shared_global",
+    "dynamic_broadcast",
+    "cloud_variable_not_number",
+    "variable_type_mismatch",
+    "list_index_out_of_bounds",
+    "empty_glob",
+    "indentation",
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
 }
 
 impl DiagnosticDetail {
@@ -52,18 +140,86 @@ impl DiagnosticDetail {
         Diagnostic { detail: self, span }
     }
 
+    /// Stable name of this diagnostic, if it is a lint whose level can be changed in the
+    /// `[lints]` table of `goboscript.toml`.
+    pub fn lint(&self) -> Option<&'static str> {
+        Some(match self {
+            Self::FollowedByUnreachableCode => "unreachable_code",
+            Self::UnusedVariable(_) => "unused_variable",
+            Self::UnusedProcedure(_) => "unused_procedure",
+            Self::UnusedList(_) => "unused_list",
+            Self::UnusedArgument(_) => "unused_argument",
+            Self::UnusedEnumVariant { .. } => "unused_enum_variant",
+            Self::ArgumentShadowsVariable(_) => "argument_shadows_variable",
+            Self::AssignToArgument(_) => "assign_to_argument",
+            Self::ExpressionTooDeep { .. } => "expression_too_deep",
+            Self::WaitInWarp(_) => "wait_in_warp",
+            Self::WaitZero => "wait_zero",
+            Self::PayloadRace { .. } => "payload_race",
+            Self::ClonesMultiply => "clones_multiply",
+            Self::NestedForever { .. } => "nested_forever",
+            Self::ChainedComparison => "chained_comparison",
+            Self::EmptyLoop => "empty_loop",
+            Self::EmptyProcedure(_) => "empty_procedure",
+            Self::ConstantVariable(_) => "constant_variable",
+            Self::SharedGlobal { .. } => "shared_global",
+            Self::DynamicBroadcast => "dynamic_broadcast",
+            Self::CloudVariableNotNumber(_) => "cloud_variable_not_number",
+            Self::VariableTypeMismatch { .. } => "variable_type_mismatch",
+            Self::ListIndexOutOfBounds { .. } => "list_index_out_of_bounds",
+            Self::EmptyGlob(_) => "empty_glob",
+            Self::WrongIndentation(_) => "indentation",
+            _ => return None,
+        })
+    }
+
+    pub fn level(&self) -> Level {
+        match self {
+            Self::ArgumentShadowsVariable(_)
+            | Self::AssignToArgument(_)
+            | Self::ExpressionTooDeep { .. }
+            | Self::WaitInWarp(_)
+            | Self::WaitZero
+            | Self::PayloadRace { .. }
+            | Self::ClonesMultiply
+            | Self::NestedForever { .. }
+            | Self::ChainedComparison
+            | Self::EmptyLoop
+            | Self::EmptyProcedure(_)
+            | Self::ConstantVariable(_)
+            | Self::SharedGlobal { .. }
+            | Self::DynamicBroadcast
+            | Self::CloudVariableNotNumber(_)
+            | Self::VariableTypeMismatch { .. }
+            | Self::ListIndexOutOfBounds { .. }
+            | Self::EmptyGlob(_)
+            | Self::WrongIndentation(_) => Level::Warning,
+            _ => Level::Error,
+        }
+    }
+
     fn message(&self, sprite: &Sprite) -> &'static str {
         match self {
             Self::InvalidToken => "invalid token",
             Self::UnrecognizedEof(_) => "unrecognized end of file",
+            Self::UnrecognizedToken(Token::Assign, expected)
+                if expected.iter().any(|it| it == r#""==""#) =>
+            {
+                "`=` is assignment, not comparison"
+            }
             Self::UnrecognizedToken(_, _) => "unrecognized token",
             Self::ExtraToken(_) => "extra token",
             Self::FileNotFound(_) => "file not found",
+            Self::EmptyGlob(_) => "pattern matches no files",
+            Self::WrongIndentation(_) => "line is indented with the wrong character",
             Self::FollowedByUnreachableCode => "this is followed by unreachable code",
             Self::UnrecognizedReporter(_) => "unrecognized reporter",
             Self::UnrecognizedVariable(_) => "unrecognized variable",
+            Self::UndeclaredVariable(_) => "variable is not declared",
+            Self::UnreceivedBroadcast(_) => "no script receives this message",
             Self::UnrecognizedProcedure(_) => "unrecognized block or procedure",
             Self::UnrecognizedList(_) => "unrecognized list",
+            Self::UnrecognizedSprite(_) => "unrecognized sprite",
             Self::UnrecognizedKey(_) => "unrecognized key",
             Self::UnrecognizedArgument { .. } => "unrecognized argument",
             Self::UnrecognizedEnum { .. } => "unrecognized enum",
@@ -88,13 +244,81 @@ impl DiagnosticDetail {
                 }
             }
             Self::ProcArgsCountMismatch { proc, given } => {
-                match given.cmp(&sprite.procs[proc].args.len()) {
-                    Ordering::Less => "too few arguments for procedure",
-                    Ordering::Greater => "too many arguments for procedure",
-                    Ordering::Equal => unreachable!(),
+                let overloads = sprite.overloads(proc);
+                if overloads.iter().all(|proc| *given < proc.args.len()) {
+                    "too few arguments for procedure"
+                } else if overloads.iter().all(|proc| *given > proc.args.len()) {
+                    "too many arguments for procedure"
+                } else {
+                    "wrong number of arguments for procedure"
                 }
             }
+            Self::DuplicateProcedure { .. } => "procedure is already declared",
             Self::NoCostumes => "no costumes declared",
+            Self::DuplicateArgument(_) => "duplicate argument",
+            Self::ArgumentShadowsVariable(_) => "argument shadows a global variable",
+            Self::AssignToArgument(_) => "assignment to a procedure argument",
+            Self::InvalidColor(_) => "invalid color",
+            Self::ExpressionTooDeep { .. } => "expression is nested too deeply",
+            Self::RecursiveInline(_) => "inline procedure calls itself",
+            Self::InlineWarp(_) => {
+                "inline procedure would lose its screen refresh mode"
+            }
+            Self::InlineCapture { .. } => {
+                "local variable hides a variable of an inline procedure"
+            }
+            Self::RecursiveMacro(_) => "macro uses itself",
+            Self::DuplicateMacro(_) => "macro is already declared",
+            Self::MacroShadowsReporter(_) => "macro has the name of a reporter",
+            Self::MacroShadowsBlock(_) => "macro has the name of a block",
+            Self::MacroArgsCountMismatch { expected, given, .. } => {
+                if given < expected {
+                    "too few arguments for macro"
+                } else {
+                    "too many arguments for macro"
+                }
+            }
+            Self::MacroArgumentNotVariable { .. } => "macro argument is not a variable",
+            Self::StatementMacroInExpression(_) => {
+                "statement macro used as an expression"
+            }
+            Self::ExpressionMacroAsStatement(_) => {
+                "expression macro used as a statement"
+            }
+            Self::InvalidVersion(_) => "invalid version",
+            Self::IncompatibleVersion(_) => "incompatible goboscript version",
+            Self::UnclosedBracket(_) => "unclosed bracket",
+            Self::UnopenedBracket(_) => "unexpected closing bracket",
+            Self::MismatchedBracket { .. } => "mismatched brackets",
+            Self::InvalidDragMode => "invalid drag mode",
+            Self::SpriteSheetNotPng(_) => "sprite sheet is not a PNG image",
+            Self::InvalidSpriteSheet(_) => "invalid sprite sheet",
+            Self::DuplicateCostume(_) => "costume name is already used",
+            Self::InvalidListFile(_) => "invalid list file",
+            Self::InvalidRawJson(_) => "invalid JSON fragment",
+            Self::InvalidCloudVariable(_) => "invalid cloud variable",
+            Self::InvalidScratchName(_) => "name is not valid in Scratch",
+            Self::CloudVariableNotNumber(_) => "cloud variables can only hold numbers",
+            Self::VariableTypeMismatch { .. } => "value does not match variable type",
+            Self::ConstantVariable(_) => "variable is never changed",
+            Self::SharedGlobal { .. } => "global variable is set by several sprites",
+            Self::DynamicBroadcast => "broadcast message is computed",
+            Self::SpriteSheetTooSmall(_) => "sprite sheet is too small",
+            Self::ListIndexOutOfBounds { .. } => "list index is out of bounds",
+            Self::NotInLoop => "not inside a loop",
+            Self::BlockNotOnStage(_) => "block cannot be used in the stage",
+            Self::ChainedComparison => "comparisons cannot be chained",
+            Self::EmptyLoop => "loop has an empty body",
+            Self::EmptyProcedure(_) => "procedure has an empty body",
+            Self::PayloadRace { .. } => "receivers may not see this payload",
+            Self::ClonesMultiply => "every clone will clone itself too",
+            Self::NestedForever { .. } => {
+                "`forever` loop inside another `forever` loop"
+            }
+            Self::WaitInWarp(_) => {
+                "waiting in a procedure which runs without screen refresh"
+            }
+            Self::WaitZero => "`wait 0` waits for the next frame",
         }
     }
 
@@ -134,25 +358,63 @@ impl DiagnosticDetail {
                 }
                 Some(format!("this reporter takes {}", repr.args().join(", ")))
             }
-            Self::ProcArgsCountMismatch { proc, given: _ } => Some(format!(
-                "this procedure takes {}",
-                sprite.procs[proc]
-                    .args
-                    .iter()
-                    .map(|(name, _)| name)
-                    .cloned()
-                    .collect::<Vec<_>>()
-                    .join(", ")
+            Self::ProcArgsCountMismatch { proc, given: _ } => {
+                let overloads = sprite.overloads(proc);
+                if let [proc] = overloads.as_slice() {
+                    return Some(format!(
+                        "this procedure takes {}",
+                        proc.args
+                            .iter()
+                            .map(|(name, _)| name)
+                            .cloned()
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ));
+                }
+                Some(format!(
+                    "`{proc}` can be called with:\n - {}",
+                    overloads
+                        .iter()
+                        .map(|proc| {
+                            let args: Vec<_> =
+                                proc.args.iter().map(|(name, _)| name.as_str()).collect();
+                            format!("{} {}", proc.name, args.join(", ")).trim_end().to_string()
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n - ")
+                ))
+            }
+            Self::DuplicateProcedure { name, args } => Some(format!(
+                "`{name}` is already declared with {args} argument{}, procedures with \
+                 the same name must take different numbers of arguments",
+                if *args == 1 { "" } else { "s" }
             )),
+            Self::UnrecognizedToken(Token::Assign, expected)
+                if expected.iter().any(|it| it == r#""==""#) =>
+            {
+                Some("use `==` to compare values".to_string())
+            }
+            Self::UnrecognizedToken(found, expected) => {
+                Some(format!("{}, but found `{found}`", expected_tokens(expected)?))
+            }
+            Self::UnrecognizedEof(expected) => {
+                Some(format!("{}, but the file ended", expected_tokens(expected)?))
+            }
             Self::UnrecognizedReporter(name) => {
-                get_closest_match(name, Repr::all_names().iter().copied())
+                get_closest_match(name, Repr::all_names().iter().copied().chain(["number", "text"]))
             }
             Self::UnrecognizedProcedure(name) => get_closest_match(
                 name,
                 Block::all_names()
                     .iter()
                     .copied()
-                    .chain(sprite.procs.keys().map(SmolStr::as_str)),
+                    .chain(
+                        sprite
+                            .procs
+                            .keys()
+                            .map(|(name, _)| name.as_str())
+                            .filter(|name| !is_on_messages_proc(name)),
+                    ),
             ),
             Self::UnrecognizedArgument { name, proc } => {
                 let proc = sprite.procs.get(proc.as_ref()?)?;
@@ -165,12 +427,191 @@ impl DiagnosticDetail {
                 get_closest_match(name, sprite.lists.keys().map(SmolStr::as_str))
             }
             Self::UnrecognizedKey(name) => get_closest_match(name, all_keys()),
+            Self::UndeclaredVariable(name) => Some(format!(
+                "strict mode requires variables to be declared, add `var {name};` \
+                 outside of any script"
+            )),
+            Self::UnreceivedBroadcast(message) => Some(format!(
+                "strict mode requires every message to be received, add an \
+                 `on \"{message}\"` script or remove this broadcast"
+            )),
+            Self::DuplicateArgument(name) => {
+                Some(format!("`{name}` is declared more than once"))
+            }
+            Self::ArgumentShadowsVariable(name) => {
+                Some(format!("consider renaming the argument `{name}`"))
+            }
+            Self::AssignToArgument(name) => Some(format!(
+                "arguments cannot be changed, this sets a variable named `{name}` instead. \
+                 to change it, copy it to a local variable with `local {name} = ${name};`"
+            )),
+            Self::InvalidColor(value) => Some(format!(
+                "`{value}` is not an opaque color, use a hex color such as \"#ff0000\""
+            )),
+            Self::ExpressionTooDeep { depth, max } => Some(format!(
+                "{depth} nested reporters, the limit is {max}. consider storing parts of \
+                 this expression in variables"
+            )),
+            Self::RecursiveInline(name) => Some(format!(
+                "`{name}` cannot be inlined into itself, remove `inline` from it"
+            )),
+            Self::InlineWarp(name) => Some(format!(
+                "the loops of `{name}` would refresh the screen here, declare it with \
+                 `nowarp inline proc` or call it from a procedure"
+            )),
+            Self::InlineCapture { name, proc } => Some(format!(
+                "`{proc}` uses the sprite's `{name}`, but here `{name}` is a local \
+                 variable, rename the local variable"
+            )),
+            Self::RecursiveMacro(name) => Some(format!(
+                "`{name}` would expand forever, a macro cannot use itself"
+            )),
+            Self::DuplicateMacro(name) => Some(format!("`{name}` is declared more than once")),
+            Self::MacroShadowsReporter(name) => Some(format!(
+                "`{name}` with this many arguments is a reporter, which would be used \
+                 instead of the macro, rename the macro"
+            )),
+            Self::MacroShadowsBlock(name) => Some(format!(
+                "`{name}` with this many arguments is a block, which would be used \
+                 instead of the macro, rename the macro"
+            )),
+            Self::MacroArgsCountMismatch { name, expected, .. } => Some(format!(
+                "`{name}` takes {expected} argument{}",
+                if *expected == 1 { "" } else { "s" }
+            )),
+            Self::MacroArgumentNotVariable { name, param } => Some(format!(
+                "`{name}` assigns to `{param}`, so it must be given a variable"
+            )),
+            Self::StatementMacroInExpression(name) => Some(format!(
+                "`{name}` has statements, use it as a statement, `{name} ...;`"
+            )),
+            Self::ExpressionMacroAsStatement(name) => Some(format!(
+                "`{name}` is an expression, use it where a value is expected"
+            )),
+            Self::InvalidVersion(version) => Some(format!(
+                "`{version}` is not a version, use a version such as `1.0`"
+            )),
+            Self::IncompatibleVersion(version) => Some(format!(
+                "requires goboscript {version}, but this is goboscript {}",
+                env!("CARGO_PKG_VERSION")
+            )),
+            Self::ListIndexOutOfBounds { index, len } => Some(format!(
+                "this list starts with {len} items, item {index} will be empty unless \
+                 items are added first"
+            )),
+            Self::EmptyGlob(pattern) => Some(format!(
+                "no costumes are added, check that `{pattern}` is relative to the \
+                 project directory"
+            )),
+            Self::WrongIndentation(indentation) => {
+                Some(format!("`indent_style` in `[lints]` requires {indentation}"))
+            }
+            Self::SpriteSheetNotPng(path) => {
+                Some(format!("`{path}` cannot be cut into frames, use a PNG image"))
+            }
+            Self::InvalidSpriteSheet(reason)
+            | Self::InvalidListFile(reason)
+            | Self::InvalidRawJson(reason)
+            | Self::InvalidCloudVariable(reason)
+            | Self::InvalidScratchName(reason) => {
+                Some(reason.to_string())
+            }
+            Self::DuplicateCostume(name) => Some(format!(
+                "a frame of this sprite sheet is named `{name}`, like another costume of \
+                 this sprite, rename the file or the other costume"
+            )),
+            Self::SpriteSheetTooSmall(fits) => Some(format!(
+                "only {fits} frames of this size fit in the sprite sheet"
+            )),
+            Self::PayloadRace { message, receivers } => Some(format!(
+                "`{message}` has {receivers} receivers which share one payload, use \
+                 `broadcast_and_wait` so that it is not changed before they all read it"
+            )),
+            Self::ClonesMultiply => Some(
+                "clones also run this script, so each clone makes another clone. clone \
+                 from a script which only the original runs, or check a variable which \
+                 `onclone` sets"
+                    .to_string(),
+            ),
+            Self::NestedForever { .. } => Some(
+                "this loop never ends, so the outer loop never repeats".to_string(),
+            ),
+            Self::EmptyLoop => {
+                Some("this loop does nothing, add statements to it or remove it".to_string())
+            }
+            Self::DynamicBroadcast => Some(
+                "this message is only known when the project runs, so it cannot be \
+                 checked. make sure an `on` script exists for every message it can be"
+                    .to_string(),
+            ),
+            Self::CloudVariableNotNumber(name) => Some(format!(
+                "`{name}` is a cloud variable, Scratch does not save values which are not \
+                 numbers in it"
+            )),
+            Self::VariableTypeMismatch { name, ty } => {
+                Some(format!("`{name}` is declared as `{ty}`"))
+            }
+            Self::SharedGlobal { name, writers } => {
+                let (last, rest) = writers.split_last().unwrap();
+                let rest: Vec<_> = rest.iter().map(|it| format!("`{it}`")).collect();
+                Some(format!(
+                    "`{name}` is set by {} and `{last}`, so one of them may change it \
+                     while another is using it",
+                    rest.join(", ")
+                ))
+            }
+            Self::ConstantVariable(name) => Some(format!(
+                "this is the only time `{name}` is set, consider using the value \
+                 directly, or an enum, instead of a variable"
+            )),
+            Self::EmptyProcedure(name) => Some(format!(
+                "calling `{name}` does nothing, add statements to it or remove it"
+            )),
+            Self::ChainedComparison => Some(
+                "`a < x < b` compares the result of `a < x`, which is true or false, \
+                 with `b`, use `a < x and x < b`"
+                    .to_string(),
+            ),
+            Self::BlockNotOnStage(block) => Some(format!(
+                "the stage is always shown, `{}` only works in sprites",
+                block.name()
+            )),
+            Self::NotInLoop => Some(
+                "`break` and `continue` can only be used inside `repeat`, `until`, \
+                 `forever`, `for` and `loop`"
+                    .to_string(),
+            ),
+            Self::InvalidDragMode => {
+                Some(r#"use "draggable" or "not draggable""#.to_string())
+            }
+            Self::FollowedByUnreachableCode => {
+                Some("the statements after this never run, remove them".to_string())
+            }
+            Self::UnclosedBracket(closer) => {
+                Some(format!("this is never closed, add a `{closer}`"))
+            }
+            Self::UnopenedBracket(found) => {
+                Some(format!("this `{found}` does not close anything"))
+            }
+            Self::MismatchedBracket { found, expected } => {
+                Some(format!("expected `{expected}`, found `{found}`"))
+            }
+            Self::WaitInWarp(proc) => Some(format!(
+                "this will freeze the project while it waits, declare `{proc}` with \
+                 `nowarp proc` instead"
+            )),
+            Self::WaitZero => Some("write `yield;` to make this clear".to_string()),
             Self::UnrecognizedEnumVariant { enum_name, variant_name } => {
                 let enum_ = sprite.enums.get(enum_name)?;
-                get_closest_match(
-                    variant_name,
-                    enum_.variants.iter().map(|(variant, _)| variant.as_str()),
-                )
+                let variants = enum_.variants.iter().map(|(variant, _, _)| variant.as_str());
+                let valid = format!(
+                    "`{enum_name}` has the variants {}",
+                    variants.clone().collect::<Vec<_>>().join(", ")
+                );
+                match get_closest_match(variant_name, variants) {
+                    Some(closest) => Some(format!("{closest}\n{valid}")),
+                    None => Some(valid),
+                }
             }
             _ => None,
         }
@@ -179,26 +620,46 @@ impl DiagnosticDetail {
     fn info(&self) -> Option<String> {
         None
     }
+
+    /// Another span which explains this diagnostic, with a label for it.
+    fn related(&self) -> Option<(&Span, &'static str)> {
+        match self {
+            Self::NestedForever { outer } => Some((outer, "the outer loop")),
+            _ => None,
+        }
+    }
 }
 
 impl Diagnostic {
+    /// Position of the start of this diagnostic as `path:line:column`, which editors
+    /// can jump to.
+    pub fn location(&self, path: &str, src: &str) -> String {
+        let (line_no, col_no) = line_col(src, self.span.start);
+        format!("{path}:{}:{}", line_no + 1, col_no + 1)
+    }
+
     pub fn eprint(&self, path: &str, src: &str, sprite: &Sprite) {
-        let mut line_no = 0;
-        let mut col_no = 0;
-        let mut i = 0;
-        for (line_no1, line) in src.lines().enumerate() {
-            if i <= self.span.start && self.span.end <= (i + line.len()) {
-                line_no = line_no1;
-                col_no = self.span.start - i;
-                break;
-            }
-            i += line.len() + 1;
-        }
+        self.eprint_as(self.detail.level(), path, src, sprite);
+    }
+
+    /// Prints the diagnostic at `level`, which may differ from its own if it is a lint
+    /// whose level was changed.
+    pub fn eprint_as(&self, level: Level, path: &str, src: &str, sprite: &Sprite) {
+        let (line_no, col_no) = line_col(src, self.span.start);
+        let level = match level {
+            Level::Error => "error".red().bold(),
+            Level::Warning => "warning".yellow().bold(),
+        };
+        let lint = match self.detail.lint() {
+            Some(lint) => format!("[{lint}]").bold(),
+            None => "".normal(),
+        };
         eprintln!(
-            "{}{} {}",
-            "error".red().bold(),
+            "{}{}{} {}",
+            level,
+            lint,
             ":".bold(),
-            self.detail.message(sprite).bold(),
+            self.detail.message(sprite).bold()
         );
         if self.span == (0..0) {
             eprintln!(
@@ -212,6 +673,8 @@ impl Diagnostic {
         }
         let help = self.detail.help(sprite);
         let line = src.lines().nth(line_no).unwrap();
+        // Spans over multiple lines are only underlined on the first line.
+        let len = self.span.len().min(line.len() - col_no);
         eprintln!(
             "      {} {}:{}:{}",
             "╭→".bold(),
@@ -222,23 +685,87 @@ impl Diagnostic {
         eprintln!("{}", "      │".bold());
         eprintln!("{} {}", format!(" {:4} │", line_no + 1).bold(), line);
         let pad = " ".repeat(col_no);
-        let padn = " ".repeat(self.span.len());
+        let padn = " ".repeat(len);
         eprintln!(
             "{} {}{} {}",
             "      │".bold(),
             pad,
-            "─".repeat(self.span.len()).bold().red(),
+            "─".repeat(len).bold().red(),
             help.unwrap_or_default()
                 .replace('\n', &format!("\n         {pad}{padn}"))
                 .bold()
                 .green(),
         );
+        if let Some((span, label)) = self.detail.related() {
+            let (line_no, col_no) = line_col(src, span.start);
+            let line = src.lines().nth(line_no).unwrap();
+            let len = span.len().min(line.len() - col_no);
+            eprintln!("{}", "      │".bold());
+            eprintln!("{} {}", format!(" {:4} │", line_no + 1).bold(), line);
+            eprintln!(
+                "{} {}{} {}",
+                "      │".bold(),
+                " ".repeat(col_no),
+                "─".repeat(len).bold().blue(),
+                label.bold().blue(),
+            );
+        }
         if let Some(info) = self.detail.info() {
             eprintln!("{}", info.magenta());
         }
     }
 }
 
+/// Zero-based line and column of a byte offset in `src`.
+fn line_col(src: &str, offset: usize) -> (usize, usize) {
+    let mut i = 0;
+    for (line_no, line) in src.lines().enumerate() {
+        if i <= offset && offset <= i + line.len() {
+            return (line_no, offset - i);
+        }
+        i += line.len() + 1;
+    }
+    (0, 0)
+}
+
+/// Expected tokens are listed at most this many at a time.
+const MAX_EXPECTED: usize = 8;
+const CLOSERS: &[&str] = &["`;`", "`)`", "`]`", "`}`"];
+
+/// Describes the terminals the parser expected, using the text of keywords and symbols
+/// rather than their names in the grammar.
+fn expected_tokens(expected: &[String]) -> Option<String> {
+    let mut names: Vec<String> = vec![];
+    for terminal in expected {
+        let name = match terminal.as_str() {
+            "NAME" => "a name".to_string(),
+            "ARG" => "an argument".to_string(),
+            "MAC" => "a macro".to_string(),
+            "BIN" | "OCT" | "INT" | "HEX" | "FLOAT" => "a number".to_string(),
+            "STR" => "a string".to_string(),
+            terminal => {
+                match terminal.strip_prefix('"').and_then(|it| it.strip_suffix('"')) {
+                    Some(symbol) => format!("`{symbol}`"),
+                    None => format!("`{}`", terminal.to_lowercase()),
+                }
+            }
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    // Closing punctuation is usually what is missing, so it is listed first.
+    names.sort_by_key(|name| !CLOSERS.contains(&name.as_str()));
+    match names.as_slice() {
+        [] => None,
+        [name] => Some(format!("expected {name}")),
+        _ if names.len() > MAX_EXPECTED => {
+            Some(format!("expected one of {}, ...", names[..MAX_EXPECTED].join(", ")))
+        }
+        _ => Some(format!("expected one of {}", names.join(", "))),
+    }
+}
+
 fn get_closest_match<'a, T>(pattern: &str, choices: T) -> Option<String>
 where T: Iterator<Item = &'a str> {
     let matcher = SkimMatcherV2::default();
@@ -1,17 +1,82 @@
 pub mod build;
+pub mod check;
+pub mod diff;
+pub mod glob;
+pub mod graph;
+pub mod indentation;
+pub mod list_file;
 pub mod new;
+pub mod symbols;
 
 use anyhow::Result;
-use clap::{CommandFactory, Parser};
+use build::BuildOptions;
+use clap::CommandFactory;
 
 use crate::{
-    cli::{Cli, Commands},
+    cli::{Cli, Commands, Emit},
     config::Config,
 };
 
-pub fn frontend() -> Result<()> {
-    match Cli::parse().command {
-        Commands::Build { input, output } => build::build(input, output),
+pub fn frontend(cli: Cli) -> Result<()> {
+    match cli.command {
+        Commands::Build {
+            input,
+            single,
+            output,
+            target,
+            timings,
+            emit,
+            indent,
+            max_errors,
+            manifest,
+            debug_overlay,
+            release,
+            warn_constants,
+            warn_shared_globals,
+            strict,
+            keep_going,
+            compact,
+            package,
+        } => build::build(BuildOptions {
+            input,
+            single,
+            output,
+            target,
+            timings,
+            emit,
+            indent,
+            max_errors,
+            manifest,
+            debug_overlay,
+            release,
+            warn_constants,
+            warn_shared_globals,
+            strict,
+            keep_going,
+            compact,
+            package,
+            ..Default::default()
+        }),
+        Commands::Lint { input, target, max_errors, deny_warnings, strict } => {
+            build::build(BuildOptions {
+                input,
+                target,
+                emit: Emit::Lint,
+                max_errors,
+                warn_constants: true,
+                warn_shared_globals: true,
+                deny_warnings,
+                strict,
+                keep_going: true,
+                ..Default::default()
+            })
+        }
+        Commands::Graph { input, output } => build::build(BuildOptions {
+            input,
+            output: Some(output.unwrap_or_else(|| "-".into())),
+            emit: Emit::Graph,
+            ..Default::default()
+        }),
         Commands::New {
             name,
             frame_rate,
@@ -22,6 +87,7 @@ pub fn frontend() -> Result<()> {
             high_quality_pen,
             stage_width,
             stage_height,
+            strict,
         } => new::new(
             name,
             Config {
@@ -33,8 +99,13 @@ pub fn frontend() -> Result<()> {
                 high_quality_pen: high_quality_pen.then_some(true),
                 stage_width,
                 stage_height,
+                strict: strict.then_some(true),
+                ..Default::default()
             },
         ),
+        Commands::NewSprite { name, input } => new::new_sprite(input, name),
+        Commands::Check { file, deep } => check::check(file, deep),
+        Commands::Diff { old, new } => diff::diff(old, new),
         Commands::Completions { shell } => {
             shell.generate(&mut Cli::command(), &mut std::io::stdout());
             Ok(())
@@ -1,31 +1,163 @@
 use std::{
     env,
+    fmt::Display,
     fs::{self, read_dir, File},
-    io::{self, BufWriter},
-    path::PathBuf,
+    io::{self, BufWriter, Cursor, Seek, SeekFrom, Write},
+    iter,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{bail, Result};
+use colored::Colorize;
 use fxhash::FxHashMap;
+use serde::Serialize;
+use serde_json::{ser::PrettyFormatter, Serializer};
 use smol_str::SmolStr;
 
 use crate::{
-    ast::{Project, Sprite},
+    ast::{
+        scratch_name_problem, Costume, Literal, Monitor, Project, Sprite,
+        BLANK_COSTUME, DEBUG_FPS, RESERVED_SPRITE_NAMES,
+    },
+    cli::{Emit, Package},
     codegen::Sb3,
-    config::Config,
+    config::{Config, LintLevel, Target, Value},
     custom_toml_error::CustomTOMLError,
-    diagnostic::Diagnostic,
+    diagnostic::{Diagnostic, DiagnosticDetail, Level, LINTS},
+    frontend::{glob, graph, indentation, list_file, symbols},
     parser::parse,
-    visitors::{pass1, pass2},
+    visitors::{
+        constants, counter, defer, globals, inline, loops, measure, pass1, pass2,
+        AstPass,
+    },
 };
 
-pub fn build(input: Option<PathBuf>, output: Option<PathBuf>) -> Result<()> {
-    let input = if let Some(input) = input { input } else { env::current_dir()? };
-    let canonical_input = input.canonicalize()?;
+const THUMBNAIL_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif"];
+/// Number of largest assets listed when the project is larger than `max_size_mb`.
+const LARGEST_ASSETS: usize = 5;
+/// Longest instructions and credits the Scratch website accepts.
+const MAX_NOTES: usize = 5000;
+
+/// What to build and how, set from the options of `goboscript build`, `goboscript lint`
+/// and `goboscript graph`.
+pub struct BuildOptions {
+    /// Project directory, `GOBOSCRIPT_INPUT` or the current directory if not given.
+    pub input: Option<PathBuf>,
+    /// A single `.gs` file built as a project with one sprite.
+    pub single: Option<PathBuf>,
+    /// Output file, `GOBOSCRIPT_OUTPUT` or named after the project if not given.
+    pub output: Option<PathBuf>,
+    pub target: Target,
+    pub timings: bool,
+    pub emit: Emit,
+    /// Spaces to indent `project.json` by with `Emit::ProjectJson`.
+    pub indent: usize,
+    pub max_errors: usize,
+    pub manifest: bool,
+    pub debug_overlay: bool,
+    pub release: bool,
+    pub warn_constants: bool,
+    pub warn_shared_globals: bool,
+    /// Fail if there are any warnings.
+    pub deny_warnings: bool,
+    pub strict: bool,
+    /// Leave out sprites with syntax errors instead of stopping at the first one.
+    pub keep_going: bool,
+    pub compact: bool,
+    pub package: Option<Package>,
+    /// Passes of crates which use goboscript as a library, run in this order.
+    pub passes: Vec<Box<dyn AstPass>>,
+}
+
+/// The defaults of `goboscript build`.
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self {
+            input: None,
+            single: None,
+            output: None,
+            target: Target::default(),
+            timings: false,
+            emit: Emit::default(),
+            indent: 2,
+            max_errors: 20,
+            manifest: false,
+            debug_overlay: false,
+            release: false,
+            warn_constants: false,
+            warn_shared_globals: false,
+            deny_warnings: false,
+            strict: false,
+            keep_going: false,
+            compact: false,
+            package: None,
+            passes: vec![],
+        }
+    }
+}
+
+pub fn build(options: BuildOptions) -> Result<()> {
+    let BuildOptions {
+        input,
+        single,
+        output,
+        target,
+        timings,
+        emit,
+        indent,
+        max_errors,
+        manifest,
+        debug_overlay,
+        release,
+        warn_constants,
+        warn_shared_globals,
+        deny_warnings,
+        strict,
+        keep_going,
+        compact,
+        package,
+        passes,
+    } = options;
+    let input = input.or_else(|| env::var_os("GOBOSCRIPT_INPUT").map(PathBuf::from));
+    let output = output.or_else(|| env::var_os("GOBOSCRIPT_OUTPUT").map(PathBuf::from));
+    // A single file is built from the directory it is in, so that the paths of its
+    // costumes are relative to it.
+    let input = match (&single, input) {
+        (Some(single), _) => match single.parent() {
+            Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        },
+        (None, Some(input)) => input,
+        (None, None) => env::current_dir()?,
+    };
+    if single.as_ref().is_some_and(|single| !single.is_file()) {
+        bail!("`{}` not found", single.unwrap().display())
+    }
+    let canonical_input = match &single {
+        Some(single) => single.canonicalize()?.with_extension(""),
+        None => input.canonicalize()?,
+    };
     let project_name = canonical_input.file_name().unwrap().to_str().unwrap();
-    let output = output.unwrap_or_else(|| input.join(format!("{project_name}.sb3")));
+    let extension = if emit == Emit::ProjectJson { "project.json" } else { "sb3" };
+    let output =
+        output.unwrap_or_else(|| input.join(format!("{project_name}.{extension}")));
+    // `-` writes the project to standard output, so the summary goes to standard error.
+    let to_stdout = output == Path::new("-");
+    if to_stdout && manifest {
+        bail!("--manifest needs an output file, it cannot be used with `--output -`")
+    }
+    if emit == Emit::ProjectJson && manifest {
+        bail!("--manifest describes a `.sb3` file, it cannot be used with `--emit project-json`")
+    }
+    if package.is_some() && (to_stdout || emit != Emit::Sb3) {
+        bail!("--package needs a `.sb3` output file, it cannot be used with `--output -` or `--emit`")
+    }
     let config_path = input.join("goboscript.toml");
-    let config = if let Ok(config_src) = fs::read_to_string(&config_path) {
+    let mut config = if single.is_some() {
+        Default::default()
+    } else if let Ok(config_src) = fs::read_to_string(&config_path) {
         match toml::from_str::<Config>(&config_src) {
             Ok(config) => config,
             Err(err) => {
@@ -36,8 +168,44 @@ pub fn build(input: Option<PathBuf>, output: Option<PathBuf>) -> Result<()> {
     } else {
         Default::default()
     };
+    if strict {
+        config.strict = Some(true);
+    }
+    if compact {
+        config.compact = Some(true);
+    }
+    for (field, text) in
+        [("instructions", &config.instructions), ("credits", &config.credits)]
+    {
+        if text.as_ref().is_some_and(|text| text.chars().count() > MAX_NOTES) {
+            bail!("{field} can be at most {MAX_NOTES} characters long")
+        }
+    }
+    if let Some(lint) =
+        config.lints.levels.keys().find(|lint| !LINTS.contains(&lint.as_str()))
+    {
+        bail!("lints: `{lint}` is not a lint")
+    }
+    let thumbnail = config.thumbnail.as_ref().and_then(|thumbnail| {
+        let path = input.join(thumbnail);
+        let extension = path.extension().and_then(|it| it.to_str()).unwrap_or_default();
+        if !THUMBNAIL_EXTENSIONS.contains(&extension.to_lowercase().as_str()) {
+            eprint_warning(&format!(
+                "thumbnail `{thumbnail}` is not a PNG, JPEG or GIF image, skipping"
+            ));
+            return None;
+        }
+        if !path.is_file() {
+            eprint_warning(&format!("thumbnail `{thumbnail}` not found, skipping"));
+            return None;
+        }
+        Some(path)
+    });
+    let begin = Instant::now();
     let stage_path = input.join("stage.gs");
     let stage_src = match fs::read_to_string(&stage_path) {
+        // The Stage of a single file is empty.
+        _ if single.is_some() => String::new(),
         Ok(src) => src,
         Err(err) => {
             if matches!(err.kind(), io::ErrorKind::NotFound) {
@@ -46,59 +214,684 @@ pub fn build(input: Option<PathBuf>, output: Option<PathBuf>) -> Result<()> {
             bail!(err)
         }
     };
-    let stage = match parse(&stage_src) {
+    let mut stage = match parse(&stage_src) {
         Ok(stage) => stage,
         Err(diag) => {
-            diag.eprint(stage_path.to_str().unwrap(), &stage_src, &Default::default());
-            bail!("cannot continue due to syntax errors")
+            let path = stage_path.to_str().unwrap();
+            diag.eprint(path, &stage_src, &Default::default());
+            bail!(
+                "cannot continue due to syntax errors, first at {}",
+                diag.location(path, &stage_src)
+            )
         }
     };
+    if let Some(diag) = check_version(&stage) {
+        diag.eprint(stage_path.to_str().unwrap(), &stage_src, &stage);
+        bail!("cannot continue due to incompatible goboscript version")
+    }
     let mut sprites: FxHashMap<SmolStr, Sprite> = Default::default();
     let mut stage_diags: Vec<Diagnostic> = Default::default();
     let mut srcs: FxHashMap<SmolStr, (PathBuf, String)> = Default::default();
     let mut diags: FxHashMap<SmolStr, Vec<Diagnostic>> = Default::default();
-    for path in read_dir(&input)?.flatten().map(|entry| entry.path()) {
-        if !(path.extension() == Some("gs".as_ref())
-            && path.file_stem() != Some("stage".as_ref())
-            && path.is_file())
+    // With `--keep-going`, sprites with syntax errors are left out, so that the rest of
+    // the project is still compiled.
+    let mut omitted: Vec<SmolStr> = vec![];
+    let paths: Vec<_> = match &single {
+        Some(single) => vec![single.clone()],
+        None => read_dir(&input)?.flatten().map(|entry| entry.path()).collect(),
+    };
+    for path in paths {
+        if single.is_none()
+            && !(path.extension() == Some("gs".as_ref())
+                && path.file_stem() != Some("stage".as_ref())
+                && path.is_file())
         {
             continue;
         }
+        let name = SmolStr::from(path.file_stem().unwrap().to_str().unwrap());
+        if name.eq_ignore_ascii_case("stage") {
+            bail!("sprite `{name}` would have the same name as the Stage, rename it")
+        }
+        if RESERVED_SPRITE_NAMES.contains(&name.as_str()) {
+            bail!("sprite `{name}` has a name which Scratch uses in menus, rename it")
+        }
+        if let Some(problem) = scratch_name_problem(&name) {
+            bail!("`{name}` cannot be a sprite name, as {problem}, rename it")
+        }
+        if let Some(other) = sprites.keys().find(|it| it.eq_ignore_ascii_case(&name)) {
+            bail!(
+                "sprites `{other}` and `{name}` would have the same name, sprite names \
+                 are not case-sensitive"
+            )
+        }
         let src = fs::read_to_string(&path)?;
         let sprite = match parse(&src) {
             Ok(sprite) => sprite,
             Err(diag) => {
-                diag.eprint(path.to_str().unwrap(), &src, &Default::default());
-                bail!("cannot continue due to syntax errors")
+                let path = path.to_str().unwrap();
+                diag.eprint(path, &src, &Default::default());
+                if !keep_going {
+                    bail!(
+                        "cannot continue due to syntax errors, first at {}",
+                        diag.location(path, &src)
+                    )
+                }
+                omitted.push(name);
+                continue;
             }
         };
-        let name = SmolStr::from(path.file_stem().unwrap().to_str().unwrap());
+        if let Some(diag) = check_version(&sprite) {
+            diag.eprint(path.to_str().unwrap(), &src, &sprite);
+            bail!("cannot continue due to incompatible goboscript version")
+        }
         sprites.insert(name.clone(), sprite);
         srcs.insert(name.clone(), (path, src));
         diags.insert(name.clone(), Default::default());
     }
+    if let Some(indentation) = config.lints.indent_style {
+        indentation::check_indentation(&stage_src, indentation, &mut stage_diags);
+        for (name, (_, src)) in &srcs {
+            indentation::check_indentation(
+                src,
+                indentation,
+                diags.get_mut(name).unwrap(),
+            );
+        }
+    }
+    if single.is_some() {
+        for sprite in iter::once(&mut stage).chain(sprites.values_mut()) {
+            if sprite.costumes.is_empty() {
+                let blank =
+                    Costume::new(BLANK_COSTUME.into(), 0..0, Some("blank".into()));
+                sprite.costumes.push(blank);
+            }
+        }
+    }
+    glob::expand_costumes(&mut stage, &input, &mut stage_diags);
+    list_file::load_lists(&mut stage, &input, &mut stage_diags);
+    for (name, sprite) in &mut sprites {
+        glob::expand_costumes(sprite, &input, diags.get_mut(name).unwrap());
+        list_file::load_lists(sprite, &input, diags.get_mut(name).unwrap());
+    }
+    omitted.sort();
+    let parse_time = begin.elapsed();
+    let begin = Instant::now();
+    let is_sprite =
+        |name: &str| sprites.contains_key(name) || omitted.iter().any(|it| it == name);
+    if let Some(selected) = &config.selected_sprite {
+        if !is_sprite(selected) {
+            bail!("selected_sprite `{selected}` is not a sprite in this project")
+        }
+    }
+    for draggable in &config.draggable_sprites {
+        if !is_sprite(draggable) {
+            bail!("draggable_sprites: `{draggable}` is not a sprite in this project")
+        }
+    }
+    for hidden in &config.hidden_sprites {
+        if !is_sprite(hidden) {
+            bail!("hidden_sprites: `{hidden}` is not a sprite in this project")
+        }
+    }
+    for (i, name) in config.startup_order.iter().enumerate() {
+        if !is_sprite(name) {
+            bail!("startup_order: `{name}` is not a sprite in this project")
+        }
+        if config.startup_order[..i].contains(name) {
+            bail!("startup_order: `{name}` is listed more than once")
+        }
+    }
+    if config.max_size_mb.is_some_and(|max| max <= 0.0) {
+        bail!("max_size_mb must be greater than 0")
+    }
+    if config.tempo.is_some_and(|tempo| tempo <= 0.0) {
+        bail!("tempo must be greater than 0")
+    }
+    for (name, volume) in &config.volume {
+        if !is_sprite(name) {
+            bail!("volume: `{name}` is not a sprite in this project")
+        }
+        if !(0.0..=100.0).contains(volume) {
+            bail!("volume of `{name}` must be between 0 and 100")
+        }
+    }
+    if let Some(backdrop) = &config.initial_backdrop {
+        if !stage.costumes.iter().any(|costume| costume.name == backdrop) {
+            bail!("initial_backdrop `{backdrop}` is not a backdrop of the stage")
+        }
+    }
+    for (name, costume) in &config.initial_costumes {
+        let Some(sprite) = sprites.get(name.as_str()) else {
+            if is_sprite(name) {
+                continue;
+            }
+            bail!("initial_costumes: `{name}` is not a sprite in this project")
+        };
+        if !sprite.costumes.iter().any(|it| it.name == costume) {
+            bail!("initial_costumes: `{costume}` is not a costume of `{name}`")
+        }
+    }
     let mut project = Project::new(stage, sprites);
-    pass1::visit_project(&mut project);
+    project.constants = build_constants(project_name, &config)?;
+    set_initial_values(&mut project, &config, &omitted)?;
+    let clone_counter = config.clone_counter.unwrap_or(false);
+    if clone_counter {
+        project.add_clone_counter();
+    }
+    measure::visit_project(&mut project, release);
+    defer::visit_project(&mut project);
+    if emit == Emit::Graph {
+        let dot = graph::graph(&project);
+        if to_stdout {
+            io::stdout().write_all(dot.as_bytes())?;
+        } else {
+            fs::write(&output, dot)?;
+        }
+        return check_omitted(&omitted);
+    }
+    if debug_overlay {
+        let monitors = debug_monitors(&mut project, &config, &omitted)?;
+        project.add_debug_overlay(monitors);
+    }
+    loops::visit_project(&mut project);
+    if target == Target::Turbowarp && config.native_counter.unwrap_or(false) {
+        let shown: &[String] =
+            if debug_overlay { &config.debug_variables } else { &[] };
+        counter::visit_project(&mut project, shown);
+    }
+    for pass in &passes {
+        pass.run(&mut project, &mut stage_diags, &mut diags);
+    }
+    pass1::visit_project(&mut project, target, clone_counter);
+    inline::visit_project(&mut project, &mut stage_diags, &mut diags);
     pass2::visit_project(&mut project);
-    let mut sb3 = Sb3::new(BufWriter::new(File::create(output)?));
-    sb3.package(&project, &config, &input, &mut stage_diags, &mut diags)?;
+    if warn_constants {
+        constants::visit_project(&project, &mut stage_diags, &mut diags);
+    }
+    if warn_shared_globals {
+        globals::visit_project(&project, &mut stage_diags);
+    }
+    let analysis_time = begin.elapsed();
+    if emit == Emit::Symbols {
+        symbols::print_symbols(&project);
+        return check_omitted(&omitted);
+    }
+    let outputs = Outputs {
+        input: &input,
+        thumbnail: thumbnail.as_deref(),
+        manifest: manifest.then_some(output.as_path()),
+    };
+    // The `.sb3` file the project is written to before it is moved over the output.
+    let mut partial = None;
+    let (codegen_time, write_time) = if emit == Emit::Lint {
+        let begin = Instant::now();
+        let mut sb3 = Sb3::<Cursor<Vec<u8>>>::new_project_json();
+        sb3.project(&project, &config, &input, &mut stage_diags, &mut diags)?;
+        (begin.elapsed(), Duration::ZERO)
+    } else if emit == Emit::ProjectJson {
+        let begin = Instant::now();
+        let mut sb3 = Sb3::<Cursor<Vec<u8>>>::new_project_json();
+        sb3.project(&project, &config, &input, &mut stage_diags, &mut diags)?;
+        let codegen_time = begin.elapsed();
+        let begin = Instant::now();
+        let json = indent_json(sb3.into_project_json(), indent)?;
+        if to_stdout {
+            io::stdout().write_all(&json)?;
+        } else {
+            fs::write(&output, json)?;
+        }
+        (codegen_time, begin.elapsed())
+    } else if to_stdout {
+        let (file, codegen_time, write_time) = write_sb3(
+            Cursor::new(vec![]),
+            &project,
+            &config,
+            outputs,
+            &mut stage_diags,
+            &mut diags,
+        )?;
+        io::stdout().write_all(file.get_ref())?;
+        (codegen_time, write_time)
+    } else {
+        // The project is written next to the output and moved over it once the build
+        // has succeeded, so that a failed build, such as one with errors or over
+        // `max_size_mb`, does not replace the output with a partial or invalid `.sb3`.
+        let path = partial.insert(output.clone().into_os_string());
+        path.push(".partial");
+        let written = write_sb3(
+            BufWriter::new(File::create(&*path)?),
+            &project,
+            &config,
+            outputs,
+            &mut stage_diags,
+            &mut diags,
+        )
+        .and_then(|(mut file, codegen_time, write_time)| {
+            file.flush()?;
+            Ok((codegen_time, write_time))
+        });
+        match written {
+            Ok(times) => times,
+            Err(err) => {
+                // The error is what failed the build, not the cleanup.
+                let _ = fs::remove_file(&*path);
+                return Err(err);
+            }
+        }
+    };
+    stage_diags.retain(|diag| !caused_by_omitted(&diag.detail, &omitted, true));
+    for diags in diags.values_mut() {
+        diags.retain(|diag| !caused_by_omitted(&diag.detail, &omitted, false));
+    }
     let mut errors = 0;
-    for diag in stage_diags {
-        diag.eprint(stage_path.to_str().unwrap(), &stage_src, &project.stage);
-        errors += 1;
+    let mut warnings = 0;
+    let mut first_error = None;
+    let stage_diags = stage_diags
+        .into_iter()
+        .map(|diag| (diag, &stage_path, stage_src.as_str(), &project.stage));
+    let mut diags: Vec<_> = diags.into_iter().collect();
+    diags.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let sprite_diags = diags.into_iter().flat_map(|(name, diags)| {
+        let (path, src) = &srcs[&name];
+        let sprite = &project.sprites[&name];
+        diags.into_iter().map(move |diag| (diag, path, src.as_str(), sprite))
+    });
+    for (diag, path, src, sprite) in stage_diags.chain(sprite_diags) {
+        let level =
+            match diag.detail.lint().and_then(|lint| config.lints.levels.get(lint)) {
+                Some(LintLevel::Allow) => continue,
+                Some(LintLevel::Warn) => Level::Warning,
+                Some(LintLevel::Deny) => Level::Error,
+                // Strict mode makes warnings errors, unless their lint level is set.
+                None if config.strict == Some(true) => Level::Error,
+                None => diag.detail.level(),
+            };
+        match level {
+            Level::Error => {
+                errors += 1;
+                if first_error.is_none() {
+                    first_error = Some(diag.location(path.to_str().unwrap(), src));
+                }
+                if errors > max_errors {
+                    continue;
+                }
+            }
+            Level::Warning => warnings += 1,
+        }
+        diag.eprint_as(level, path.to_str().unwrap(), src, sprite);
+    }
+    if errors > max_errors {
+        eprintln!("{}", format!("... and {} more errors", errors - max_errors).bold());
+    }
+    if warnings == 1 {
+        print_summary(to_stdout, "one warning generated".bold().yellow());
+    }
+    if warnings > 1 {
+        print_summary(
+            to_stdout,
+            format!("{warnings} warnings generated").bold().yellow(),
+        );
+    }
+    if timings {
+        for (phase, time) in [
+            ("parse", parse_time),
+            ("analysis", analysis_time),
+            ("codegen", codegen_time),
+            ("write", write_time),
+        ] {
+            print_summary(
+                to_stdout,
+                format!("{:>10} {}", phase.bold(), format_duration(time)),
+            );
+        }
+    }
+    if let Some(first_error) = first_error {
+        if let Some(partial) = &partial {
+            let _ = fs::remove_file(partial);
+        }
+        if errors == 1 {
+            bail!("one error generated, at {first_error}")
+        }
+        bail!("{errors} errors generated, first at {first_error}")
+    }
+    if let Some(partial) = &partial {
+        fs::rename(partial, &output)?;
+    }
+    check_omitted(&omitted)?;
+    if deny_warnings && warnings > 0 {
+        bail!("warnings are denied by --deny-warnings")
     }
-    for (name, diags) in diags {
-        for diag in diags {
-            let (path, src) = &srcs[&name];
-            diag.eprint(path.to_str().unwrap(), src, &project.sprites[&name]);
-            errors += 1;
+    if let Some(package) = package {
+        run_packager(&config, &output, package)?;
+    }
+    Ok(())
+}
+
+/// Runs the `packager` command from `goboscript.toml` with the path of the `.sb3` and
+/// the path of the file to package it into, next to the `.sb3`.
+fn run_packager(config: &Config, sb3: &Path, package: Package) -> Result<()> {
+    let Some((program, args)) = config.packager.split_first() else {
+        bail!(
+            "--package needs a packager, such as the TurboWarp packager, set `packager` in \
+             goboscript.toml to the command which runs it"
+        )
+    };
+    let extension = match package {
+        Package::Html => "html",
+    };
+    let packaged = sb3.with_extension(extension);
+    let status = match Command::new(program).args(args).arg(sb3).arg(&packaged).status()
+    {
+        Ok(status) => status,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            bail!("packager `{program}` not found, is it installed?")
+        }
+        Err(err) => bail!("cannot run packager `{program}`: {err}"),
+    };
+    if !status.success() {
+        bail!("packager `{program}` failed with {status}")
+    }
+    if !packaged.is_file() {
+        bail!("packager `{program}` did not write {}", packaged.display())
+    }
+    Ok(())
+}
+
+/// Fails the build if any sprites were left out because of syntax errors, after the
+/// rest of the project was compiled.
+fn check_omitted(omitted: &[SmolStr]) -> Result<()> {
+    let names: Vec<_> = omitted.iter().map(|name| format!("`{name}`")).collect();
+    match names.as_slice() {
+        [] => Ok(()),
+        [name] => bail!(
+            "sprite {name} was left out due to syntax errors, the rest of the project was \
+             compiled"
+        ),
+        _ => bail!(
+            "sprites {} were left out due to syntax errors, the rest of the project was \
+             compiled",
+            names.join(", ")
+        ),
+    }
+}
+
+/// Whether a diagnostic may only be there because sprites were left out: a reference to
+/// one of them, a message which no script left receives, or a variable or list of the
+/// Stage which no script left uses.
+fn caused_by_omitted(
+    detail: &DiagnosticDetail,
+    omitted: &[SmolStr],
+    stage: bool,
+) -> bool {
+    match detail {
+        _ if omitted.is_empty() => false,
+        DiagnosticDetail::UnrecognizedSprite(name) => omitted.contains(name),
+        DiagnosticDetail::UnreceivedBroadcast(_) => true,
+        DiagnosticDetail::UnusedVariable(_) | DiagnosticDetail::UnusedList(_) => stage,
+        _ => false,
+    }
+}
+
+/// Where the files other than the project itself are read from and written to.
+#[derive(Copy, Clone)]
+struct Outputs<'a> {
+    input: &'a Path,
+    thumbnail: Option<&'a Path>,
+    /// Path of the `.sb3`, if a manifest should be written next to it.
+    manifest: Option<&'a Path>,
+}
+
+/// Writes the project to `file`, returns it with how long codegen and writing took.
+fn write_sb3<T: Write + Seek>(
+    file: T,
+    project: &Project,
+    config: &Config,
+    outputs: Outputs,
+    stage_diags: &mut Vec<Diagnostic>,
+    diags: &mut FxHashMap<SmolStr, Vec<Diagnostic>>,
+) -> Result<(T, Duration, Duration)> {
+    let begin = Instant::now();
+    let mut sb3 = Sb3::new(file);
+    sb3.project(project, config, outputs.input, stage_diags, diags)?;
+    let codegen_time = begin.elapsed();
+    let begin = Instant::now();
+    let mut file = sb3.finish(outputs.input, outputs.thumbnail)?;
+    if let Some(max) = config.max_size_mb {
+        let size = file.seek(SeekFrom::End(0))?;
+        if size as f64 > max * MB {
+            let assets: Vec<_> = sb3
+                .largest_assets(LARGEST_ASSETS)
+                .into_iter()
+                .map(|(path, size)| format!("\n    {:>10}  {path}", format_size(size)))
+                .collect();
+            bail!(
+                "the project is {}, more than max_size_mb = {max}, its largest assets \
+                 are:{}",
+                format_size(size),
+                assets.join("")
+            )
         }
     }
-    if errors == 1 {
-        bail!("one error generated")
+    if let Some(output) = outputs.manifest {
+        fs::write(
+            output.with_extension("manifest.json"),
+            serde_json::to_string_pretty(&sb3.manifest(output))?,
+        )?;
     }
-    if errors > 1 {
-        bail!("{errors} errors generated")
+    Ok((file, codegen_time, begin.elapsed()))
+}
+
+/// Bytes in a megabyte, as used by `max_size_mb`.
+const MB: f64 = 1024.0 * 1024.0;
+
+fn format_size(bytes: u64) -> String {
+    if bytes as f64 >= MB {
+        format!("{:.2} MB", bytes as f64 / MB)
+    } else {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    }
+}
+
+/// Indents `project.json` by `indent` spaces, so that it can be read and compared line by
+/// line. It is left as it is if `indent` is 0, or if errors left it incomplete.
+fn indent_json(json: Vec<u8>, indent: usize) -> Result<Vec<u8>> {
+    if indent == 0 {
+        return Ok(json);
+    }
+    let Ok(value) = serde_json::from_slice::<serde_json::Value>(&json) else {
+        return Ok(json);
+    };
+    let indent = " ".repeat(indent);
+    let mut indented = vec![];
+    let formatter = PrettyFormatter::with_indent(indent.as_bytes());
+    value.serialize(&mut Serializer::with_formatter(&mut indented, formatter))?;
+    indented.push(b'\n');
+    Ok(indented)
+}
+
+/// Prints a line of the build summary to standard output, or to standard error when
+/// the project itself is written to standard output.
+pub fn print_summary(to_stderr: bool, line: impl Display) {
+    if to_stderr {
+        eprintln!("{line}");
+    } else {
+        println!("{line}");
+    }
+}
+
+/// Sets the initial values of variables and lists from `goboscript.toml`. Names of the
+/// form `sprite::name` refer to a sprite's own variables or lists, other names refer to
+/// those for all sprites.
+fn set_initial_values(
+    project: &mut Project,
+    config: &Config,
+    omitted: &[SmolStr],
+) -> Result<()> {
+    for (key, value) in &config.variables {
+        if in_omitted_sprite(key, omitted) {
+            continue;
+        }
+        let (sprite, name) = find_sprite(project, key)?;
+        let Some(var) = sprite.vars.get_mut(&name) else {
+            bail!("`{key}` in goboscript.toml: variable `{name}` not found")
+        };
+        if var.cloud
+            && matches!(value, Value::Str(value) if value.parse::<f64>().is_err())
+        {
+            bail!("`{key}` in goboscript.toml: cloud variable `{name}` can only hold numbers")
+        }
+        var.default = literal(value);
+    }
+    for (key, values) in &config.lists {
+        if in_omitted_sprite(key, omitted) {
+            continue;
+        }
+        let (sprite, name) = find_sprite(project, key)?;
+        let Some(list) = sprite.lists.get_mut(&name) else {
+            bail!("`{key}` in goboscript.toml: list `{name}` not found")
+        };
+        list.default = values.iter().map(literal).collect();
     }
     Ok(())
 }
+
+/// Values of the names which the compiler replaces with a string. The build date can be
+/// fixed with `SOURCE_DATE_EPOCH`, for reproducible builds.
+fn build_constants(
+    project_name: &str,
+    config: &Config,
+) -> Result<FxHashMap<SmolStr, SmolStr>> {
+    let seconds = match env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => match epoch.parse() {
+            Ok(seconds) => seconds,
+            Err(err) => bail!(
+                "SOURCE_DATE_EPOCH must be a number of seconds since 1970, but it is \
+                 `{epoch}`: {err}"
+            ),
+        },
+        Err(_) => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    };
+    let mut constants = FxHashMap::default();
+    constants.insert("__PROJECT_NAME".into(), project_name.into());
+    constants.insert("__BUILD_DATE".into(), format_date(seconds / 86400).into());
+    if let Some(version) = &config.version {
+        constants.insert("__VERSION".into(), version.into());
+    }
+    Ok(constants)
+}
+
+/// Formats days since 1970-01-01 as `YYYY-MM-DD`, see
+/// <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn format_date(days: u64) -> String {
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as u64;
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+fn debug_monitors(
+    project: &mut Project,
+    config: &Config,
+    omitted: &[SmolStr],
+) -> Result<Vec<Monitor>> {
+    let owners = iter::once(("the Stage".to_string(), &project.stage)).chain(
+        project.sprites.iter().map(|(name, sprite)| (format!("`{name}`"), sprite)),
+    );
+    for (owner, sprite) in owners {
+        if sprite.vars.contains_key(DEBUG_FPS) {
+            bail!(
+                "--debug-overlay adds a global variable `{DEBUG_FPS}`, but {owner} already \
+                 has a variable `{DEBUG_FPS}`, rename it"
+            )
+        }
+    }
+    let mut monitors = vec![];
+    for key in &config.debug_variables {
+        if in_omitted_sprite(key, omitted) {
+            continue;
+        }
+        let (sprite, name) = find_sprite(project, key)?;
+        if !sprite.vars.contains_key(&name) {
+            bail!("`{key}` in goboscript.toml: variable `{name}` not found")
+        }
+        let sprite = key.split_once("::").map(|(sprite, _)| sprite.into());
+        monitors.push(Monitor { sprite, name });
+    }
+    Ok(monitors)
+}
+
+/// Whether `key` is of the form `sprite::name`, for a sprite which was left out.
+fn in_omitted_sprite(key: &str, omitted: &[SmolStr]) -> bool {
+    key.split_once("::")
+        .is_some_and(|(sprite, _)| omitted.iter().any(|it| it == sprite))
+}
+
+fn find_sprite<'a>(
+    project: &'a mut Project,
+    key: &str,
+) -> Result<(&'a mut Sprite, SmolStr)> {
+    let Some((sprite_name, name)) = key.split_once("::") else {
+        return Ok((&mut project.stage, key.into()));
+    };
+    match project.sprites.get_mut(sprite_name) {
+        Some(sprite) => Ok((sprite, name.into())),
+        None => bail!("`{key}` in goboscript.toml: sprite `{sprite_name}` not found"),
+    }
+}
+
+fn literal(value: &Value) -> Literal {
+    match value {
+        Value::Int(value) => Literal::Int(*value),
+        Value::Float(value) => Literal::Float(*value),
+        Value::Str(value) => Literal::Str(value.into()),
+    }
+}
+
+/// Checks the version required by a sprite's `#!goboscript` pragma against the version
+/// of this compiler. Sprites without a pragma are always accepted.
+fn check_version(sprite: &Sprite) -> Option<Diagnostic> {
+    let (version, span) = sprite.version.as_ref()?;
+    let Some(required) = parse_version(version) else {
+        return Some(
+            DiagnosticDetail::InvalidVersion(version.clone())
+                .to_diagnostic(span.clone()),
+        );
+    };
+    let current = parse_version(env!("CARGO_PKG_VERSION")).unwrap();
+    if required > current {
+        return Some(
+            DiagnosticDetail::IncompatibleVersion(version.clone())
+                .to_diagnostic(span.clone()),
+        );
+    }
+    None
+}
+
+/// Parses a version such as `1.0` into `[1, 0, 0]`.
+fn parse_version(version: &str) -> Option<[u64; 3]> {
+    let mut parts = [0; 3];
+    let mut components = version.split('.');
+    for part in &mut parts {
+        let Some(component) = components.next() else { break };
+        *part = component.parse().ok()?;
+    }
+    if components.next().is_some() || version.is_empty() {
+        return None;
+    }
+    Some(parts)
+}
+
+/// Formats a duration as milliseconds with one decimal place.
+pub fn format_duration(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn eprint_warning(message: &str) {
+    eprintln!("{}{} {}", "warning".yellow().bold(), ":".bold(), message.bold());
+}
@@ -0,0 +1,151 @@
+use std::{
+    fs,
+    io::{Cursor, Read},
+    path::PathBuf,
+};
+
+use anyhow::{bail, Result};
+use colored::Colorize;
+use serde_json::{Map, Value};
+use zip::ZipArchive;
+
+const TARGET_FIELDS: &[&str] =
+    &["isStage", "name", "blocks", "costumes", "variables", "lists", "sounds"];
+
+pub fn check(file: PathBuf, deep: bool) -> Result<()> {
+    let sb3 = fs::read(&file)?;
+    let problems = validate(&sb3, deep);
+    for problem in &problems {
+        eprintln!("{}{} {}", "problem".bold().red(), ":".bold(), problem);
+    }
+    if !problems.is_empty() {
+        bail!("{} is not a valid project", file.display());
+    }
+    Ok(())
+}
+
+/// Reads back a `.sb3` file and checks that Scratch could load it: `project.json`
+/// parses, every target has the fields Scratch requires, and every costume and sound is
+/// in the archive. `deep` also checks that every block reference resolves, and that
+/// each block's `parent` is the block which references it through `next` or an input.
+/// Returns the problems found, an empty list means the project is valid.
+pub fn validate(sb3: &[u8], deep: bool) -> Vec<String> {
+    let mut problems = vec![];
+    let (mut archive, project) = match open(sb3) {
+        Ok(opened) => opened,
+        Err(problem) => return vec![problem],
+    };
+    let Some(targets) = project.get("targets").and_then(Value::as_array) else {
+        return vec!["project.json has no `targets` list".to_string()];
+    };
+    if project.get("meta").is_none() {
+        problems.push("project.json has no `meta`".to_string());
+    }
+    let stages = targets.iter().filter(|target| target["isStage"] == true).count();
+    if stages != 1 {
+        problems.push(format!("expected exactly one stage, found {stages}"));
+    }
+    for (i, target) in targets.iter().enumerate() {
+        let name =
+            target["name"].as_str().map(str::to_string).unwrap_or(format!("#{i}"));
+        for field in TARGET_FIELDS {
+            if target.get(field).is_none() {
+                problems.push(format!("target `{name}` has no `{field}`"));
+            }
+        }
+        let costumes = target["costumes"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        if costumes.is_empty() {
+            problems.push(format!("target `{name}` has no costumes"));
+        }
+        let sounds = target["sounds"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        for asset in costumes.iter().chain(sounds) {
+            let md5ext = asset["md5ext"].as_str().unwrap_or_default();
+            if archive.by_name(md5ext).is_err() {
+                problems.push(format!("target `{name}` uses missing asset `{md5ext}`"));
+            }
+        }
+        if deep {
+            if let Some(blocks) = target["blocks"].as_object() {
+                validate_blocks(&name, blocks, &mut problems);
+            }
+        }
+    }
+    problems
+}
+
+type Archive<'a> = ZipArchive<Cursor<&'a [u8]>>;
+
+/// Opens a `.sb3` file, returning its archive and its parsed `project.json`.
+pub fn open(sb3: &[u8]) -> Result<(Archive<'_>, Value), String> {
+    let mut archive = match ZipArchive::new(Cursor::new(sb3)) {
+        Ok(archive) => archive,
+        Err(err) => return Err(format!("not a zip archive: {err}")),
+    };
+    let mut project_json = String::new();
+    match archive.by_name("project.json") {
+        Ok(mut file) => {
+            if let Err(err) = file.read_to_string(&mut project_json) {
+                return Err(format!("cannot read project.json: {err}"));
+            }
+        }
+        Err(_) => return Err("missing project.json".to_string()),
+    }
+    match serde_json::from_str(&project_json) {
+        Ok(project) => Ok((archive, project)),
+        Err(err) => Err(format!("project.json is not valid JSON: {err}")),
+    }
+}
+
+fn validate_blocks(
+    target: &str,
+    blocks: &Map<String, Value>,
+    problems: &mut Vec<String>,
+) {
+    for (id, block) in blocks {
+        // Top-level reporters are stored as arrays, and cannot reference other blocks.
+        if !block.is_object() {
+            continue;
+        }
+        if let Some(parent) = block["parent"].as_str() {
+            match blocks.get(parent) {
+                None => problems.push(format!(
+                    "target `{target}`: block `{id}` has missing parent `{parent}`"
+                )),
+                Some(parent_block) if !children(parent_block).any(|child| child == id) => {
+                    problems.push(format!(
+                        "target `{target}`: block `{id}` has parent `{parent}`, which does \
+                         not reference it"
+                    ))
+                }
+                Some(_) => {}
+            }
+        } else if block["topLevel"] != true {
+            problems.push(format!(
+                "target `{target}`: block `{id}` has no parent and is not top-level"
+            ));
+        }
+        for child in children(block) {
+            match blocks.get(child) {
+                None => problems.push(format!(
+                    "target `{target}`: block `{id}` references missing block `{child}`"
+                )),
+                Some(child_block) if child_block["parent"] != id.as_str() => {
+                    problems.push(format!(
+                        "target `{target}`: block `{id}` references `{child}`, whose parent \
+                         is not `{id}`"
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+/// The blocks referenced by a block, through `next` and its inputs.
+fn children(block: &Value) -> impl Iterator<Item = &str> {
+    let inputs = block["inputs"].as_object().into_iter().flat_map(Map::values);
+    let input_blocks = inputs
+        .filter_map(Value::as_array)
+        .flat_map(|input| input.iter().skip(1).filter_map(Value::as_str));
+    block["next"].as_str().into_iter().chain(input_blocks)
+}
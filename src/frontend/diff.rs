@@ -0,0 +1,237 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use serde_json::{Map, Value};
+
+use super::check::open;
+
+/// A script, as one line of text for each block in its stacks. Reporters are written
+/// in the line of the block they are in, and the stacks of C blocks are indented.
+type Script = Vec<String>;
+
+/// Prints the scripts which were added, removed or changed in each sprite from `old` to
+/// `new`. Scripts are compared by their text, which leaves out the IDs of blocks,
+/// variables and arguments, so that building the same code twice shows no changes. A
+/// script which is not in `old` is changed if `old` has a script with the same hat
+/// block, and is printed with the lines which changed.
+pub fn diff(old: PathBuf, new: PathBuf) -> Result<()> {
+    let old_targets = read_targets(&old)?;
+    let new_targets = read_targets(&new)?;
+    let mut changed = false;
+    for (name, _) in &old_targets {
+        if !new_targets.iter().any(|(new_name, _)| new_name == name) {
+            println!("{} {}", format!("{name}:").bold(), "removed sprite".red());
+            changed = true;
+        }
+    }
+    for (name, new_scripts) in &new_targets {
+        match old_targets.iter().find(|(old_name, _)| old_name == name) {
+            Some((_, old_scripts)) => {
+                changed |= diff_scripts(name, old_scripts, new_scripts);
+            }
+            None => {
+                println!("{} {}", format!("{name}:").bold(), "added sprite".green());
+                changed = true;
+            }
+        }
+    }
+    if !changed {
+        println!("no changes");
+    }
+    Ok(())
+}
+
+/// The scripts of each target, sorted, in the order of the targets.
+fn read_targets(file: &Path) -> Result<Vec<(String, Vec<Script>)>> {
+    let sb3 = fs::read(file)?;
+    let (_, project) =
+        open(&sb3).map_err(|problem| anyhow!("{}: {problem}", file.display()))?;
+    let Some(targets) = project["targets"].as_array() else {
+        return Err(anyhow!("{}: project.json has no `targets` list", file.display()));
+    };
+    let empty = Map::new();
+    Ok(targets
+        .iter()
+        .map(|target| {
+            let name = target["name"].as_str().unwrap_or_default().to_string();
+            let blocks = target["blocks"].as_object().unwrap_or(&empty);
+            let mut scripts: Vec<Script> = blocks
+                .iter()
+                .filter(|(_, block)| block.is_array() || block["topLevel"] == true)
+                .map(|(id, _)| {
+                    let mut lines = vec![];
+                    stack(blocks, id, 0, &mut lines);
+                    lines
+                })
+                .collect();
+            scripts.sort();
+            (name, scripts)
+        })
+        .collect())
+}
+
+/// Prints the scripts of a sprite which are only in one of `old` and `new`, returns
+/// whether there were any.
+fn diff_scripts(sprite: &str, old: &[Script], new: &[Script]) -> bool {
+    let mut old: Vec<&Script> = old.iter().collect();
+    let mut new: Vec<&Script> = new.iter().collect();
+    new.retain(|script| match old.iter().position(|it| it == script) {
+        Some(i) => {
+            old.remove(i);
+            false
+        }
+        None => true,
+    });
+    let header = format!("{sprite}:").bold();
+    for script in &new {
+        match old.iter().position(|it| it.first() == script.first()) {
+            Some(i) => {
+                println!("{header} {}", "changed script".yellow());
+                for (change, line) in line_diff(old.remove(i), script) {
+                    match change {
+                        '-' => println!("{}", format!("- {line}").red()),
+                        '+' => println!("{}", format!("+ {line}").green()),
+                        _ => println!("  {line}"),
+                    }
+                }
+            }
+            None => {
+                println!("{header} {}", "added script".green());
+                for line in *script {
+                    println!("{}", format!("+ {line}").green());
+                }
+            }
+        }
+    }
+    for script in &old {
+        println!("{header} {}", "removed script".red());
+        for line in *script {
+            println!("{}", format!("- {line}").red());
+        }
+    }
+    !(old.is_empty() && new.is_empty())
+}
+
+/// The lines of `old` and `new`, marked with `-` if they are only in `old`, `+` if they
+/// are only in `new`, and ` ` if they are in both, keeping as many lines as possible.
+fn line_diff<'a>(old: &'a [String], new: &'a [String]) -> Vec<(char, &'a String)> {
+    // `common[i][j]` is the number of lines kept between `old[i..]` and `new[j..]`.
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', &old[i]));
+            i += 1;
+            j += 1;
+        } else if j == new.len()
+            || (i < old.len() && common[i + 1][j] >= common[i][j + 1])
+        {
+            lines.push(('-', &old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', &new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// Writes the blocks of the stack starting at `id`, and the stacks inside them.
+fn stack(blocks: &Map<String, Value>, id: &str, depth: usize, lines: &mut Vec<String>) {
+    let indent = "    ".repeat(depth);
+    let mut next = Some(id);
+    while let Some(id) = next {
+        let Some(block) = blocks.get(id) else {
+            lines.push(format!("{indent}(missing {id})"));
+            return;
+        };
+        if block.is_array() {
+            lines.push(format!("{indent}{}", primitive(block)));
+            return;
+        }
+        lines.push(format!("{indent}{}", text(blocks, block)));
+        for (name, input) in inputs(block) {
+            if let (true, Some(substack)) =
+                (name.starts_with("SUBSTACK"), input[1].as_str())
+            {
+                stack(blocks, substack, depth + 1, lines);
+            }
+        }
+        next = block["next"].as_str();
+    }
+}
+
+/// A block without its stacks: its opcode, procedure, fields and other inputs.
+fn text(blocks: &Map<String, Value>, block: &Value) -> String {
+    let mut text = block["opcode"].as_str().unwrap_or_default().to_string();
+    if let Some(proccode) = block["mutation"]["proccode"].as_str() {
+        text += &format!(" {proccode:?}");
+    }
+    for (name, field) in block["fields"].as_object().into_iter().flatten() {
+        text += &format!(" {name}={}", field[0]);
+    }
+    for (name, input) in inputs(block) {
+        if !name.starts_with("SUBSTACK") {
+            text += &format!(" {name}={}", value(blocks, &input[1]));
+        }
+    }
+    text
+}
+
+/// The inputs of a block, by name. The inputs of procedures are named by the IDs of
+/// their arguments, so they are numbered by the position of the argument instead.
+fn inputs(block: &Value) -> Vec<(String, &Value)> {
+    let inputs = block["inputs"].as_object();
+    let argument_ids: Option<Vec<String>> = block["mutation"]["argumentids"]
+        .as_str()
+        .and_then(|ids| serde_json::from_str(ids).ok());
+    match (inputs, argument_ids) {
+        (None, _) => vec![],
+        (Some(inputs), Some(ids)) => ids
+            .iter()
+            .enumerate()
+            .filter_map(|(i, id)| Some((format!("#{}", i + 1), inputs.get(id)?)))
+            .collect(),
+        (Some(inputs), None) => {
+            inputs.iter().map(|(name, input)| (name.clone(), input)).collect()
+        }
+    }
+}
+
+/// What an input holds: a reporter, or a literal, variable, list or broadcast.
+fn value(blocks: &Map<String, Value>, value: &Value) -> String {
+    match value {
+        Value::String(id) => match blocks.get(id) {
+            Some(block) if block.is_array() => primitive(block),
+            Some(block) => format!("({})", text(blocks, block)),
+            None => format!("(missing {id})"),
+        },
+        Value::Array(_) => primitive(value),
+        _ => "_".to_string(),
+    }
+}
+
+/// A literal, variable, list or broadcast, stored as an array of its type and value.
+fn primitive(primitive: &Value) -> String {
+    let name = primitive[1].as_str().unwrap_or_default();
+    match primitive[0].as_i64() {
+        Some(11) => format!("(broadcast {name})"),
+        Some(12) => format!("(variable {name})"),
+        Some(13) => format!("(list {name})"),
+        _ => primitive[1].to_string(),
+    }
+}
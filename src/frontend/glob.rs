@@ -0,0 +1,111 @@
+use std::{cmp::Ordering, fs::read_dir, path::Path};
+
+use smol_str::SmolStr;
+
+use crate::{
+    ast::{Costume, Sprite},
+    diagnostic::{Diagnostic, DiagnosticDetail},
+};
+
+/// Replaces each costume whose path has a `*` or `?` in its file name with a costume for
+/// every matching file, in natural order, so that `frame2.png` comes before
+/// `frame10.png`.
+pub fn expand_costumes(sprite: &mut Sprite, input: &Path, d: &mut Vec<Diagnostic>) {
+    if !sprite.costumes.iter().any(|costume| is_glob(&costume.path)) {
+        return;
+    }
+    let mut costumes = vec![];
+    for costume in std::mem::take(&mut sprite.costumes) {
+        if costume.frame.is_some() || !is_glob(&costume.path) {
+            costumes.push(costume);
+            continue;
+        }
+        let paths = glob(input, &costume.path);
+        if paths.is_empty() {
+            d.push(
+                DiagnosticDetail::EmptyGlob(costume.path.clone())
+                    .to_diagnostic(costume.span.clone()),
+            );
+        }
+        let stem =
+            Path::new(costume.path.as_str()).file_stem().unwrap().to_str().unwrap();
+        let alias = (costume.name != stem).then_some(costume.name);
+        costumes.extend(
+            paths
+                .into_iter()
+                .map(|path| Costume::new(path, costume.span.clone(), alias.clone())),
+        );
+    }
+    sprite.costumes = costumes;
+}
+
+fn is_glob(path: &str) -> bool {
+    path.contains(['*', '?'])
+}
+
+/// Paths of the files in the project directory which match `pattern`. Only the file
+/// name may contain wildcards, the directory is taken as it is.
+fn glob(input: &Path, pattern: &str) -> Vec<SmolStr> {
+    let (dir, name) = match pattern.rsplit_once('/') {
+        Some((dir, name)) => (Some(dir), name),
+        None => (None, pattern),
+    };
+    let Ok(entries) = read_dir(input.join(dir.unwrap_or("."))) else { return vec![] };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        // Hidden files are only matched by patterns which start with a dot.
+        .filter(|file| !file.starts_with('.') || name.starts_with('.'))
+        .filter(|file| matches(name.as_bytes(), file.as_bytes()))
+        .collect();
+    names.sort_by(|a, b| natural_cmp(a, b));
+    names
+        .into_iter()
+        .map(|file| match dir {
+            Some(dir) => format!("{dir}/{file}").into(),
+            None => file.into(),
+        })
+        .collect()
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any number of characters and
+/// `?` matches one.
+fn matches(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|i| matches(rest, &name[i..])),
+        Some((b'?', rest)) => {
+            // Skip one whole UTF-8 character.
+            let len = name.iter().skip(1).take_while(|&&b| b & 0xC0 == 0x80).count();
+            !name.is_empty() && matches(rest, &name[len + 1..])
+        }
+        Some((c, rest)) => name.first() == Some(c) && matches(rest, &name[1..]),
+    }
+}
+
+/// Compares runs of digits by their value and everything else character by character.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let a_end = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+            let b_end = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+            let x = a[..a_end].trim_start_matches('0');
+            let y = b[..b_end].trim_start_matches('0');
+            let ordering = x.len().cmp(&y.len()).then_with(|| x.cmp(y));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            (a, b) = (&a[a_end..], &b[b_end..]);
+        } else {
+            if x != y {
+                return x.cmp(&y);
+            }
+            (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::{collections::BTreeSet, fmt::Write};
+
+use smol_str::SmolStr;
+
+use crate::{
+    ast::{EventDetail, Expr, ProcKey, Project, Script, Sprite, Stmt, Stmts},
+    blocks::Block,
+};
+
+/// Returns a Graphviz graph of the scripts and procedures of each sprite, with an edge
+/// for each procedure call, from each script to the messages it broadcasts, and from
+/// each message to the scripts which receive it. Broadcasts of computed messages are
+/// left out, as they are only known when the project runs.
+pub fn graph(project: &Project) -> String {
+    let mut sprites = vec![("Stage", &project.stage)];
+    let mut names: Vec<_> = project.sprites.keys().collect();
+    names.sort();
+    sprites
+        .extend(names.into_iter().map(|name| (name.as_str(), &project.sprites[name])));
+    let mut dot = String::from("digraph project {\n    node [shape=box];\n");
+    let mut edges = BTreeSet::new();
+    let mut messages = BTreeSet::new();
+    for (name, sprite) in sprites {
+        writeln!(dot, "    subgraph {:?} {{", format!("cluster_{name}")).unwrap();
+        writeln!(dot, "        label={name:?};").unwrap();
+        let mut nodes = vec![];
+        for (i, script) in sprite.scripts().into_iter().enumerate() {
+            let (id, label) = match script {
+                Script::Event(event) => {
+                    (format!("{name}/script/{i}"), event_label(&event.kind))
+                }
+                Script::OnMessage(on_message) => {
+                    let id = format!("{name}/on/{}", on_message.message);
+                    edges.insert((message_id(&on_message.message), id.clone(), ""));
+                    messages.insert(on_message.message.clone());
+                    (id, format!("when I receive {:?}", on_message.message))
+                }
+            };
+            nodes.push((id, label, script.body()));
+        }
+        let mut procs: Vec<_> = sprite.procs.values().collect();
+        procs.sort_by_key(|proc| proc.key());
+        for proc in procs {
+            let label = sprite.proc_label(proc).to_string();
+            nodes.push((proc_id(name, &proc.key()), label, &proc.body));
+        }
+        for (id, label, body) in nodes {
+            writeln!(dot, "        {id:?} [label={label:?}];").unwrap();
+            let (calls, broadcasts) = visit_body(sprite, body);
+            for key in calls {
+                edges.insert((id.clone(), proc_id(name, &key), ""));
+            }
+            for message in broadcasts {
+                edges.insert((id.clone(), message_id(&message), " [style=dashed]"));
+                messages.insert(message);
+            }
+        }
+        dot.push_str("    }\n");
+    }
+    for message in messages {
+        let label = format!("{message:?}");
+        writeln!(
+            dot,
+            "    {:?} [label={label:?}, shape=ellipse];",
+            message_id(&message)
+        )
+        .unwrap();
+    }
+    for (from, to, style) in edges {
+        writeln!(dot, "    {from:?} -> {to:?}{style};").unwrap();
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn proc_id(sprite: &str, (name, args): &ProcKey) -> String {
+    format!("{sprite}/proc/{name}/{args}")
+}
+
+fn message_id(message: &str) -> String {
+    format!("message/{message}")
+}
+
+fn event_label(event: &EventDetail) -> String {
+    match event {
+        EventDetail::OnFlag { .. } => "when flag clicked".to_string(),
+        EventDetail::OnKey { key, .. } => format!("when {key:?} key pressed"),
+        EventDetail::OnClick => "when this sprite clicked".to_string(),
+        EventDetail::OnBackdrop { backdrop, .. } => {
+            format!("when backdrop switches to {backdrop:?}")
+        }
+        EventDetail::OnLoudnessGt { .. } => "when loudness >".to_string(),
+        EventDetail::OnTimerGt { .. } => "when timer >".to_string(),
+        EventDetail::OnClone => "when I start as a clone".to_string(),
+    }
+}
+
+/// The procedures called and the messages broadcast by a script or procedure.
+fn visit_body(sprite: &Sprite, body: &Stmts) -> (BTreeSet<ProcKey>, BTreeSet<SmolStr>) {
+    let mut found = (BTreeSet::new(), BTreeSet::new());
+    visit_stmts(sprite, body, &mut found);
+    found
+}
+
+fn visit_stmts(
+    sprite: &Sprite,
+    stmts: &Stmts,
+    found: &mut (BTreeSet<ProcKey>, BTreeSet<SmolStr>),
+) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Repeat { body, .. }
+            | Stmt::Forever { body, .. }
+            | Stmt::Until { body, .. }
+            | Stmt::Loop { body, .. }
+            | Stmt::ForEach { body, .. }
+            | Stmt::Measure { body, .. } => visit_stmts(sprite, body, found),
+            Stmt::Branch { if_body, else_body, .. } => {
+                visit_stmts(sprite, if_body, found);
+                visit_stmts(sprite, else_body, found);
+            }
+            Stmt::ProcCall { name, args, .. } => {
+                let key = (name.clone(), args.len());
+                if sprite.procs.contains_key(&key) {
+                    found.0.insert(key);
+                }
+            }
+            Stmt::Broadcast { message, .. } => {
+                found.1.insert(message.clone());
+            }
+            Stmt::Block {
+                block: Block::Broadcast | Block::BroadcastAndWait,
+                args,
+                ..
+            } => {
+                if let Some(message) = args.first() {
+                    if let Expr::Str(message) = &*message.borrow() {
+                        found.1.insert(message.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
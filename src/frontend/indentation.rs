@@ -0,0 +1,29 @@
+use crate::{
+    config::Indentation,
+    diagnostic::{Diagnostic, DiagnosticDetail},
+};
+
+/// Reports each line of `src` whose indentation contains the character which
+/// `indentation` does not allow. This only reads the source, it does not change it.
+pub fn check_indentation(src: &str, indentation: Indentation, d: &mut Vec<Diagnostic>) {
+    let wrong = match indentation {
+        Indentation::Spaces => '\t',
+        Indentation::Tabs => ' ',
+    };
+    let mut start = 0;
+    for line in src.split_inclusive('\n') {
+        let len = line.len() - line.trim_start_matches([' ', '\t']).len();
+        // Lines with only whitespace are not indented.
+        if line[len..].trim().is_empty() {
+            start += line.len();
+            continue;
+        }
+        if line[..len].contains(wrong) {
+            d.push(
+                DiagnosticDetail::WrongIndentation(indentation)
+                    .to_diagnostic(start..start + len),
+            );
+        }
+        start += line.len();
+    }
+}
@@ -0,0 +1,105 @@
+use std::{fs, io, path::Path};
+
+use serde_json::Value;
+use smol_str::SmolStr;
+
+use crate::{
+    ast::{Literal, Literals, Sprite},
+    diagnostic::{Diagnostic, DiagnosticDetail},
+};
+
+/// Reads the initial items of each list declared with `list name from "file";`. A
+/// `.json` file must hold an array of numbers, strings and booleans. Any other file is
+/// read as CSV, which gives an item for each field, row by row.
+pub fn load_lists(sprite: &mut Sprite, input: &Path, d: &mut Vec<Diagnostic>) {
+    for list in sprite.lists.values_mut() {
+        let Some(source) = &list.source else { continue };
+        let src = match fs::read_to_string(input.join(source.as_str())) {
+            Ok(src) => src,
+            Err(err) => {
+                let detail = if err.kind() == io::ErrorKind::NotFound {
+                    DiagnosticDetail::FileNotFound(source.clone())
+                } else {
+                    DiagnosticDetail::InvalidListFile(err.to_string().into())
+                };
+                d.push(detail.to_diagnostic(list.span.clone()));
+                continue;
+            }
+        };
+        let items = if source.to_lowercase().ends_with(".json") {
+            parse_json(&src)
+        } else {
+            Ok(parse_csv(&src))
+        };
+        match items {
+            Ok(items) => list.default = items,
+            Err(reason) => d.push(
+                DiagnosticDetail::InvalidListFile(
+                    format!("`{source}`: {reason}").into(),
+                )
+                .to_diagnostic(list.span.clone()),
+            ),
+        }
+    }
+}
+
+fn parse_json(src: &str) -> Result<Literals, String> {
+    let value: Value = serde_json::from_str(src).map_err(|err| err.to_string())?;
+    let Value::Array(items) = value else {
+        return Err("expected a JSON array".to_string());
+    };
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| match item {
+            Value::Number(number) => Ok(match number.as_i64() {
+                Some(int) => Literal::Int(int),
+                None => Literal::Float(number.as_f64().unwrap()),
+            }),
+            Value::String(string) => Ok(Literal::Str(string.into())),
+            Value::Bool(bool) => Ok(Literal::Str(bool.to_string().into())),
+            _ => Err(format!("item {} is not a number, string or boolean", i + 1)),
+        })
+        .collect()
+}
+
+/// Fields are separated by commas, and may be quoted with `"` to contain commas, line
+/// breaks, or `""` for a quote. Empty lines are skipped. Every field is a string, as
+/// Scratch treats strings which are numbers as numbers.
+fn parse_csv(src: &str) -> Literals {
+    let mut items = vec![];
+    let mut field = String::new();
+    let mut row_empty = true;
+    let mut quoted = false;
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => {
+                quoted = !quoted;
+                row_empty = false;
+            }
+            ',' if !quoted => {
+                items.push(Literal::Str(SmolStr::from(&field)));
+                field.clear();
+                row_empty = false;
+            }
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                if !row_empty || !field.is_empty() {
+                    items.push(Literal::Str(SmolStr::from(&field)));
+                }
+                field.clear();
+                row_empty = true;
+            }
+            c => field.push(c),
+        }
+    }
+    if !row_empty || !field.is_empty() {
+        items.push(Literal::Str(field.into()));
+    }
+    items
+}
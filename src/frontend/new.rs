@@ -6,7 +6,10 @@ use std::{
 
 use anyhow::{bail, Result};
 
-use crate::config::Config;
+use crate::{
+    ast::{scratch_name_problem, RESERVED_SPRITE_NAMES},
+    config::Config,
+};
 
 macro_rules! write_templates {
     ($input:expr, $($file:expr),*) => {
@@ -36,3 +39,54 @@ pub fn new(input: Option<PathBuf>, config: Config) -> Result<()> {
     write_templates!(input, "stage.gs", "main.gs", "blank.svg");
     Ok(())
 }
+
+/// Characters which cannot be used in a sprite name, as they are not allowed in file
+/// names on some platforms.
+const RESERVED: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+pub fn new_sprite(input: Option<PathBuf>, name: String) -> Result<()> {
+    let input = input.or_else(|| env::var_os("GOBOSCRIPT_INPUT").map(PathBuf::from));
+    let input = if let Some(input) = input { input } else { env::current_dir()? };
+    if !input.join("stage.gs").is_file() {
+        bail!("{} is not a goboscript project, it has no stage.gs", input.display());
+    }
+    if name.trim().is_empty() {
+        bail!("sprite name cannot be empty");
+    }
+    if let Some(c) = name.chars().find(|c| RESERVED.contains(c) || c.is_control()) {
+        bail!("sprite name cannot contain {c:?}");
+    }
+    if name.starts_with('.') {
+        bail!("sprite name cannot start with `.`");
+    }
+    if name.eq_ignore_ascii_case("stage") {
+        bail!("sprite `{name}` would have the same name as the Stage, choose another name");
+    }
+    if RESERVED_SPRITE_NAMES.contains(&name.as_str()) {
+        bail!("sprite `{name}` would have a name which Scratch uses in menus, choose another name");
+    }
+    if let Some(problem) = scratch_name_problem(&name) {
+        bail!("`{name}` cannot be a sprite name, as {problem}, choose another name");
+    }
+    for path in input.read_dir()?.flatten().map(|entry| entry.path()) {
+        if path.extension() != Some("gs".as_ref()) {
+            continue;
+        }
+        let Some(other) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        if other == name {
+            bail!("sprite `{name}` already exists");
+        }
+        if other.eq_ignore_ascii_case(&name) {
+            bail!(
+                "sprite `{other}` already exists, sprite names are not case-sensitive"
+            );
+        }
+    }
+    fs::write(input.join(format!("{name}.gs")), include_str!("templates/main.gs"))?;
+    if !input.join("blank.svg").exists() {
+        write_templates!(input, "blank.svg");
+    }
+    Ok(())
+}
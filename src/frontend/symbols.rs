@@ -0,0 +1,45 @@
+use colored::Colorize;
+use smol_str::SmolStr;
+
+use crate::ast::{Project, Sprite};
+
+/// Prints the variables, lists, broadcasts and procedures declared by each sprite.
+/// Names starting with `__` are generated by the compiler and are left out.
+pub fn print_symbols(project: &Project) {
+    print_sprite("Stage", &project.stage);
+    let mut sprites: Vec<_> = project.sprites.iter().collect();
+    sprites.sort_by_key(|(name, _)| *name);
+    for (name, sprite) in sprites {
+        print_sprite(name, sprite);
+    }
+}
+
+fn print_sprite(name: &str, sprite: &Sprite) {
+    println!("{}", name.bold());
+    print_names("variables", sprite.vars.keys().cloned());
+    print_names("lists", sprite.lists.keys().cloned());
+    print_names(
+        "broadcasts",
+        sprite
+            .on_messages
+            .iter()
+            .map(|on_message| format!("{:?}", on_message.message).into()),
+    );
+    print_names(
+        "procedures",
+        sprite.procs.values().map(|proc| {
+            let args: Vec<_> = proc.args.iter().map(|(arg, _)| arg.as_str()).collect();
+            format!("{}({})", proc.name, args.join(", ")).into()
+        }),
+    );
+}
+
+fn print_names(kind: &str, names: impl Iterator<Item = SmolStr>) {
+    let mut names: Vec<_> = names.filter(|name| !name.starts_with("__")).collect();
+    if names.is_empty() {
+        return;
+    }
+    names.sort();
+    names.dedup();
+    println!("    {}{}", format!("{kind:<12}").blue(), names.join(", "));
+}
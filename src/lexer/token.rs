@@ -1,3 +1,5 @@
+use std::fmt;
+
 use logos::Logos;
 use smol_str::SmolStr;
 
@@ -27,6 +29,12 @@ pub enum Token {
     Str(SmolStr),
     #[token("costumes")]
     Costumes,
+    #[token("costumes_from")]
+    CostumesFrom,
+    #[token("raw_json")]
+    RawJson,
+    #[token("cloud")]
+    Cloud,
     #[token("sounds")]
     Sounds,
     #[token("local")]
@@ -35,6 +43,10 @@ pub enum Token {
     Proc,
     #[token("nowarp")]
     NoWarp,
+    #[token("inline")]
+    Inline,
+    #[token("macro")]
+    Macro,
     #[token("on")]
     On,
     #[token("onflag")]
@@ -51,6 +63,8 @@ pub enum Token {
     OnTimer,
     #[token("onclone")]
     OnClone,
+    #[token("init")]
+    Init,
     #[token("if")]
     If,
     #[token("else")]
@@ -63,6 +77,24 @@ pub enum Token {
     Forever,
     #[token("repeat")]
     Repeat,
+    #[token("for")]
+    For,
+    #[token("loop")]
+    Loop,
+    #[token("break")]
+    Break,
+    #[token("continue")]
+    Continue,
+    #[token("pass")]
+    Pass,
+    #[token("measure")]
+    Measure,
+    #[token("defer")]
+    Defer,
+    #[token("true")]
+    True,
+    #[token("false")]
+    False,
     #[token(",")]
     Comma,
     #[token("(")]
@@ -73,6 +105,8 @@ pub enum Token {
     LBrace,
     #[token("}")]
     RBrace,
+    #[token("=>")]
+    FatArrow,
     #[token("=")]
     Assign,
     #[token("==")]
@@ -127,6 +161,12 @@ pub enum Token {
     Star,
     #[token("/")]
     Slash,
+    #[token("//")]
+    DoubleSlash,
+    #[token("::")]
+    DoubleColon,
+    #[token(":")]
+    Colon,
     #[token("%")]
     Percent,
     #[token(";")]
@@ -181,6 +221,127 @@ pub enum Token {
     Of,
     #[token("as")]
     As,
+    #[token("with")]
+    With,
+    #[token("into")]
+    Into,
     #[token("enum")]
     Enum,
 }
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Token::Name(name) => name,
+            Token::Arg(arg) => return write!(f, "${arg}"),
+            Token::Mac(mac) => return write!(f, "{mac}!"),
+            Token::Bin(value)
+            | Token::Oct(value)
+            | Token::Int(value)
+            | Token::Hex(value) => return write!(f, "{value}"),
+            Token::Float(value) => return write!(f, "{value}"),
+            Token::Str(value) => return write!(f, "{value:?}"),
+            Token::Costumes => "costumes",
+            Token::CostumesFrom => "costumes_from",
+            Token::RawJson => "raw_json",
+            Token::Cloud => "cloud",
+            Token::Sounds => "sounds",
+            Token::Local => "local",
+            Token::Proc => "proc",
+            Token::NoWarp => "nowarp",
+            Token::Inline => "inline",
+            Token::Macro => "macro",
+            Token::On => "on",
+            Token::OnFlag => "onflag",
+            Token::OnKey => "onkey",
+            Token::OnClick => "onclick",
+            Token::OnBackdrop => "onbackdrop",
+            Token::OnLoudness => "onloudness",
+            Token::OnTimer => "ontimer",
+            Token::OnClone => "onclone",
+            Token::Init => "init",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::Elif => "elif",
+            Token::Until => "until",
+            Token::Forever => "forever",
+            Token::Repeat => "repeat",
+            Token::For => "for",
+            Token::Loop => "loop",
+            Token::Break => "break",
+            Token::Continue => "continue",
+            Token::Pass => "pass",
+            Token::Measure => "measure",
+            Token::Defer => "defer",
+            Token::True => "true",
+            Token::False => "false",
+            Token::Comma => ",",
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::LBrace => "{",
+            Token::RBrace => "}",
+            Token::FatArrow => "=>",
+            Token::Assign => "=",
+            Token::Eq => "==",
+            Token::Increment => "++",
+            Token::Decrement => "--",
+            Token::AssignAdd => "+=",
+            Token::AssignSubtract => "-=",
+            Token::AssignMultiply => "*=",
+            Token::AssignDivide => "/=",
+            Token::AssignModulo => "%=",
+            Token::AssignJoin => "&=",
+            Token::LBracket => "[",
+            Token::RBracket => "]",
+            Token::Dot => ".",
+            Token::Ne => "!=",
+            Token::Lt => "<",
+            Token::Gt => ">",
+            Token::Le => "<=",
+            Token::Ge => ">=",
+            Token::Not => "not",
+            Token::And => "and",
+            Token::Or => "or",
+            Token::In => "in",
+            Token::Amp => "&",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Star => "*",
+            Token::Slash => "/",
+            Token::DoubleSlash => "//",
+            Token::DoubleColon => "::",
+            Token::Colon => ":",
+            Token::Percent => "%",
+            Token::Semicolon => ";",
+            Token::Length => "length",
+            Token::Round => "round",
+            Token::Abs => "abs",
+            Token::Floor => "floor",
+            Token::Ceil => "ceil",
+            Token::Sqrt => "sqrt",
+            Token::Sin => "sin",
+            Token::Cos => "cos",
+            Token::Tan => "tan",
+            Token::Asin => "asin",
+            Token::Acos => "acos",
+            Token::Atan => "atan",
+            Token::Ln => "ln",
+            Token::Log => "log",
+            Token::Antiln => "antiln",
+            Token::Antilog => "antilog",
+            Token::Show => "show",
+            Token::Hide => "hide",
+            Token::Add => "add",
+            Token::To => "to",
+            Token::Delete => "delete",
+            Token::Insert => "insert",
+            Token::At => "at",
+            Token::Of => "of",
+            Token::As => "as",
+            Token::With => "with",
+            Token::Into => "into",
+            Token::Enum => "enum",
+        };
+        f.write_str(text)
+    }
+}
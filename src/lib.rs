@@ -0,0 +1,15 @@
+//! goboscript is the Scratch compiler. The `goboscript` binary is a thin wrapper around
+//! `frontend::frontend`. Other crates can build projects with `frontend::build::build`,
+//! and run their own passes over the project with `visitors::AstPass`.
+pub mod ast;
+pub mod blocks;
+pub mod cli;
+pub mod codegen;
+pub mod config;
+pub mod custom_toml_error;
+pub mod diagnostic;
+pub mod frontend;
+pub mod lexer;
+pub mod parser;
+pub mod preproc;
+pub mod visitors;
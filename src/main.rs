@@ -1,19 +1,8 @@
 use std::{panic, process::ExitCode, time::Instant};
 
+use clap::Parser;
 use colored::Colorize;
-
-mod ast;
-mod blocks;
-mod cli;
-mod codegen;
-mod config;
-mod custom_toml_error;
-mod diagnostic;
-mod frontend;
-mod lexer;
-mod parser;
-mod preproc;
-mod visitors;
+use goboscript::{cli::Cli, frontend};
 
 fn main() -> ExitCode {
     panic::set_hook(Box::new(|info| {
@@ -24,11 +13,20 @@ fn main() -> ExitCode {
         );
     }));
     let begin = Instant::now();
-    let result = frontend::frontend();
+    let cli = Cli::parse();
+    let to_stderr = cli.command.writes_to_stdout();
+    let result = frontend::frontend(cli);
     if let Err(err) = &result {
         eprintln!("{}{} {}", "error".bold().red(), ":".bold(), err.to_string().bold());
     }
-    eprintln!("{} in {:?}", "finished".bold().blue(), begin.elapsed());
+    frontend::build::print_summary(
+        to_stderr,
+        format!(
+            "{} in {}",
+            "finished".bold().blue(),
+            frontend::build::format_duration(begin.elapsed())
+        ),
+    );
     if result.is_ok() {
         ExitCode::SUCCESS
     } else {
@@ -1,8 +1,11 @@
 use lalrpop_util::{lalrpop_mod, ParseError};
+use logos::Span;
+use smol_str::SmolStr;
 
 use crate::{lexer::adaptor::Lexer, parser::grammar::SpriteParser};
 
 lalrpop_mod!(grammar, "/parser/grammar.rs");
+mod brackets;
 
 use crate::{
     ast::Sprite,
@@ -10,34 +13,55 @@ use crate::{
     preproc,
 };
 
+/// The version pragma is a comment on the first line, such as `#!goboscript 1.0`.
+fn version_pragma(src: &str) -> Option<(SmolStr, Span)> {
+    let line = src.lines().next()?;
+    let version = line.strip_prefix("#!goboscript")?;
+    let start = line.len() - version.trim_start().len();
+    let version = version.trim();
+    Some((SmolStr::from(version), start..start + version.len()))
+}
+
 pub fn parse(src: &str) -> Result<Sprite, Diagnostic> {
     let tokens = preproc::process(
         Lexer::new(src).flatten().map(|(left, token, right)| (token, left..right)),
         &mut Default::default(),
     );
     let parser = SpriteParser::new();
-    let mut sprite = Sprite::default();
+    let mut sprite = Sprite { version: version_pragma(src), ..Default::default() };
     parser
         .parse(
             &mut sprite,
-            tokens.into_iter().map(|(token, span)| (span.start, token, span.end)),
+            tokens.iter().cloned().map(|(token, span)| (span.start, token, span.end)),
         )
         .map(|_| sprite)
-        .map_err(|err| match err {
-            ParseError::InvalidToken { location } => {
-                DiagnosticDetail::InvalidToken.to_diagnostic(location..location + 1)
-            }
-            ParseError::UnrecognizedEof { location, expected } => {
-                DiagnosticDetail::UnrecognizedEof(expected)
-                    .to_diagnostic(location..location + 1)
-            }
-            ParseError::UnrecognizedToken { token: (left, token, right), expected } => {
-                DiagnosticDetail::UnrecognizedToken(token, expected)
-                    .to_diagnostic(left..right)
+        .map_err(|err| {
+            if matches!(
+                err,
+                ParseError::UnrecognizedEof { .. }
+                    | ParseError::UnrecognizedToken { .. }
+            ) {
+                if let Some(err) = brackets::check_brackets(&tokens) {
+                    return err;
+                }
             }
-            ParseError::ExtraToken { token: (left, token, right) } => {
-                DiagnosticDetail::ExtraToken(token).to_diagnostic(left..right)
+            match err {
+                ParseError::InvalidToken { location } => {
+                    DiagnosticDetail::InvalidToken.to_diagnostic(location..location + 1)
+                }
+                ParseError::UnrecognizedEof { location, expected } => {
+                    DiagnosticDetail::UnrecognizedEof(expected)
+                        .to_diagnostic(location..location + 1)
+                }
+                ParseError::UnrecognizedToken {
+                    token: (left, token, right),
+                    expected,
+                } => DiagnosticDetail::UnrecognizedToken(token, expected)
+                    .to_diagnostic(left..right),
+                ParseError::ExtraToken { token: (left, token, right) } => {
+                    DiagnosticDetail::ExtraToken(token).to_diagnostic(left..right)
+                }
+                ParseError::User { error } => error,
             }
-            ParseError::User { error } => error,
         })
 }
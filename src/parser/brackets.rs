@@ -0,0 +1,54 @@
+use logos::Span;
+
+use crate::{
+    diagnostic::{Diagnostic, DiagnosticDetail},
+    lexer::token::Token,
+};
+
+fn closer(opener: &Token) -> Option<char> {
+    match opener {
+        Token::LParen => Some(')'),
+        Token::LBrace => Some('}'),
+        Token::LBracket => Some(']'),
+        _ => None,
+    }
+}
+
+fn closing(token: &Token) -> Option<char> {
+    match token {
+        Token::RParen => Some(')'),
+        Token::RBrace => Some('}'),
+        Token::RBracket => Some(']'),
+        _ => None,
+    }
+}
+
+/// Finds the first bracket which is not closed, or closed by the wrong bracket. The
+/// parser only reports where it gave up, which for a missing `}` is the end of the
+/// file, so this is used to point at the bracket instead.
+pub fn check_brackets(tokens: &[(Token, Span)]) -> Option<Diagnostic> {
+    let mut stack: Vec<(char, &Span)> = vec![];
+    for (token, span) in tokens {
+        if let Some(closer) = closer(token) {
+            stack.push((closer, span));
+        } else if let Some(found) = closing(token) {
+            match stack.pop() {
+                Some((expected, _)) if expected == found => {}
+                Some((expected, opener)) => {
+                    return Some(
+                        DiagnosticDetail::MismatchedBracket { found, expected }
+                            .to_diagnostic(opener.start..span.end),
+                    );
+                }
+                None => {
+                    return Some(
+                        DiagnosticDetail::UnopenedBracket(found)
+                            .to_diagnostic(span.clone()),
+                    );
+                }
+            }
+        }
+    }
+    let (closer, opener) = stack.pop()?;
+    Some(DiagnosticDetail::UnclosedBracket(closer).to_diagnostic(opener.clone()))
+}
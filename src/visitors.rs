@@ -1,3 +1,47 @@
+// Passes over the parsed project, run by `frontend::build` in this order:
+//
+// - `measure` lowers `measure` blocks.
+// - `defer` copies deferred statements to the ways out of their body.
+// - `loops` lowers `break`, `continue` and `loop`.
+// - `counter` counts loops with TurboWarp's counter blocks, with `native_counter`.
+// - `pass1` records the names each script uses, folds constants and lowers what
+//   Scratch has no block for.
+// - `inline` replaces calls to inline procedures with their bodies.
+// - `pass2` marks the variables, lists, enums and procedures which are used.
+// - `constants` warns about constant variables, with `--warn-constants`.
+// - `globals` warns about globals set by several sprites, with `--warn-shared-globals`.
+//
+// Passes given to `frontend::build` in `BuildOptions::passes` run after `counter`, in
+// the order they are given, see `AstPass`.
+//
+// A new pass is a module here with a `visit_project` function, called from
+// `frontend::build` between parsing and codegen. Passes which only report diagnostics
+// take the project by reference, and push to the diagnostics of each sprite.
+pub mod constants;
+pub mod counter;
+pub mod defer;
+pub mod globals;
+pub mod inline;
+pub mod loops;
+pub mod measure;
 pub mod pass0;
 pub mod pass1;
 pub mod pass2;
+
+use fxhash::FxHashMap;
+use smol_str::SmolStr;
+
+use crate::{ast::Project, diagnostic::Diagnostic};
+
+/// A pass over the project, for crates which use goboscript as a library to add their
+/// own lints or transforms. It runs before `pass1`, so the names it adds or renames are
+/// recorded and marked as used like those written in the source. `diags` holds the
+/// diagnostics of each sprite by its name.
+pub trait AstPass {
+    fn run(
+        &self,
+        project: &mut Project,
+        stage_diags: &mut Vec<Diagnostic>,
+        diags: &mut FxHashMap<SmolStr, Vec<Diagnostic>>,
+    );
+}
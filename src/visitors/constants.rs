@@ -0,0 +1,134 @@
+use fxhash::FxHashMap;
+use logos::Span;
+use smol_str::SmolStr;
+
+use crate::{
+    ast::{Expr, Project, Sprite, Stmt, Stmts, Var},
+    diagnostic::{Diagnostic, DiagnosticDetail},
+};
+
+/// A variable, by the sprite which declares it, `None` for the stage.
+pub type Key = (Option<SmolStr>, SmolStr);
+
+/// A write to a variable, by the sprite it is in and whether it writes a literal.
+pub struct Write {
+    pub sprite: Option<SmolStr>,
+    pub span: Span,
+    pub literal: bool,
+}
+
+/// Warns about variables which are read, but only ever set once, to a literal. They
+/// could be replaced by their value. Variables generated by the compiler are skipped.
+pub fn visit_project(
+    project: &Project,
+    stage_diags: &mut Vec<Diagnostic>,
+    diags: &mut FxHashMap<SmolStr, Vec<Diagnostic>>,
+) {
+    for ((owner, name), writes) in writes(project) {
+        let [write] = writes.as_slice() else { continue };
+        let owner = match &owner {
+            Some(owner) => &project.sprites[owner],
+            None => &project.stage,
+        };
+        if !write.literal || name.starts_with("__") || !owner.vars[&name].used {
+            continue;
+        }
+        let diag =
+            DiagnosticDetail::ConstantVariable(name).to_diagnostic(write.span.clone());
+        match &write.sprite {
+            Some(sprite) => diags.get_mut(sprite).unwrap().push(diag),
+            None => stage_diags.push(diag),
+        }
+    }
+}
+
+/// The writes to every variable of the project, in the order of the variables. Writes
+/// to locals are skipped.
+pub fn writes(project: &Project) -> Vec<(Key, Vec<Write>)> {
+    let mut writes: FxHashMap<Key, Vec<Write>> = Default::default();
+    visit_sprite(project, &project.stage, None, &mut writes);
+    for (name, sprite) in &project.sprites {
+        visit_sprite(project, sprite, Some(name), &mut writes);
+    }
+    let mut writes: Vec<_> = writes.into_iter().collect();
+    writes.sort_by(|(a, _), (b, _)| a.cmp(b));
+    writes
+}
+
+fn visit_sprite(
+    project: &Project,
+    sprite: &Sprite,
+    name: Option<&SmolStr>,
+    writes: &mut FxHashMap<Key, Vec<Write>>,
+) {
+    let mut v = V { project, sprite, name, locals: None, writes };
+    for event in &sprite.events {
+        v.visit_stmts(&event.body);
+    }
+    for on_message in sprite.on_messages.iter() {
+        v.visit_stmts(&on_message.body);
+    }
+    // Inline procedures are already copied into the scripts which call them.
+    for proc in sprite.procs.values().filter(|proc| !proc.inline) {
+        v.locals = Some(&proc.locals);
+        v.visit_stmts(&proc.body);
+    }
+}
+
+struct V<'a> {
+    project: &'a Project,
+    sprite: &'a Sprite,
+    name: Option<&'a SmolStr>,
+    locals: Option<&'a FxHashMap<SmolStr, Var>>,
+    writes: &'a mut FxHashMap<Key, Vec<Write>>,
+}
+
+impl V<'_> {
+    fn visit_stmts(&mut self, stmts: &Stmts) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Repeat { body, .. }
+                | Stmt::Forever { body, .. }
+                | Stmt::Until { body, .. }
+                | Stmt::Loop { body, .. } => self.visit_stmts(body),
+                Stmt::ForEach { name, span, body, .. } => {
+                    self.write(name, span, false);
+                    self.visit_stmts(body);
+                }
+                Stmt::Branch { if_body, else_body, .. } => {
+                    self.visit_stmts(if_body);
+                    self.visit_stmts(else_body);
+                }
+                Stmt::SetVar { name, span, value, is_local: false } => {
+                    let literal = matches!(
+                        &*value.borrow(),
+                        Expr::Int(_)
+                            | Expr::Float(_)
+                            | Expr::Str(_)
+                            | Expr::EnumVariant { .. }
+                    );
+                    self.write(name, span, literal);
+                }
+                Stmt::ChangeVar { name, span, .. } | Stmt::Ask { name, span, .. } => {
+                    self.write(name, span, false);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn write(&mut self, name: &SmolStr, span: &Span, literal: bool) {
+        if self.locals.is_some_and(|locals| locals.contains_key(name)) {
+            return;
+        }
+        let owner = if self.sprite.vars.contains_key(name) {
+            self.name.cloned()
+        } else if self.project.stage.vars.contains_key(name) {
+            None
+        } else {
+            return;
+        };
+        let write = Write { sprite: self.name.cloned(), span: span.clone(), literal };
+        self.writes.entry((owner, name.clone())).or_default().push(write);
+    }
+}
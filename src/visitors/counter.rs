@@ -0,0 +1,264 @@
+use fxhash::FxHashSet;
+use smol_str::SmolStr;
+
+use crate::{
+    ast::{Expr, Project, Rrc, Sprite, Stmt, Stmts},
+    blocks::{Block, Repr},
+};
+
+/// Counts `for` and `repeat ... as` loops with the counter of TurboWarp's hidden
+/// `control_*_counter` blocks instead of their index variable, with `native_counter`.
+/// The counter is cleared before the loop and increased at the start of each iteration,
+/// and the index is read with `counter()`. There is only one counter, so a loop keeps
+/// its variable if its body contains another counted loop, calls a procedure, waits for
+/// a broadcast, uses the counter blocks or sets the index. Loops whose index is shown
+/// by the debug overlay, listed in `debug_variables`, also keep their variable, and so
+/// do loops whose index is read after the loop, as the counter does not set it. Index
+/// variables which are no longer used anywhere are removed.
+pub fn visit_project(project: &mut Project, debug_variables: &[String]) {
+    let mut read_after = FxHashSet::default();
+    sprite_reads(&project.stage, &mut read_after);
+    for sprite in project.sprites.values() {
+        sprite_reads(sprite, &mut read_after);
+    }
+    let mut globals: FxHashSet<SmolStr> = debug_variables
+        .iter()
+        .filter(|key| !key.contains("::"))
+        .map(|key| key.into())
+        .collect();
+    // An index read outside of its loops can not be counted either.
+    globals.extend(read_after);
+    let stage_counted = visit_sprite(&mut project.stage, &globals);
+    let counted: Vec<_> = project
+        .sprites
+        .iter_mut()
+        .map(|(sprite_name, sprite)| {
+            let mut shown = globals.clone();
+            shown.extend(debug_variables.iter().filter_map(|key| {
+                let (sprite, name) = key.split_once("::")?;
+                (sprite == sprite_name).then(|| name.into())
+            }));
+            visit_sprite(sprite, &shown)
+        })
+        .collect();
+    let mut names = FxHashSet::default();
+    sprite_names(&project.stage, &mut names);
+    for sprite in project.sprites.values() {
+        sprite_names(sprite, &mut names);
+    }
+    project
+        .stage
+        .vars
+        .retain(|name, _| !stage_counted.contains(name) || names.contains(name));
+    for (sprite, counted) in project.sprites.values_mut().zip(counted) {
+        sprite.vars.retain(|name, _| !counted.contains(name) || names.contains(name));
+    }
+}
+
+/// Returns the names of the index variables of the loops which now use the counter.
+/// Loops whose index is in `shown` are left as they are.
+fn visit_sprite(sprite: &mut Sprite, shown: &FxHashSet<SmolStr>) -> FxHashSet<SmolStr> {
+    let mut counted = FxHashSet::default();
+    for event in &mut sprite.events {
+        visit_stmts(&mut event.body, shown, &mut counted);
+    }
+    for on_message in sprite.on_messages.iter_mut() {
+        visit_stmts(&mut on_message.body, shown, &mut counted);
+    }
+    for proc in sprite.procs.values_mut() {
+        visit_stmts(&mut proc.body, shown, &mut counted);
+    }
+    counted
+}
+
+fn visit_stmts(
+    stmts: &mut Stmts,
+    shown: &FxHashSet<SmolStr>,
+    counted: &mut FxHashSet<SmolStr>,
+) {
+    let mut i = 0;
+    while i < stmts.len() {
+        // Inner loops are counted first, so that the loops around them are not.
+        for body in stmts[i].bodies_mut() {
+            visit_stmts(body, shown, counted);
+        }
+        if let Stmt::ForEach { name, span, times, body } = &mut stmts[i] {
+            if !shown.contains(name) && can_count(body, name) {
+                let mut body = std::mem::take(body);
+                for stmt in &body {
+                    replace_reads(stmt, name);
+                }
+                body.insert(
+                    0,
+                    Stmt::Block {
+                        block: Block::IncrCounter,
+                        span: span.clone(),
+                        args: vec![],
+                    },
+                );
+                counted.insert(name.clone());
+                let clear = Stmt::Block {
+                    block: Block::ClearCounter,
+                    span: span.clone(),
+                    args: vec![],
+                };
+                let repeat =
+                    Stmt::Repeat { times: times.clone(), span: span.clone(), body };
+                stmts.splice(i..=i, [clear, repeat]);
+                i += 1;
+            }
+        }
+        i += 1;
+    }
+}
+
+fn can_count(stmts: &Stmts, index: &str) -> bool {
+    stmts.iter().all(|stmt| {
+        let disturbs = match stmt {
+            Stmt::ForEach { .. } | Stmt::ProcCall { .. } => true,
+            Stmt::Broadcast { and_wait, .. } => *and_wait,
+            Stmt::Block { block, .. } => matches!(
+                block,
+                Block::ClearCounter | Block::IncrCounter | Block::BroadcastAndWait
+            ),
+            Stmt::SetVar { name, .. }
+            | Stmt::ChangeVar { name, .. }
+            | Stmt::Ask { name, .. } => name == index,
+            _ => false,
+        };
+        !disturbs && stmt.bodies().into_iter().all(|body| can_count(body, index))
+    })
+}
+
+fn replace_reads(stmt: &Stmt, index: &str) {
+    for expr in stmt.exprs() {
+        replace_expr(expr, index);
+    }
+    for body in stmt.bodies() {
+        for stmt in body {
+            replace_reads(stmt, index);
+        }
+    }
+}
+
+fn replace_expr(expr: &Rrc<Expr>, index: &str) {
+    let counter = match &*expr.borrow() {
+        Expr::Name { name, span } if name == index => {
+            Some(Expr::Repr { repr: Repr::Counter, span: span.clone(), args: vec![] })
+        }
+        Expr::Repr { args, .. } => {
+            for arg in args {
+                replace_expr(arg, index);
+            }
+            None
+        }
+        Expr::UnOp { val, .. } => {
+            replace_expr(val, index);
+            None
+        }
+        Expr::BinOp { lhs, rhs, .. } => {
+            replace_expr(lhs, index);
+            replace_expr(rhs, index);
+            None
+        }
+        _ => None,
+    };
+    if let Some(counter) = counter {
+        *expr.borrow_mut() = counter;
+    }
+}
+
+/// The names a sprite reads outside of the `for` loops over them, which would see the
+/// value a loop leaves in its index.
+fn sprite_reads(sprite: &Sprite, reads: &mut FxHashSet<SmolStr>) {
+    let bodies = sprite
+        .events
+        .iter()
+        .map(|event| &event.body)
+        .chain(sprite.on_messages.iter().map(|on_message| &on_message.body))
+        .chain(sprite.procs.values().map(|proc| &proc.body));
+    for body in bodies {
+        stmts_reads(body, reads);
+    }
+}
+
+fn stmts_reads(stmts: &Stmts, reads: &mut FxHashSet<SmolStr>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::ForEach { name, times, body, .. } => {
+                expr_names(times, reads);
+                let mut inner = FxHashSet::default();
+                stmts_reads(body, &mut inner);
+                inner.remove(name);
+                reads.extend(inner);
+                continue;
+            }
+            Stmt::ChangeVar { name, .. }
+            | Stmt::Show { name, .. }
+            | Stmt::Hide { name, .. } => {
+                reads.insert(name.clone());
+            }
+            _ => {}
+        }
+        for expr in stmt.exprs() {
+            expr_names(expr, reads);
+        }
+        for body in stmt.bodies() {
+            stmts_reads(body, reads);
+        }
+    }
+}
+
+/// Every name a sprite reads or sets, whether of a variable or a list.
+fn sprite_names(sprite: &Sprite, names: &mut FxHashSet<SmolStr>) {
+    let bodies = sprite
+        .events
+        .iter()
+        .map(|event| &event.body)
+        .chain(sprite.on_messages.iter().map(|on_message| &on_message.body))
+        .chain(sprite.procs.values().map(|proc| &proc.body));
+    for body in bodies {
+        stmts_names(body, names);
+    }
+}
+
+fn stmts_names(stmts: &Stmts, names: &mut FxHashSet<SmolStr>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::ForEach { name, .. }
+            | Stmt::SetVar { name, .. }
+            | Stmt::ChangeVar { name, .. }
+            | Stmt::Show { name, .. }
+            | Stmt::Hide { name, .. }
+            | Stmt::Ask { name, .. } => {
+                names.insert(name.clone());
+            }
+            _ => {}
+        }
+        for expr in stmt.exprs() {
+            expr_names(expr, names);
+        }
+        for body in stmt.bodies() {
+            stmts_names(body, names);
+        }
+    }
+}
+
+fn expr_names(expr: &Rrc<Expr>, names: &mut FxHashSet<SmolStr>) {
+    match &*expr.borrow() {
+        Expr::Name { name, .. } | Expr::SpriteVar { name, .. } => {
+            names.insert(name.clone());
+        }
+        Expr::Repr { args, .. } => {
+            for arg in args {
+                expr_names(arg, names);
+            }
+        }
+        Expr::UnOp { val, .. } => expr_names(val, names),
+        Expr::BinOp { lhs, rhs, .. } => {
+            expr_names(lhs, names);
+            expr_names(rhs, names);
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{
+    ast::{Project, Sprite, Stmt, Stmts},
+    blocks::Block,
+    visitors::{inline::deep_clone, loops::loop_control},
+};
+
+/// The `defer`s which have run so far in a body which is being visited.
+struct Frame {
+    defers: Vec<Stmts>,
+    /// Whether this is the body of a loop, which `break` and `continue` leave.
+    is_loop: bool,
+}
+
+/// Replaces each `defer` with copies of its body at every way out of the body it is
+/// in: its end, `stop_this_script`, and `break` or `continue` if it is in a loop. The
+/// bodies of later `defer`s run first. A `defer` only runs if it was reached, so it is
+/// not copied to the ways out before it. `stop_all` and `delete_this_clone` do not run
+/// anything deferred, like the end of a body which never ends.
+pub fn visit_project(project: &mut Project) {
+    visit_sprite(&mut project.stage);
+    for sprite in project.sprites.values_mut() {
+        visit_sprite(sprite);
+    }
+}
+
+fn visit_sprite(sprite: &mut Sprite) {
+    for event in &mut sprite.events {
+        visit_stmts(&mut event.body, &mut vec![], false);
+    }
+    for on_message in sprite.on_messages.iter_mut() {
+        visit_stmts(&mut on_message.body, &mut vec![], false);
+    }
+    for proc in sprite.procs.values_mut() {
+        visit_stmts(&mut proc.body, &mut vec![], false);
+    }
+}
+
+fn visit_stmts(stmts: &mut Stmts, frames: &mut Vec<Frame>, is_loop: bool) {
+    frames.push(Frame { defers: vec![], is_loop });
+    let mut i = 0;
+    while i < stmts.len() {
+        let exit = match &mut stmts[i] {
+            Stmt::Repeat { body, .. }
+            | Stmt::Forever { body, .. }
+            | Stmt::Until { body, .. }
+            | Stmt::Loop { body, .. }
+            | Stmt::ForEach { body, .. } => {
+                visit_stmts(body, frames, true);
+                vec![]
+            }
+            Stmt::Branch { if_body, else_body, .. } => {
+                visit_stmts(if_body, frames, false);
+                visit_stmts(else_body, frames, false);
+                vec![]
+            }
+            Stmt::Defer { body, .. } => {
+                let mut body = std::mem::take(body);
+                // The deferred body is a body of its own, it ends where it is copied to.
+                visit_stmts(&mut body, &mut vec![], false);
+                frames.last_mut().unwrap().defers.push(body);
+                stmts.remove(i);
+                continue;
+            }
+            Stmt::Block { block: Block::StopThisScript, .. } => exit(frames, false),
+            Stmt::Break { .. } | Stmt::Continue { .. } => exit(frames, true),
+            _ => vec![],
+        };
+        let len = exit.len();
+        stmts.splice(i..i, exit);
+        i += len + 1;
+    }
+    let frame = frames.pop().unwrap();
+    if !stmts.last().is_some_and(never_ends) {
+        stmts.extend(frame.defers.iter().rev().flat_map(deep_clone));
+    }
+}
+
+/// Copies of the deferred bodies which run when leaving the innermost loop, if
+/// `to_loop`, or else the whole script.
+fn exit(frames: &[Frame], to_loop: bool) -> Stmts {
+    let mut stmts = vec![];
+    for frame in frames.iter().rev() {
+        stmts.extend(frame.defers.iter().rev().flat_map(deep_clone));
+        if to_loop && frame.is_loop {
+            break;
+        }
+    }
+    stmts
+}
+
+/// Whether the statements after `stmt` never run.
+fn never_ends(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Forever { body, .. } | Stmt::Loop { body, .. } => {
+            loop_control(body).0.is_none()
+        }
+        Stmt::Break { .. } | Stmt::Continue { .. } => true,
+        Stmt::Block { block, .. } => matches!(
+            block,
+            Block::StopAll | Block::StopThisScript | Block::DeleteThisClone
+        ),
+        _ => false,
+    }
+}
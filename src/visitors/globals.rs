@@ -0,0 +1,33 @@
+use smol_str::SmolStr;
+
+use super::constants::writes;
+use crate::{
+    ast::{Project, CLONE_COUNTER},
+    diagnostic::{Diagnostic, DiagnosticDetail},
+};
+
+/// Warns about global variables which are set by scripts of more than one sprite, as
+/// one sprite can change the variable while another is using it. The Stage counts as a
+/// sprite. The warning is shown at the variable in the Stage, and lists the sprites
+/// which set it. Variables generated by the compiler are skipped.
+pub fn visit_project(project: &Project, stage_diags: &mut Vec<Diagnostic>) {
+    for ((owner, name), writes) in writes(project) {
+        if owner.is_some() || name.starts_with("__") || name == CLONE_COUNTER {
+            continue;
+        }
+        let mut sprites: Vec<_> =
+            writes.into_iter().map(|write| write.sprite).collect();
+        sprites.sort();
+        sprites.dedup();
+        if sprites.len() < 2 {
+            continue;
+        }
+        let writers: Vec<SmolStr> = sprites
+            .into_iter()
+            .map(|sprite| sprite.unwrap_or_else(|| "Stage".into()))
+            .collect();
+        let span = project.stage.vars[&name].span.clone();
+        stage_diags
+            .push(DiagnosticDetail::SharedGlobal { name, writers }.to_diagnostic(span));
+    }
+}
@@ -0,0 +1,434 @@
+use fxhash::{FxHashMap, FxHashSet};
+use logos::Span;
+use smol_str::SmolStr;
+
+use crate::{
+    ast::{Expr, ProcKey, Project, Rrc, Sprite, Stmt, Stmts, Var},
+    diagnostic::{Diagnostic, DiagnosticDetail},
+};
+
+/// An inline procedure, with the names its body uses which are not its own.
+struct Inline {
+    label: SmolStr,
+    params: Vec<SmolStr>,
+    locals: Vec<SmolStr>,
+    body: Stmts,
+    free: FxHashSet<SmolStr>,
+    warp: bool,
+    loops: bool,
+}
+
+/// Where calls are being expanded: the locals of the procedure they are in, and
+/// whether it runs without screen refresh.
+struct Caller<'a> {
+    locals: Option<&'a FxHashMap<SmolStr, Var>>,
+    warp: bool,
+}
+
+/// Replaces calls to inline procedures with their bodies. The arguments and locals of
+/// each copy are stored in their own sprite variables, so that copies do not overwrite
+/// each other's variables. Other names in the body are the sprite's, so a local of the
+/// caller with the same name is an error, as is an argument which the inline procedure
+/// does not take. An inline procedure which runs without screen refresh cannot be
+/// copied into a script which refreshes it if the procedure has loops.
+pub fn visit_project(
+    project: &mut Project,
+    stage_diags: &mut Vec<Diagnostic>,
+    diags: &mut FxHashMap<SmolStr, Vec<Diagnostic>>,
+) {
+    visit_sprite(&mut project.stage, stage_diags);
+    for (name, sprite) in &mut project.sprites {
+        visit_sprite(sprite, diags.get_mut(name).unwrap());
+    }
+}
+
+fn visit_sprite(sprite: &mut Sprite, d: &mut Vec<Diagnostic>) {
+    let mut inlines: FxHashMap<ProcKey, Inline> = Default::default();
+    for proc in sprite.procs.values().filter(|proc| proc.inline) {
+        let mut free = FxHashSet::default();
+        let mut args = vec![];
+        stmts_names(&proc.body, &mut free, &mut args);
+        for (name, span) in args {
+            if !proc.args.iter().any(|(arg, _)| *arg == name) {
+                d.push(
+                    DiagnosticDetail::UnrecognizedArgument {
+                        name,
+                        proc: Some(proc.key()),
+                    }
+                    .to_diagnostic(span),
+                );
+            }
+        }
+        free.retain(|name| !proc.locals.contains_key(name));
+        inlines.insert(
+            proc.key(),
+            Inline {
+                label: sprite.proc_label(proc),
+                params: proc.args.iter().map(|(arg, _)| arg.clone()).collect(),
+                locals: proc.locals.keys().cloned().collect(),
+                body: deep_clone(&proc.body),
+                free,
+                warp: proc.warp,
+                loops: has_loops(&proc.body),
+            },
+        );
+    }
+    if inlines.is_empty() {
+        return;
+    }
+    let mut e = Expander { inlines: &inlines, vars: &mut sprite.vars, d, copies: 0 };
+    let script = Caller { locals: None, warp: false };
+    for event in &mut sprite.events {
+        e.expand_stmts(&mut event.body, &script, &mut vec![]);
+    }
+    for on_message in sprite.on_messages.iter_mut() {
+        e.expand_stmts(&mut on_message.body, &script, &mut vec![]);
+    }
+    for proc in sprite.procs.values_mut().filter(|proc| !proc.inline) {
+        let caller = Caller { locals: Some(&proc.locals), warp: proc.warp };
+        e.expand_stmts(&mut proc.body, &caller, &mut vec![]);
+    }
+}
+
+struct Expander<'a> {
+    inlines: &'a FxHashMap<ProcKey, Inline>,
+    vars: &'a mut FxHashMap<SmolStr, Var>,
+    d: &'a mut Vec<Diagnostic>,
+    /// Number of copies made so far, which numbers the variables of the next copy.
+    copies: usize,
+}
+
+impl Expander<'_> {
+    /// Replaces calls to inline procedures with their bodies. Recursive calls are left
+    /// as they are, and reported by codegen.
+    fn expand_stmts(
+        &mut self,
+        stmts: &mut Stmts,
+        caller: &Caller,
+        stack: &mut Vec<ProcKey>,
+    ) {
+        let mut i = 0;
+        while i < stmts.len() {
+            for body in stmts[i].bodies_mut() {
+                self.expand_stmts(body, caller, stack);
+            }
+            let Stmt::ProcCall { name, span, args } = &stmts[i] else {
+                i += 1;
+                continue;
+            };
+            let key = (name.clone(), args.len());
+            let inlines = self.inlines;
+            let Some(inline) = inlines.get(&key) else {
+                i += 1;
+                continue;
+            };
+            if stack.contains(&key) {
+                i += 1;
+                continue;
+            }
+            if inline.warp && inline.loops && !caller.warp {
+                self.d.push(
+                    DiagnosticDetail::InlineWarp(inline.label.clone())
+                        .to_diagnostic(span.clone()),
+                );
+            }
+            if let Some(locals) = caller.locals {
+                let mut captured: Vec<_> = inline
+                    .free
+                    .iter()
+                    .filter(|name| locals.contains_key(*name))
+                    .collect();
+                captured.sort();
+                for name in captured {
+                    self.d.push(
+                        DiagnosticDetail::InlineCapture {
+                            name: name.clone(),
+                            proc: inline.label.clone(),
+                        }
+                        .to_diagnostic(span.clone()),
+                    );
+                }
+            }
+            let copy = self.copies;
+            self.copies += 1;
+            let mut renames = Substitution::default();
+            for local in &inline.locals {
+                let name = self.variable(inline, copy, local, span);
+                renames.names.insert(local.clone(), name);
+            }
+            let mut expansion: Stmts = vec![];
+            for (param, value) in inline.params.iter().zip(args) {
+                let name = self.variable(inline, copy, &format!("${param}"), span);
+                renames.names.insert(format!("${param}").into(), name.clone());
+                expansion.push(Stmt::SetVar {
+                    name,
+                    span: span.clone(),
+                    value: value.clone(),
+                    is_local: false,
+                });
+            }
+            let mut body = clone_stmts(&inline.body, &renames);
+            stack.push(key);
+            self.expand_stmts(&mut body, caller, stack);
+            stack.pop();
+            expansion.extend(body);
+            let len = expansion.len();
+            stmts.splice(i..=i, expansion);
+            i += len;
+        }
+    }
+
+    /// Creates the sprite variable which stores an argument or local of a copy.
+    fn variable(
+        &mut self,
+        inline: &Inline,
+        copy: usize,
+        name: &str,
+        span: &Span,
+    ) -> SmolStr {
+        let name = inline_variable_name(&inline.label, copy, name);
+        let mut var = Var::new(name.clone(), span.clone(), None);
+        var.used = true;
+        self.vars.insert(name.clone(), var);
+        name
+    }
+}
+
+fn has_loops(stmts: &Stmts) -> bool {
+    stmts.iter().any(|stmt| {
+        matches!(
+            stmt,
+            Stmt::Repeat { .. }
+                | Stmt::Forever { .. }
+                | Stmt::Until { .. }
+                | Stmt::Loop { .. }
+                | Stmt::ForEach { .. }
+        ) || stmt.bodies().into_iter().any(has_loops)
+    })
+}
+
+/// Every variable name the statements read or set, and every argument they read.
+fn stmts_names(
+    stmts: &Stmts,
+    names: &mut FxHashSet<SmolStr>,
+    args: &mut Vec<(SmolStr, Span)>,
+) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::ForEach { name, .. }
+            | Stmt::SetVar { name, .. }
+            | Stmt::ChangeVar { name, .. }
+            | Stmt::Show { name, .. }
+            | Stmt::Hide { name, .. }
+            | Stmt::Ask { name, .. } => {
+                names.insert(name.clone());
+            }
+            _ => {}
+        }
+        for expr in stmt.exprs() {
+            expr_names(expr, names, args);
+        }
+        for body in stmt.bodies() {
+            stmts_names(body, names, args);
+        }
+    }
+}
+
+fn expr_names(
+    expr: &Rrc<Expr>,
+    names: &mut FxHashSet<SmolStr>,
+    args: &mut Vec<(SmolStr, Span)>,
+) {
+    match &*expr.borrow() {
+        Expr::Name { name, .. } => {
+            names.insert(name.clone());
+        }
+        Expr::Arg { name, span } => args.push((name.clone(), span.clone())),
+        Expr::Repr { args: reprs, .. } => {
+            for arg in reprs {
+                expr_names(arg, names, args);
+            }
+        }
+        Expr::UnOp { val, .. } => expr_names(val, names, args),
+        Expr::BinOp { lhs, rhs, .. } => {
+            expr_names(lhs, names, args);
+            expr_names(rhs, names, args);
+        }
+        _ => {}
+    }
+}
+
+/// Deep copies statements, without renaming anything.
+pub fn deep_clone(stmts: &Stmts) -> Stmts {
+    clone_stmts(stmts, &Default::default())
+}
+
+/// What changes in a copy: variables which are renamed, and names which are replaced
+/// by a copy of an expression.
+#[derive(Default)]
+pub struct Substitution {
+    pub names: FxHashMap<SmolStr, SmolStr>,
+    pub exprs: FxHashMap<SmolStr, Rrc<Expr>>,
+}
+
+fn inline_variable_name(proc: &str, copy: usize, name: &str) -> SmolStr {
+    SmolStr::from(format!("__inline_{proc}_{copy}_{name}"))
+}
+
+fn rename(name: &SmolStr, s: &Substitution) -> SmolStr {
+    s.names.get(name).unwrap_or(name).clone()
+}
+
+/// Deep copies statements, so that constant folding in one copy does not change
+/// another. Variables in `s.names` are renamed, and so are arguments, which are looked
+/// up with a `$` before their name. Variables declared with `local` become sprite
+/// variables.
+pub fn clone_stmts(stmts: &Stmts, s: &Substitution) -> Stmts {
+    stmts.iter().map(|stmt| clone_stmt(stmt, s)).collect()
+}
+
+fn clone_stmt(stmt: &Stmt, s: &Substitution) -> Stmt {
+    let e = |expr: &Rrc<Expr>| clone_expr(expr, s);
+    let b = |stmts: &Stmts| clone_stmts(stmts, s);
+    match stmt {
+        Stmt::Repeat { times, span, body } => {
+            Stmt::Repeat { times: e(times), span: span.clone(), body: b(body) }
+        }
+        Stmt::Forever { body, span } => {
+            Stmt::Forever { body: b(body), span: span.clone() }
+        }
+        Stmt::Branch { cond, if_body, else_body } => {
+            Stmt::Branch { cond: e(cond), if_body: b(if_body), else_body: b(else_body) }
+        }
+        Stmt::Until { cond, span, body } => {
+            Stmt::Until { cond: e(cond), span: span.clone(), body: b(body) }
+        }
+        Stmt::Loop { body, span } => Stmt::Loop { body: b(body), span: span.clone() },
+        Stmt::Break { span } => Stmt::Break { span: span.clone() },
+        Stmt::Continue { span } => Stmt::Continue { span: span.clone() },
+        Stmt::Pass { span } => Stmt::Pass { span: span.clone() },
+        Stmt::Measure { name, span, body } => {
+            Stmt::Measure { name: name.clone(), span: span.clone(), body: b(body) }
+        }
+        Stmt::Defer { span, body } => Stmt::Defer { span: span.clone(), body: b(body) },
+        Stmt::ForEach { name, span, times, body } => Stmt::ForEach {
+            name: rename(name, s),
+            span: span.clone(),
+            times: e(times),
+            body: b(body),
+        },
+        Stmt::SetVar { name, span, value, is_local: _ } => Stmt::SetVar {
+            name: rename(name, s),
+            span: span.clone(),
+            value: e(value),
+            is_local: false,
+        },
+        Stmt::ChangeVar { name, span, value } => Stmt::ChangeVar {
+            name: rename(name, s),
+            span: span.clone(),
+            value: e(value),
+        },
+        Stmt::Show { name, span } => {
+            Stmt::Show { name: rename(name, s), span: span.clone() }
+        }
+        Stmt::Hide { name, span } => {
+            Stmt::Hide { name: rename(name, s), span: span.clone() }
+        }
+        Stmt::ListAdd { name, span, value } => {
+            Stmt::ListAdd { name: name.clone(), span: span.clone(), value: e(value) }
+        }
+        Stmt::ListDelete { name, span, index } => {
+            Stmt::ListDelete { name: name.clone(), span: span.clone(), index: e(index) }
+        }
+        Stmt::ListDeleteAll { name, span } => {
+            Stmt::ListDeleteAll { name: name.clone(), span: span.clone() }
+        }
+        Stmt::ListInsert { name, span, index, value } => Stmt::ListInsert {
+            name: name.clone(),
+            span: span.clone(),
+            index: e(index),
+            value: e(value),
+        },
+        Stmt::ListSet { name, span, index, value } => Stmt::ListSet {
+            name: name.clone(),
+            span: span.clone(),
+            index: e(index),
+            value: e(value),
+        },
+        Stmt::ListChange { op, name, span, index, value } => Stmt::ListChange {
+            op: *op,
+            name: name.clone(),
+            span: span.clone(),
+            index: e(index),
+            value: e(value),
+        },
+        Stmt::Block { block, span, args } => Stmt::Block {
+            block: *block,
+            span: span.clone(),
+            args: args.iter().map(e).collect(),
+        },
+        Stmt::ProcCall { name, span, args } => Stmt::ProcCall {
+            name: name.clone(),
+            span: span.clone(),
+            args: args.iter().map(e).collect(),
+        },
+        Stmt::Broadcast { message, span, payload, and_wait } => Stmt::Broadcast {
+            message: message.clone(),
+            span: span.clone(),
+            payload: e(payload),
+            and_wait: *and_wait,
+        },
+        Stmt::Ask { name, span, prompt, number } => Stmt::Ask {
+            name: rename(name, s),
+            span: span.clone(),
+            prompt: e(prompt),
+            number: *number,
+        },
+    }
+}
+
+/// Deep copies an expression, see [`clone_stmts`]. Names in `s.exprs` are replaced by
+/// a copy of their expression, which is not substituted itself.
+pub fn clone_expr(expr: &Rrc<Expr>, s: &Substitution) -> Rrc<Expr> {
+    let e = |expr: &Rrc<Expr>| clone_expr(expr, s);
+    match &*expr.borrow() {
+        Expr::Name { name, .. } if s.exprs.contains_key(name) => {
+            return clone_expr(&s.exprs[name], &Default::default());
+        }
+        Expr::Int(value) => Expr::Int(*value),
+        Expr::Float(value) => Expr::Float(*value),
+        Expr::Str(value) => Expr::Str(value.clone()),
+        Expr::Bool(value) => Expr::Bool(*value),
+        Expr::Name { name, span } => {
+            Expr::Name { name: rename(name, s), span: span.clone() }
+        }
+        Expr::Arg { name, span } => match s.names.get(format!("${name}").as_str()) {
+            Some(renamed) => Expr::Name { name: renamed.clone(), span: span.clone() },
+            None => Expr::Arg { name: name.clone(), span: span.clone() },
+        },
+        Expr::Repr { repr, span, args } => Expr::Repr {
+            repr: *repr,
+            span: span.clone(),
+            args: args.iter().map(e).collect(),
+        },
+        Expr::UnOp { op, val } => Expr::UnOp { op: *op, val: e(val) },
+        Expr::BinOp { op, lhs, rhs } => {
+            Expr::BinOp { op: *op, lhs: e(lhs), rhs: e(rhs) }
+        }
+        Expr::EnumVariant { enum_name, enum_span, variant_name, variant_span } => {
+            Expr::EnumVariant {
+                enum_name: enum_name.clone(),
+                enum_span: enum_span.clone(),
+                variant_name: variant_name.clone(),
+                variant_span: variant_span.clone(),
+            }
+        }
+        Expr::SpriteVar { sprite, sprite_span, name, span } => Expr::SpriteVar {
+            sprite: sprite.clone(),
+            sprite_span: sprite_span.clone(),
+            name: name.clone(),
+            span: span.clone(),
+        },
+    }
+    .into()
+}
@@ -0,0 +1,366 @@
+use fxhash::{FxHashMap, FxHashSet};
+use logos::Span;
+use smol_str::SmolStr;
+
+use crate::{
+    ast::{Expr, List, Proc, ProcKey, Project, Rrc, Sprite, Stmt, Stmts, Var},
+    blocks::BinOp,
+};
+
+/// Value of a loop's flag variable after `continue`.
+const CONTINUE: i64 = 1;
+/// Value of a loop's flag variable after `break`.
+const BREAK: i64 = 2;
+/// List which the variables of loops are saved on during calls which may reach the
+/// procedure the loop is in.
+const STACK: &str = "__loop_stack";
+
+struct L<'a> {
+    vars: &'a mut FxHashMap<SmolStr, Var>,
+    lists: &'a mut FxHashMap<SmolStr, List>,
+    count: &'a mut usize,
+    reaches: &'a FxHashMap<ProcKey, FxHashSet<ProcKey>>,
+    /// The procedure whose body is being lowered.
+    proc: Option<ProcKey>,
+}
+
+/// Scratch has no `break` or `continue`, so every loop which uses them gets a flag
+/// variable. They set the flag, and the statements after them are moved into `if`
+/// blocks which only run while it is still 0. `break` also ends the loop, by turning it
+/// into an `until` which checks the flag. `loop` becomes `forever` when it has no
+/// `break`. Any `break` or `continue` left over is outside a loop, and reported by
+/// codegen.
+///
+/// The variables of a loop belong to the sprite, so a recursive call in the loop would
+/// overwrite them. Calls which may reach the procedure the loop is in push them onto a
+/// list before, and pop them after.
+pub fn visit_project(project: &mut Project) {
+    let mut count = 0;
+    visit_sprite(&mut project.stage, &mut count);
+    for sprite in project.sprites.values_mut() {
+        visit_sprite(sprite, &mut count);
+    }
+}
+
+fn visit_sprite(sprite: &mut Sprite, count: &mut usize) {
+    let reaches = reaches(&sprite.procs);
+    let l = &mut L {
+        vars: &mut sprite.vars,
+        lists: &mut sprite.lists,
+        count,
+        reaches: &reaches,
+        proc: None,
+    };
+    for event in &mut sprite.events {
+        visit_stmts(&mut event.body, l);
+    }
+    for on_message in sprite.on_messages.iter_mut() {
+        visit_stmts(&mut on_message.body, l);
+    }
+    for proc in sprite.procs.values_mut() {
+        l.proc = Some(proc.key());
+        visit_stmts(&mut proc.body, l);
+    }
+}
+
+/// Procedures which each procedure may call, directly or through other procedures.
+fn reaches(procs: &FxHashMap<ProcKey, Proc>) -> FxHashMap<ProcKey, FxHashSet<ProcKey>> {
+    let calls: FxHashMap<_, _> = procs
+        .iter()
+        .map(|(key, proc)| {
+            let mut callees = FxHashSet::default();
+            stmts_calls(&proc.body, &mut callees);
+            (key.clone(), callees)
+        })
+        .collect();
+    calls
+        .keys()
+        .map(|key| {
+            let mut reached = FxHashSet::default();
+            let mut stack = vec![key];
+            while let Some(key) = stack.pop() {
+                for callee in calls.get(key).into_iter().flatten() {
+                    if reached.insert(callee.clone()) {
+                        stack.push(callee);
+                    }
+                }
+            }
+            (key.clone(), reached)
+        })
+        .collect()
+}
+
+fn stmts_calls(stmts: &Stmts, callees: &mut FxHashSet<ProcKey>) {
+    for stmt in stmts {
+        if let Stmt::ProcCall { name, args, .. } = stmt {
+            callees.insert((name.clone(), args.len()));
+        }
+        for body in stmt.bodies() {
+            stmts_calls(body, callees);
+        }
+    }
+}
+
+fn visit_stmts(stmts: &mut Stmts, l: &mut L<'_>) {
+    let mut i = 0;
+    while i < stmts.len() {
+        let before = lower_loop(&mut stmts[i], l);
+        match &mut stmts[i] {
+            Stmt::Repeat { body, .. }
+            | Stmt::Forever { body, .. }
+            | Stmt::Until { body, .. }
+            | Stmt::ForEach { body, .. } => visit_stmts(body, l),
+            Stmt::Branch { if_body, else_body, .. } => {
+                visit_stmts(if_body, l);
+                visit_stmts(else_body, l);
+            }
+            _ => {}
+        }
+        let len = before.len();
+        stmts.splice(i..i, before);
+        i += len + 1;
+    }
+}
+
+/// Lowers `break` and `continue` in a loop, returning the statements to run before it.
+fn lower_loop(stmt: &mut Stmt, l: &mut L<'_>) -> Stmts {
+    let (Stmt::Repeat { body, .. }
+    | Stmt::Forever { body, .. }
+    | Stmt::Until { body, .. }
+    | Stmt::ForEach { body, .. }
+    | Stmt::Loop { body, .. }) = stmt
+    else {
+        return vec![];
+    };
+    let (breaks, continues) = loop_control(body);
+    let Some(span) = breaks.clone().or(continues.clone()) else {
+        if let Stmt::Loop { body, span } = stmt {
+            *stmt = Stmt::Forever { body: std::mem::take(body), span: span.clone() };
+        }
+        return vec![];
+    };
+    let flag = l.new_var(format!("__loop_{}", l.count), &span);
+    let mut saved = vec![flag.clone()];
+    let mut body = guard(std::mem::take(body), &flag);
+    if continues.is_some() {
+        body.insert(0, set_var(&flag, &span, Expr::Int(0).into()));
+    }
+    let mut before = vec![set_var(&flag, &span, Expr::Int(0).into())];
+    *l.count += 1;
+    if breaks.is_none() {
+        match stmt {
+            Stmt::Loop { span, .. } => {
+                *stmt = Stmt::Forever { body, span: span.clone() };
+            }
+            Stmt::Repeat { body: old, .. }
+            | Stmt::Forever { body: old, .. }
+            | Stmt::Until { body: old, .. }
+            | Stmt::ForEach { body: old, .. } => *old = body,
+            _ => unreachable!(),
+        }
+        l.save_around_calls(stmt, &saved, &span);
+        return before;
+    }
+    let index = match stmt {
+        Stmt::ForEach { name, .. } => Some(name.clone()),
+        _ => None,
+    };
+    let loop_span = stmt.span().clone();
+    let broken: Rrc<Expr> =
+        BinOp::Eq.to_expr(var(&flag, &span), Expr::Int(BREAK).into()).into();
+    *stmt = match stmt {
+        Stmt::Loop { .. } | Stmt::Forever { .. } => {
+            Stmt::Until { cond: broken, span: loop_span, body }
+        }
+        Stmt::Until { cond, .. } => Stmt::Until {
+            cond: BinOp::Or.to_expr(broken, cond.clone()).into(),
+            span: loop_span,
+            body,
+        },
+        // Scratch would keep repeating after `break` until all the iterations are used
+        // up, so the iterations are counted down in a variable instead.
+        Stmt::Repeat { times, .. } | Stmt::ForEach { times, .. } => {
+            let left = l.new_var(format!("{flag}_left"), &span);
+            saved.push(left.clone());
+            if let Some(name) = index {
+                before.push(set_var(&name, &span, Expr::Int(0).into()));
+                body.insert(
+                    0,
+                    Stmt::ChangeVar {
+                        name: name.clone(),
+                        span: span.clone(),
+                        value: Expr::Int(1).into(),
+                    },
+                );
+            }
+            before.push(set_var(
+                &left,
+                &span,
+                BinOp::Add.to_expr(times.clone(), Expr::Int(0).into()).into(),
+            ));
+            body.insert(
+                0,
+                Stmt::ChangeVar {
+                    name: left.clone(),
+                    span: span.clone(),
+                    value: Expr::Int(-1).into(),
+                },
+            );
+            let done = BinOp::Lt.to_expr(var(&left, &span), Expr::Float(0.5).into());
+            Stmt::Until {
+                cond: BinOp::Or.to_expr(broken, done.into()).into(),
+                span: loop_span,
+                body,
+            }
+        }
+        _ => unreachable!(),
+    };
+    l.save_around_calls(stmt, &saved, &span);
+    before
+}
+
+impl L<'_> {
+    fn new_var(&mut self, name: String, span: &Span) -> SmolStr {
+        let name = SmolStr::from(name);
+        let mut var = Var::new(name.clone(), span.clone(), None);
+        var.used = true;
+        self.vars.insert(name.clone(), var);
+        name
+    }
+
+    fn save_around_calls(&mut self, stmt: &mut Stmt, saved: &[SmolStr], span: &Span) {
+        for body in stmt.bodies_mut() {
+            self.save_stmts(body, saved, span);
+        }
+    }
+
+    fn save_stmts(&mut self, stmts: &mut Stmts, saved: &[SmolStr], span: &Span) {
+        let mut i = 0;
+        while i < stmts.len() {
+            self.save_around_calls(&mut stmts[i], saved, span);
+            let Stmt::ProcCall { name, args, .. } = &stmts[i] else {
+                i += 1;
+                continue;
+            };
+            let key = (name.clone(), args.len());
+            let reenters = self.proc.as_ref().is_some_and(|proc| {
+                key == *proc
+                    || self.reaches.get(&key).is_some_and(|it| it.contains(proc))
+            });
+            if !reenters {
+                i += 1;
+                continue;
+            }
+            let stack = SmolStr::from(STACK);
+            self.lists.entry(stack.clone()).or_insert_with(|| {
+                let mut list = List::new(stack.clone(), span.clone(), vec![]);
+                list.used = true;
+                list
+            });
+            let push: Stmts = saved
+                .iter()
+                .map(|name| Stmt::ListAdd {
+                    name: stack.clone(),
+                    span: span.clone(),
+                    value: var(name, span),
+                })
+                .collect();
+            let last = || -> Rrc<Expr> { Expr::Str("last".into()).into() };
+            let pop: Stmts = saved
+                .iter()
+                .rev()
+                .flat_map(|name| {
+                    let item = BinOp::Of.to_expr(var(&stack, span), last());
+                    [
+                        set_var(name, span, item.into()),
+                        Stmt::ListDelete {
+                            name: stack.clone(),
+                            span: span.clone(),
+                            index: last(),
+                        },
+                    ]
+                })
+                .collect();
+            let len = push.len() + pop.len();
+            stmts.splice(i + 1..i + 1, pop);
+            stmts.splice(i..i, push);
+            i += len + 1;
+        }
+    }
+}
+
+/// Finds the first `break` and `continue` which belong to the loop with this body.
+pub fn loop_control(stmts: &Stmts) -> (Option<Span>, Option<Span>) {
+    let mut found = (None, None);
+    for stmt in stmts {
+        let (breaks, continues) = match stmt {
+            Stmt::Break { span } => (Some(span.clone()), None),
+            Stmt::Continue { span } => (None, Some(span.clone())),
+            Stmt::Branch { if_body, else_body, .. } => {
+                let (if_breaks, if_continues) = loop_control(if_body);
+                let (else_breaks, else_continues) = loop_control(else_body);
+                (if_breaks.or(else_breaks), if_continues.or(else_continues))
+            }
+            _ => (None, None),
+        };
+        found.0 = found.0.or(breaks);
+        found.1 = found.1.or(continues);
+    }
+    found
+}
+
+/// Replaces `break` and `continue` with setting `flag`. Statements after them are
+/// dropped, and statements after an `if` which contains them are only run if `flag` is
+/// still 0.
+fn guard(stmts: Stmts, flag: &SmolStr) -> Stmts {
+    let mut guarded = vec![];
+    let mut stmts = stmts.into_iter();
+    while let Some(stmt) = stmts.next() {
+        match stmt {
+            Stmt::Break { span } => {
+                guarded.push(set_var(flag, &span, Expr::Int(BREAK).into()));
+                break;
+            }
+            Stmt::Continue { span } => {
+                guarded.push(set_var(flag, &span, Expr::Int(CONTINUE).into()));
+                break;
+            }
+            Stmt::Branch { cond, if_body, else_body } => {
+                let (if_breaks, if_continues) = loop_control(&if_body);
+                let (else_breaks, else_continues) = loop_control(&else_body);
+                let Some(span) =
+                    if_breaks.or(if_continues).or(else_breaks).or(else_continues)
+                else {
+                    guarded.push(Stmt::Branch { cond, if_body, else_body });
+                    continue;
+                };
+                guarded.push(Stmt::Branch {
+                    cond,
+                    if_body: guard(if_body, flag),
+                    else_body: guard(else_body, flag),
+                });
+                let rest = guard(stmts.collect(), flag);
+                if !rest.is_empty() {
+                    guarded.push(Stmt::Branch {
+                        cond: BinOp::Eq
+                            .to_expr(var(flag, &span), Expr::Int(0).into())
+                            .into(),
+                        if_body: rest,
+                        else_body: vec![],
+                    });
+                }
+                break;
+            }
+            stmt => guarded.push(stmt),
+        }
+    }
+    guarded
+}
+
+fn var(name: &SmolStr, span: &Span) -> Rrc<Expr> {
+    Expr::Name { name: name.clone(), span: span.clone() }.into()
+}
+
+fn set_var(name: &SmolStr, span: &Span, value: Rrc<Expr>) -> Stmt {
+    Stmt::SetVar { name: name.clone(), span: span.clone(), value, is_local: false }
+}
@@ -0,0 +1,87 @@
+use fxhash::FxHashMap;
+use smol_str::SmolStr;
+
+use crate::{
+    ast::{measure_variable_name, Expr, Project, Rrc, Sprite, Stmt, Stmts, Var},
+    blocks::{BinOp, Repr},
+};
+
+/// Replaces each `measure` with its body, between two reads of the timer. The time
+/// the body took is stored in `__measure_<name>`, and the timer before it in
+/// `__measure_<name>_start`. In `release` builds only the body is kept.
+pub fn visit_project(project: &mut Project, release: bool) {
+    visit_sprite(&mut project.stage, release);
+    for sprite in project.sprites.values_mut() {
+        visit_sprite(sprite, release);
+    }
+}
+
+fn visit_sprite(sprite: &mut Sprite, release: bool) {
+    let vars = &mut sprite.vars;
+    for event in &mut sprite.events {
+        visit_stmts(&mut event.body, vars, release);
+    }
+    for on_message in sprite.on_messages.iter_mut() {
+        visit_stmts(&mut on_message.body, vars, release);
+    }
+    for proc in sprite.procs.values_mut() {
+        visit_stmts(&mut proc.body, vars, release);
+    }
+}
+
+fn visit_stmts(stmts: &mut Stmts, vars: &mut FxHashMap<SmolStr, Var>, release: bool) {
+    let mut i = 0;
+    while i < stmts.len() {
+        match &mut stmts[i] {
+            Stmt::Repeat { body, .. }
+            | Stmt::Forever { body, .. }
+            | Stmt::Until { body, .. }
+            | Stmt::Loop { body, .. }
+            | Stmt::Defer { body, .. }
+            | Stmt::ForEach { body, .. } => visit_stmts(body, vars, release),
+            Stmt::Branch { if_body, else_body, .. } => {
+                visit_stmts(if_body, vars, release);
+                visit_stmts(else_body, vars, release);
+            }
+            Stmt::Measure { name, span, body } => {
+                let mut body = std::mem::take(body);
+                visit_stmts(&mut body, vars, release);
+                if !release {
+                    let elapsed = measure_variable_name(name);
+                    let start = SmolStr::from(format!("{elapsed}_start"));
+                    for name in [&elapsed, &start] {
+                        let mut var = Var::new(name.clone(), span.clone(), None);
+                        var.used = true;
+                        vars.insert(name.clone(), var);
+                    }
+                    let set = |name: &SmolStr, value: Rrc<Expr>| Stmt::SetVar {
+                        name: name.clone(),
+                        span: span.clone(),
+                        value,
+                        is_local: false,
+                    };
+                    let timer = || -> Rrc<Expr> {
+                        Expr::Repr {
+                            repr: Repr::Timer,
+                            span: span.clone(),
+                            args: vec![],
+                        }
+                        .into()
+                    };
+                    let since_start = BinOp::Sub.to_expr(
+                        timer(),
+                        Expr::Name { name: start.clone(), span: span.clone() }.into(),
+                    );
+                    body.insert(0, set(&start, timer()));
+                    body.push(set(&elapsed, since_start.into()));
+                }
+                let len = body.len();
+                stmts.splice(i..=i, body);
+                i += len;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
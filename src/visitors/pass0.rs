@@ -19,6 +19,7 @@ fn visit_stmt(stmt: &mut Stmt, locals: &mut FxHashMap<SmolStr, Var>) {
             locals.insert(name.clone(), Var::new(name.clone(), span.clone(), None));
         }
         Stmt::Until { body, .. }
+        | Stmt::ForEach { body, .. }
         | Stmt::Forever { body, .. }
         | Stmt::Repeat { body, .. } => {
             visit_stmts(body, locals);
@@ -1,18 +1,20 @@
 use fxhash::FxHashMap;
 use smol_str::SmolStr;
 
-use super::pass0;
+use super::{inline::deep_clone, pass0};
 use crate::{
     ast::{
-        Enum, Event, Expr, List, OnMessage, Proc, Project, References, Rrc, Sprite,
-        Stmt, Var,
+        payload_variable_name, Enum, Event, EventDetail, Expr, List, OnMessage, Proc,
+        Project, References, Rrc, Sprite, Stmt, Stmts, Var, CLONE_COUNTER,
     },
-    blocks::{BinOp, UnOp},
+    blocks::{BinOp, Block, Repr, UnOp},
+    config::Target,
 };
 
 struct V<'a> {
     references: &'a mut References,
     used_args: Option<&'a mut FxHashMap<SmolStr, bool>>,
+    payload: Option<(&'a SmolStr, SmolStr)>,
 }
 
 struct S<'a> {
@@ -21,27 +23,41 @@ struct S<'a> {
     enums: &'a FxHashMap<SmolStr, Enum>,
     global_vars: Option<&'a FxHashMap<SmolStr, Var>>,
     global_lists: Option<&'a FxHashMap<SmolStr, List>>,
+    constants: &'a FxHashMap<SmolStr, SmolStr>,
+    target: Target,
+    clone_counter: bool,
 }
 
-pub fn visit_project(project: &mut Project) {
-    visit_sprite(&mut project.stage, None);
+pub fn visit_project(project: &mut Project, target: Target, clone_counter: bool) {
+    let constants = &project.constants;
+    visit_sprite(&mut project.stage, None, constants, target, clone_counter);
     for sprite in project.sprites.values_mut() {
-        visit_sprite(sprite, Some(&project.stage));
+        visit_sprite(sprite, Some(&project.stage), constants, target, clone_counter);
     }
 }
 
-fn visit_sprite(sprite: &mut Sprite, stage: Option<&Sprite>) {
+fn visit_sprite(
+    sprite: &mut Sprite,
+    stage: Option<&Sprite>,
+    constants: &FxHashMap<SmolStr, SmolStr>,
+    target: Target,
+    clone_counter: bool,
+) {
     let s = &mut S {
         vars: &sprite.vars,
         lists: &sprite.lists,
         enums: &sprite.enums,
         global_vars: stage.map(|s| &s.vars),
         global_lists: stage.map(|s| &s.lists),
+        constants,
+        target,
+        clone_counter,
     };
+    prepend_init(&mut sprite.events, std::mem::take(&mut sprite.init));
     for event in &mut sprite.events {
         visit_event(event, s);
     }
-    for on_message in sprite.on_messages.values_mut() {
+    for on_message in sprite.on_messages.iter_mut() {
         visit_on_message(on_message, s);
     }
     for proc in sprite.procs.values_mut() {
@@ -49,6 +65,25 @@ fn visit_sprite(sprite: &mut Sprite, stage: Option<&Sprite>) {
     }
 }
 
+/// Scratch does not run green flag scripts in a defined order, so `init` is copied to
+/// the start of every green flag script of the sprite.
+fn prepend_init(events: &mut Vec<Event>, init: Stmts) {
+    if init.is_empty() {
+        return;
+    }
+    let mut flags = events
+        .iter_mut()
+        .filter(|event| matches!(event.kind, EventDetail::OnFlag { .. }))
+        .peekable();
+    if flags.peek().is_none() {
+        events.push(EventDetail::OnFlag { priority: 0 }.to_event(0..0, init));
+        return;
+    }
+    for event in flags {
+        event.body.splice(0..0, deep_clone(&init));
+    }
+}
+
 fn visit_proc(proc: &mut Proc, s: &mut S<'_>) {
     pass0::visit_proc(proc);
     visit_stmts(
@@ -56,60 +91,172 @@ fn visit_proc(proc: &mut Proc, s: &mut S<'_>) {
         &mut V {
             references: &mut proc.references,
             used_args: Some(&mut proc.used_args),
+            payload: None,
         },
         s,
     );
 }
 
 fn visit_event(event: &mut Event, s: &mut S<'_>) {
-    visit_stmts(
-        &mut event.body,
-        &mut V { references: &mut event.references, used_args: None },
-        s,
-    );
+    let v =
+        &mut V { references: &mut event.references, used_args: None, payload: None };
+    if let EventDetail::OnLoudnessGt { value } | EventDetail::OnTimerGt { value } =
+        &mut event.kind
+    {
+        visit_expr(value, v, s);
+    }
+    visit_stmts(&mut event.body, v, s);
 }
 
 fn visit_on_message(on_message: &mut OnMessage, s: &mut S<'_>) {
     visit_stmts(
         &mut on_message.body,
-        &mut V { references: &mut on_message.references, used_args: None },
+        &mut V {
+            references: &mut on_message.references,
+            used_args: None,
+            payload: on_message
+                .payload
+                .as_ref()
+                .map(|(arg, _)| (arg, payload_variable_name(&on_message.message))),
+        },
         s,
     );
 }
 
 fn visit_stmts(stmts: &mut Vec<Stmt>, v: &mut V<'_>, s: &mut S<'_>) {
-    for stmt in stmts {
+    if s.target == Target::Scratch {
+        let mut i = 0;
+        while i < stmts.len() {
+            if let Stmt::ForEach { name, span, times, body } = &mut stmts[i] {
+                let reset_index = Stmt::SetVar {
+                    name: name.clone(),
+                    span: span.clone(),
+                    value: Expr::Int(0).into(),
+                    is_local: false,
+                };
+                let mut body = std::mem::take(body);
+                body.insert(
+                    0,
+                    Stmt::ChangeVar {
+                        name: name.clone(),
+                        span: span.clone(),
+                        value: Expr::Int(1).into(),
+                    },
+                );
+                let repeat =
+                    Stmt::Repeat { times: times.clone(), span: span.clone(), body };
+                stmts.splice(i..=i, [reset_index, repeat]);
+                i += 1;
+            }
+            i += 1;
+        }
+    }
+    for stmt in stmts.iter_mut() {
         visit_stmt(stmt, v, s);
     }
+    let mut i = 0;
+    while i < stmts.len() {
+        if let Stmt::Broadcast { message, span, payload, and_wait } = &stmts[i] {
+            let set_payload = Stmt::SetVar {
+                name: payload_variable_name(message),
+                span: span.clone(),
+                value: payload.clone(),
+                is_local: false,
+            };
+            let broadcast = Stmt::Block {
+                block: if *and_wait {
+                    Block::BroadcastAndWait
+                } else {
+                    Block::Broadcast
+                },
+                span: span.clone(),
+                args: vec![Expr::Str(message.clone()).into()],
+            };
+            stmts.splice(i..=i, [set_payload, broadcast]);
+            i += 1;
+        } else if let Stmt::Ask { name, span, prompt, number } = &stmts[i] {
+            let ask = || Stmt::Block {
+                block: Block::Ask,
+                span: span.clone(),
+                args: vec![prompt.clone()],
+            };
+            let answer = || -> Rrc<Expr> {
+                Expr::Repr { repr: Repr::Answer, span: span.clone(), args: vec![] }
+                    .into()
+            };
+            let mut lowered = vec![ask()];
+            if *number {
+                // Adding 0 to anything which is not a number gives 0, so it would no
+                // longer be equal to the answer.
+                let is_number = BinOp::Eq.to_expr(
+                    answer(),
+                    BinOp::Add.to_expr(answer(), Expr::Int(0).into()).into(),
+                );
+                lowered.push(Stmt::Until {
+                    cond: is_number.into(),
+                    span: span.clone(),
+                    body: vec![ask()],
+                });
+            }
+            lowered.push(Stmt::SetVar {
+                name: name.clone(),
+                span: span.clone(),
+                value: answer(),
+                is_local: false,
+            });
+            let len = lowered.len();
+            stmts.splice(i..=i, lowered);
+            i += len - 1;
+        } else if let Stmt::Block { block, span, .. } = &stmts[i] {
+            let change = match block {
+                Block::Clone0 | Block::Clone1 => 1,
+                Block::DeleteThisClone => -1,
+                _ => 0,
+            };
+            if s.clone_counter && change != 0 {
+                let change_counter = Stmt::ChangeVar {
+                    name: CLONE_COUNTER.into(),
+                    span: span.clone(),
+                    value: Expr::Int(change).into(),
+                };
+                stmts.insert(i, change_counter);
+                i += 1;
+            }
+        }
+        i += 1;
+    }
 }
 
 fn visit_stmt(stmt: &mut Stmt, v: &mut V<'_>, s: &mut S<'_>) {
     match stmt {
-        Stmt::Repeat { times, body } => {
+        Stmt::Repeat { times, body, .. } => {
             visit_expr(times, v, s);
-            for stmt in body {
-                visit_stmt(stmt, v, s);
+            // Scratch rounds the count, halves are rounded up.
+            let rounded = match &*times.borrow() {
+                Expr::Float(value) if value.is_finite() => Some((value + 0.5).floor()),
+                _ => None,
+            };
+            if let Some(rounded) = rounded {
+                *times = Expr::Int(rounded as i64).into();
             }
+            visit_stmts(body, v, s);
         }
         Stmt::Forever { body, span: _ } => {
-            for stmt in body {
-                visit_stmt(stmt, v, s);
-            }
+            visit_stmts(body, v, s);
         }
         Stmt::Branch { cond, if_body, else_body } => {
-            visit_expr(cond, v, s);
-            for stmt in if_body {
-                visit_stmt(stmt, v, s);
-            }
-            for stmt in else_body {
-                visit_stmt(stmt, v, s);
-            }
+            visit_condition(cond, v, s);
+            visit_stmts(if_body, v, s);
+            visit_stmts(else_body, v, s);
         }
-        Stmt::Until { cond, body } => {
-            visit_expr(cond, v, s);
-            for stmt in body {
-                visit_stmt(stmt, v, s);
-            }
+        Stmt::Until { cond, body, .. } => {
+            visit_condition(cond, v, s);
+            visit_stmts(body, v, s);
+        }
+        Stmt::ForEach { name, span: _, times, body } => {
+            v.references.vars.insert(name.clone());
+            visit_expr(times, v, s);
+            visit_stmts(body, v, s);
         }
         Stmt::SetVar { value, .. } => {
             // v.references.vars.insert(name.clone());
@@ -149,24 +296,55 @@ fn visit_stmt(stmt: &mut Stmt, v: &mut V<'_>, s: &mut S<'_>) {
             visit_expr(index, v, s);
             visit_expr(value, v, s);
         }
-        Stmt::Block { block: _, span: _, args } => {
-            for arg in args {
-                visit_expr(arg, v, s);
+        Stmt::Block { block, span: _, args } => {
+            for (i, arg) in args.iter_mut().enumerate() {
+                if block.args().get(i) == Some(&"CONDITION") {
+                    visit_condition(arg, v, s);
+                } else {
+                    visit_expr(arg, v, s);
+                }
             }
         }
         Stmt::ProcCall { name, span: _, args } => {
-            v.references.procs.insert(name.clone());
+            v.references.procs.insert((name.clone(), args.len()));
             for arg in args {
                 visit_expr(arg, v, s);
             }
         }
+        Stmt::Broadcast { payload, .. } => {
+            visit_expr(payload, v, s);
+        }
+        Stmt::Ask { prompt, .. } => {
+            visit_expr(prompt, v, s);
+        }
+        Stmt::Break { .. } | Stmt::Continue { .. } | Stmt::Pass { .. } => {}
+        Stmt::Loop { .. } | Stmt::Measure { .. } | Stmt::Defer { .. } => unreachable!(),
     }
 }
 
+/// Visits an expression in a boolean input. Scratch has no boolean literals, so `true`
+/// and `false` become `1 = 1` and `1 = 0` here, and `1` and `0` everywhere else.
+fn visit_condition(expr: &mut Rrc<Expr>, v: &mut V<'_>, s: &mut S<'_>) {
+    let value = match &*expr.borrow() {
+        Expr::Bool(value) => Some(*value),
+        _ => None,
+    };
+    if let Some(value) = value {
+        *expr = BinOp::Eq
+            .to_expr(Expr::Int(1).into(), Expr::Int(value as i64).into())
+            .into();
+        return;
+    }
+    visit_expr(expr, v, s);
+}
+
 fn visit_expr(expr: &mut Rrc<Expr>, v: &mut V<'_>, s: &mut S<'_>) {
     let mut replace: Option<Rrc<Expr>> = None;
     match &mut *expr.borrow_mut() {
         Expr::Int(_) => {}
+        Expr::Bool(value) => {
+            replace = Some(Expr::Int(*value as i64).into());
+        }
         Expr::Float(_) => {}
         Expr::Str(_) => {}
         Expr::EnumVariant { enum_name, variant_name, .. } => {
@@ -176,6 +354,18 @@ fn visit_expr(expr: &mut Rrc<Expr>, v: &mut V<'_>, s: &mut S<'_>) {
                     .insert((enum_name.clone(), variant_name.clone()));
             }
         }
+        Expr::SpriteVar { sprite, name, span, .. } => {
+            if s.global_vars.unwrap_or(s.vars).contains_key(name) {
+                v.references.vars.insert(name.clone());
+                replace =
+                    Some(Expr::Name { name: name.clone(), span: span.clone() }.into());
+            } else {
+                v.references.sprite_vars.insert((sprite.clone(), name.clone()));
+            }
+        }
+        Expr::Name { name, .. } if s.constants.contains_key(name) => {
+            replace = Some(Expr::Str(s.constants[name].clone()).into());
+        }
         Expr::Name { name, .. } => {
             if s.vars.contains_key(name)
                 || s.global_vars.is_some_and(|it| it.contains_key(name))
@@ -185,7 +375,16 @@ fn visit_expr(expr: &mut Rrc<Expr>, v: &mut V<'_>, s: &mut S<'_>) {
                 v.references.lists.insert(name.clone());
             }
         }
-        Expr::Arg { name, .. } => {
+        Expr::Arg { name, span } => {
+            if let Some((arg, variable)) = &v.payload {
+                if name == *arg {
+                    v.references.vars.insert(variable.clone());
+                    replace = Some(
+                        Expr::Name { name: variable.clone(), span: span.clone() }
+                            .into(),
+                    );
+                }
+            }
             if let Some(used_args) = &mut v.used_args {
                 if let Some(arg) = used_args.get_mut(name) {
                     *arg = true;
@@ -198,7 +397,11 @@ fn visit_expr(expr: &mut Rrc<Expr>, v: &mut V<'_>, s: &mut S<'_>) {
             }
         }
         Expr::UnOp { op, val } => {
-            visit_expr(val, v, s);
+            if matches!(op, UnOp::Not) {
+                visit_condition(val, v, s);
+            } else {
+                visit_expr(val, v, s);
+            }
             match op {
                 UnOp::Minus => match &mut *val.borrow_mut() {
                     Expr::Int(value) => {
@@ -229,8 +432,13 @@ fn visit_expr(expr: &mut Rrc<Expr>, v: &mut V<'_>, s: &mut S<'_>) {
             }
         }
         Expr::BinOp { op, lhs, rhs } => {
-            visit_expr(lhs, v, s);
-            visit_expr(rhs, v, s);
+            if matches!(op, BinOp::And | BinOp::Or) {
+                visit_condition(lhs, v, s);
+                visit_condition(rhs, v, s);
+            } else {
+                visit_expr(lhs, v, s);
+                visit_expr(rhs, v, s);
+            }
             match op {
                 BinOp::Of => {
                     if let Expr::Name { name, .. } = &*lhs.borrow() {
@@ -298,6 +506,61 @@ fn visit_expr(expr: &mut Rrc<Expr>, v: &mut V<'_>, s: &mut S<'_>) {
                     }
                     _ => {}
                 },
+                BinOp::Mod => match (&mut *lhs.borrow_mut(), &mut *rhs.borrow_mut()) {
+                    (Expr::Int(lval), Expr::Int(rval)) if *rval != 0 => {
+                        *lval = scratch_mod(*lval as f64, *rval as f64) as i64;
+                        replace = Some(lhs.clone());
+                    }
+                    (Expr::Int(lval), Expr::Float(rval)) if *rval != 0.0 => {
+                        *rval = scratch_mod(*lval as f64, *rval);
+                        replace = Some(rhs.clone());
+                    }
+                    (Expr::Float(lval), Expr::Float(rval)) if *rval != 0.0 => {
+                        *lval = scratch_mod(*lval, *rval);
+                        replace = Some(lhs.clone());
+                    }
+                    (Expr::Float(lval), Expr::Int(rval)) if *rval != 0 => {
+                        *lval = scratch_mod(*lval, *rval as f64);
+                        replace = Some(lhs.clone());
+                    }
+                    _ => {}
+                },
+                // Joining is associative, so literals next to each other in a chain of
+                // joins can be merged, even if they are not in the same join.
+                BinOp::Join => match (&mut *lhs.borrow_mut(), &mut *rhs.borrow_mut()) {
+                    (Expr::Str(lval), Expr::Str(rval)) => {
+                        *lval = format!("{lval}{rval}").into();
+                        replace = Some(lhs.clone());
+                    }
+                    (
+                        Expr::BinOp { op: BinOp::Join, rhs: inner, .. },
+                        Expr::Str(rval),
+                    ) => {
+                        if let Expr::Str(inner) = &mut *inner.borrow_mut() {
+                            *inner = format!("{inner}{rval}").into();
+                            replace = Some(lhs.clone());
+                        }
+                    }
+                    (
+                        Expr::Str(lval),
+                        Expr::BinOp { op: BinOp::Join, lhs: inner, .. },
+                    ) => {
+                        if let Expr::Str(inner) = &mut *inner.borrow_mut() {
+                            *inner = format!("{lval}{inner}").into();
+                            replace = Some(rhs.clone());
+                        }
+                    }
+                    _ => {}
+                },
+                BinOp::FloorDiv => {
+                    replace = Some(
+                        UnOp::Floor
+                            .to_expr(
+                                BinOp::Div.to_expr(lhs.clone(), rhs.clone()).into(),
+                            )
+                            .into(),
+                    )
+                }
                 BinOp::Le => {
                     replace = Some(
                         UnOp::Not
@@ -327,3 +590,14 @@ fn visit_expr(expr: &mut Rrc<Expr>, v: &mut V<'_>, s: &mut S<'_>) {
         *expr = replace;
     }
 }
+
+/// Scratch's `mod` takes the sign of the divisor, unlike Rust's `%` which takes the sign
+/// of the dividend.
+fn scratch_mod(lhs: f64, rhs: f64) -> f64 {
+    let result = lhs % rhs;
+    if result / rhs < 0.0 {
+        result + rhs
+    } else {
+        result
+    }
+}
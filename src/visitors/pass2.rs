@@ -1,10 +1,10 @@
 use fxhash::{FxHashMap, FxHashSet};
 use smol_str::SmolStr;
 
-use crate::ast::{Enum, List, Proc, Project, References, Var};
+use crate::ast::{Enum, List, Proc, ProcKey, Project, References, Sprite, Var};
 
 struct Scope<'a> {
-    used_procs: &'a mut FxHashSet<SmolStr>,
+    used_procs: &'a mut FxHashSet<ProcKey>,
     vars: &'a mut FxHashMap<SmolStr, Var>,
     lists: &'a mut FxHashMap<SmolStr, List>,
     enums: &'a mut FxHashMap<SmolStr, Enum>,
@@ -27,6 +27,20 @@ pub fn visit_project(project: &mut Project) {
             &event.references,
         );
     }
+    for on_message in project.stage.on_messages.iter() {
+        resolve_references(
+            &mut Scope {
+                used_procs: &mut project.stage.used_procs,
+                vars: &mut project.stage.vars,
+                lists: &mut project.stage.lists,
+                enums: &mut project.stage.enums,
+                global_vars: None,
+                global_lists: None,
+            },
+            &project.stage.procs,
+            &on_message.references,
+        );
+    }
     for sprite in project.sprites.values_mut() {
         for event in &sprite.events {
             resolve_references(
@@ -42,12 +56,47 @@ pub fn visit_project(project: &mut Project) {
                 &event.references,
             );
         }
+        for on_message in sprite.on_messages.iter() {
+            resolve_references(
+                &mut Scope {
+                    used_procs: &mut sprite.used_procs,
+                    vars: &mut sprite.vars,
+                    lists: &mut sprite.lists,
+                    enums: &mut sprite.enums,
+                    global_vars: Some(&mut project.stage.vars),
+                    global_lists: Some(&mut project.stage.lists),
+                },
+                &sprite.procs,
+                &on_message.references,
+            );
+        }
     }
+    let sprite_vars: Vec<(SmolStr, SmolStr)> = std::iter::once(&project.stage)
+        .chain(project.sprites.values())
+        .flat_map(all_references)
+        .flat_map(|references| references.sprite_vars.iter().cloned())
+        .collect();
+    for (sprite, name) in sprite_vars {
+        if let Some(var) =
+            project.sprites.get_mut(&sprite).and_then(|it| it.vars.get_mut(&name))
+        {
+            var.used = true;
+        }
+    }
+}
+
+fn all_references(sprite: &Sprite) -> impl Iterator<Item = &References> {
+    sprite
+        .events
+        .iter()
+        .map(|event| &event.references)
+        .chain(sprite.on_messages.iter().map(|on_message| &on_message.references))
+        .chain(sprite.procs.values().map(|proc| &proc.references))
 }
 
 fn resolve_references(
     scope: &mut Scope,
-    procs: &FxHashMap<SmolStr, Proc>,
+    procs: &FxHashMap<ProcKey, Proc>,
     references: &References,
 ) {
     for var in &references.vars {
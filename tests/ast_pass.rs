@@ -0,0 +1,80 @@
+use std::{env, fs, process};
+
+use fxhash::FxHashMap;
+use goboscript::{
+    ast::{Expr, Project, Stmt, Stmts},
+    cli::Emit,
+    diagnostic::Diagnostic,
+    frontend::build::{build, BuildOptions},
+    visitors::AstPass,
+};
+use smol_str::SmolStr;
+
+/// Renames the variable `score` of every sprite to `points`.
+struct Rename;
+
+impl AstPass for Rename {
+    fn run(
+        &self,
+        project: &mut Project,
+        _stage_diags: &mut Vec<Diagnostic>,
+        _diags: &mut FxHashMap<SmolStr, Vec<Diagnostic>>,
+    ) {
+        for sprite in project.sprites.values_mut() {
+            if let Some(mut var) = sprite.vars.remove("score") {
+                var.name = "points".into();
+                sprite.vars.insert(var.name.clone(), var);
+            }
+            for event in &mut sprite.events {
+                rename_stmts(&mut event.body);
+            }
+        }
+    }
+}
+
+fn rename_stmts(stmts: &mut Stmts) {
+    for stmt in stmts {
+        for expr in stmt.exprs() {
+            if let Expr::Name { name, .. } = &mut *expr.borrow_mut() {
+                rename(name);
+            }
+        }
+        if let Stmt::SetVar { name, .. } | Stmt::ChangeVar { name, .. } = stmt {
+            rename(name);
+        }
+        for body in stmt.bodies_mut() {
+            rename_stmts(body);
+        }
+    }
+}
+
+fn rename(name: &mut SmolStr) {
+    if name == "score" {
+        *name = "points".into();
+    }
+}
+
+#[test]
+fn pass_renames_variable() {
+    let dir = env::temp_dir().join(format!("goboscript_ast_pass_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let single = dir.join("main.gs");
+    fs::write(
+        &single,
+        "var score = 0;\n\nonflag {\n    score += 1;\n    say score;\n}\n",
+    )
+    .unwrap();
+    let output = dir.join("project.json");
+    build(BuildOptions {
+        single: Some(single),
+        output: Some(output.clone()),
+        emit: Emit::ProjectJson,
+        passes: vec![Box::new(Rename)],
+        ..Default::default()
+    })
+    .unwrap();
+    let json = fs::read_to_string(&output).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(json.contains("\"points\""), "{json}");
+    assert!(!json.contains("\"score\""), "{json}");
+}
@@ -1,4 +1,5 @@
 costumes "blank.svg";
+raw_json "extra.json";
 
 onflag {
     foo = 1;
@@ -20,3 +21,196 @@ onflag {
         stop_all;
     }
 }
+
+onclone {
+    say clone_count;
+    delete_this_clone;
+}
+
+onclick {
+    repeat 3 {}
+    repeat 2 {
+        pass;
+    }
+    # Scratch rounds the count, so this repeats 3 times, and 2.5 would too.
+    repeat 2.6 {
+        say "three times";
+    }
+    if foo > 5 {
+        pass;
+    }
+    else {
+        say "small";
+    }
+    repeat forever {
+        wait 1;
+        pass;
+    }
+}
+
+on "count" {
+    n = 0;
+    loop {
+        n += 1;
+        if n > 10 {
+            break;
+        }
+        if n % 2 == 0 {
+            continue;
+        }
+        say n;
+    }
+    repeat 100 {
+        if n > 20 {
+            break;
+        }
+        n += 1;
+    }
+}
+
+# Clones receive broadcasts too, so each clone clones itself again.
+on "spawn" {
+    clone;
+}
+
+# The inner loop never ends, so the outer loop only runs once.
+onkey "up arrow" {
+    forever {
+        say "outer";
+        if key_pressed("space") {
+            forever {
+                say "inner";
+            }
+        }
+    }
+}
+
+# The outer loop can be escaped, so this is not warned about.
+onkey "down arrow" {
+    forever {
+        if key_pressed("space") {
+            stop_this_script;
+        }
+        forever {
+            say "inner";
+        }
+    }
+}
+
+# The outer loop can be escaped through `leave`, so this is not warned about.
+onkey "page up" {
+    forever {
+        leave;
+        forever {
+            say "inner";
+        }
+    }
+}
+
+proc leave {
+    if key_pressed("space") {
+        delete_this_clone;
+    }
+}
+
+# Warns, `stop_this_script` only returns from `skip`, so the outer loop cannot be escaped.
+onkey "page down" {
+    forever {
+        skip;
+        forever {
+            say "inner";
+        }
+    }
+}
+
+proc skip {
+    if key_pressed("space") {
+        stop_this_script;
+    }
+}
+
+# Says 1, 1, 1, 2 three times, as the recursive calls do not overwrite the flag of the
+# loop or the iterations it has left.
+onkey "home" {
+    tree 2;
+}
+
+proc tree n {
+    repeat 3 {
+        if $n > 0 {
+            tree $n - 1;
+        }
+        if $n == 0 {
+            break;
+        }
+        say $n;
+    }
+}
+
+# "done" is said before `stop_this_script` and at the end, "next" is said before
+# `continue` and at the end of each iteration.
+onkey "left arrow" {
+    defer say "done";
+    if key_pressed("space") {
+        stop_this_script;
+    }
+    repeat 3 {
+        defer {
+            say "next";
+        }
+        if key_pressed("up arrow") {
+            continue;
+        }
+        say "step";
+    }
+}
+
+# Warns, `indent_style = "spaces"` is set in `goboscript.toml`.
+onkey "right arrow" {
+	say "indented with a tab";
+}
+
+onkey "space" {
+    yield;
+    # Warns, `yield;` says the same.
+    wait 0;
+    wait 0.5;
+}
+
+# Counts with the counter blocks under `--target turbowarp`, as `native_counter = true`
+# is set in `goboscript.toml`, and with a variable under `--target scratch`. The outer
+# loop keeps its variable under both, as the inner loop uses the counter.
+onkey "down arrow" {
+    repeat 3 as row {
+        repeat 4 as column {
+            say row * 10 + column;
+        }
+    }
+    # Keeps its variable, as the scripts which receive "count" may use the counter.
+    repeat 2 as lap {
+        broadcast_and_wait "count";
+        say lap;
+    }
+    # Keeps its variable under `--debug-overlay`, which shows it as a monitor.
+    repeat 5 as shown {
+        say shown;
+    }
+    # Keeps its variable, as it is read after the loop, where it must be 10.
+    repeat 10 as steps {
+        say steps;
+    }
+    say steps;
+}
+
+# The inner loop has its own index, so the outer loop still goes through every letter.
+# Under `--target turbowarp`, the inner loop counts with the counter.
+onkey "enter" {
+    delete letters;
+    add "a" to letters;
+    add "b" to letters;
+    for letter in letters {
+        for letter in letters {
+            say letter;
+        }
+    }
+}
@@ -0,0 +1,7 @@
+# expect: --debug-overlay adds a global variable `fps`
+# args: --debug-overlay
+var fps = 30;
+
+onflag {
+    say fps;
+}
@@ -0,0 +1,8 @@
+# expect: duplicate argument
+proc total x, x {
+    say $x;
+}
+
+onflag {
+    total 1, 2;
+}
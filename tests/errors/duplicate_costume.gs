@@ -0,0 +1,7 @@
+# expect: costume name is already used
+costumes "../looks/blank.svg" as "walk_2";
+costumes_from "../looks/walk.png" frames(4) size(16, 16);
+
+onflag {
+    say costume_name();
+}
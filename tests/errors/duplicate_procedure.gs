@@ -0,0 +1,12 @@
+# expect: procedure is already declared
+onflag {
+    greet;
+}
+
+proc greet {
+    say "hello";
+}
+
+proc greet {
+    say "hi";
+}
@@ -0,0 +1,6 @@
+# expect: images are too large
+costumes_from "huge.png" frames(2) size(16, 16);
+
+onflag {
+    say costume_name();
+}
@@ -0,0 +1,14 @@
+# expect: unrecognized argument
+
+onflag {
+    outer 1;
+}
+
+proc outer value, {
+    say $value;
+    show_value;
+}
+
+inline proc show_value {
+    say $value;
+}
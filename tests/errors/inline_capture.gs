@@ -0,0 +1,16 @@
+# expect: local variable hides a variable of an inline procedure
+
+onflag {
+    score = 0;
+    reset;
+    say score;
+}
+
+proc reset {
+    local score = 5;
+    clear_score;
+}
+
+inline proc clear_score {
+    score = 0;
+}
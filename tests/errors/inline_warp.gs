@@ -0,0 +1,11 @@
+# expect: inline procedure would lose its screen refresh mode
+
+onflag {
+    spin 3;
+}
+
+inline proc spin n, {
+    repeat $n {
+        turn_right 15;
+    }
+}
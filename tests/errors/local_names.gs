@@ -0,0 +1,15 @@
+# expect: which is also the name of the local variable `b_c` of another procedure
+onflag {
+    a;
+    a_b;
+}
+
+proc a {
+    local b_c = 1;
+    say b_c;
+}
+
+proc a_b {
+    local c = 2;
+    say c;
+}
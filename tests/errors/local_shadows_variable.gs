@@ -0,0 +1,12 @@
+# expect: which is also the name of the variable `count_total`
+var count_total = 0;
+
+onflag {
+    count;
+    say count_total;
+}
+
+proc count {
+    local total = 1;
+    say total;
+}
@@ -0,0 +1,8 @@
+# expect: macro argument is not a variable
+macro reset(value) {
+    value = 0;
+}
+
+onflag {
+    reset 1 + 2;
+}
@@ -0,0 +1,6 @@
+# expect: macro has the name of a reporter
+macro timer(x) => x * 2;
+
+onflag {
+    say timer(2);
+}
@@ -0,0 +1,8 @@
+# expect: statement macro used as an expression
+macro greet(name) {
+    say "hello " & name;
+}
+
+onflag {
+    say greet("world");
+}
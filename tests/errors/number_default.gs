@@ -0,0 +1,7 @@
+# expect: `lives` is declared as `number`
+# args: --strict
+var lives: number = "three";
+
+onflag {
+    say lives;
+}
@@ -0,0 +1,7 @@
+# expect: variable is not declared
+# args: --strict
+# Without `--strict`, `score` is declared by setting it, see `test_strict` in tools/run.
+onflag {
+    score = 1;
+    say score;
+}
@@ -0,0 +1,22 @@
+costumes "blank.svg";
+
+onflag {
+    say "enemy";
+    alert;
+}
+
+proc alert {
+    broadcast "message";
+}
+
+script {
+    onclick {
+        say "clicked";
+    }
+}
+
+script {
+    onclick {
+        say "clicked again";
+    }
+}
@@ -3,7 +3,76 @@ costumes "blank.svg";
 onflag {
     foo = 1;
     broadcast "message";
+    broadcast "a";
+    broadcast "b";
+    broadcast "c";
+    broadcast "d";
     broadcast_and_wait "message";
     broadcast foo;
     broadcast_and_wait foo;
+    broadcast "level" & foo;
+    broadcast "spawn" with foo + 1;
+    broadcast_and_wait "spawn" with foo + 2;
+}
+
+on "message" {
+    say "received";
+}
+
+# Runs too, a message may have several scripts.
+on "message" {
+    say "received again";
+}
+
+on "c" {
+    say "only c";
+}
+
+on "level1" {
+    say "level 1";
+}
+
+on ["a", "b", "c"] {
+    say "any of a, b or c";
+    say "received";
+}
+
+on ["d", "e"] {
+    if foo > 1 {
+        stop_this_script;
+    }
+    say "d or e";
+}
+
+on "a" {
+    say "only a";
+}
+
+on "spawn" data {
+    say $data;
+}
+
+onflag {
+    say "second script";
+}
+
+onloudness > 10 {
+    say loudness();
+}
+
+ontimer > 5 + 1 {
+    say timer();
+}
+
+init {
+    score = 0;
+    say "score: " & score;
+}
+
+onflag(priority = 1) {
+    say "first script";
+}
+
+onflag(priority = -1) {
+    say "last script";
 }
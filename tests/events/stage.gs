@@ -1 +1,5 @@
 costumes "blank.svg";
+
+on "spawn" data {
+    switch_backdrop $data;
+}
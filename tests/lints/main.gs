@@ -0,0 +1,18 @@
+costumes "blank.svg";
+
+# `goboscript lint --deny-warnings` fails on this project, `unused_variable` is only
+# a warning here, so it still builds.
+var spare;
+
+# `strict = true` requires variables to be declared and messages to be received.
+var count = 0;
+
+onflag {
+    repeat 3 {}
+    count += 1;
+    broadcast "start";
+}
+
+on "start" {
+    say count;
+}
@@ -1,5 +1,28 @@
 costumes "blank.svg";
 
+list levels from "levels.json";
+list scores from "scores.csv";
+
 onflag {
-    say "Hello, World!";
+    delete inventory;
+    add "sword" to inventory;
+    add "shield" to inventory;
+    add "potion" to inventory;
+    say inventory[3];
+    say inventory[10];
+    say length inventory;
+    say inventory.length;
+    say "sword" in inventory;
+    say inventory.contains("sword");
+}
+
+onclick {
+    for item in inventory {
+        say item;
+    }
+}
+
+onkey "space" {
+    say levels[3];
+    say scores[3];
 }
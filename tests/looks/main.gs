@@ -1,15 +1,20 @@
 costumes "blank.svg";
+costumes_from "walk.png" frames(8) size(16, 16);
+costumes "frames/*.svg";
 
 onflag {
     say "with duration", 2;
     say "without duration";
+    say __PROJECT_NAME & " " & __VERSION & " " & __BUILD_DATE;
     think "with duration", 2;
     think "without duration";
     switch_costume "dango";
     switch_costume costume_number();
+    switch_costume 2;
     next_costume;
     switch_backdrop "dango";
     switch_backdrop backdrop_number();
+    switch_backdrop 1;
     next_backdrop;
     change_size 10;
     set_size 100;
@@ -1 +1,2 @@
 costumes "blank.svg";
+costumes "blank.svg" as "menu";
@@ -7,11 +7,13 @@ onflag {
     goto_random_position;
     goto_mouse_pointer;
     goto "dango";
+    goto "random";
     goto x_position();
     goto 10, 20;
     glide_to_random_position 1;
     glide_to_mouse_pointer 1;
     glide "dango", 1;
+    glide "random", 1;
     glide y_position(), 1;
     glide 10, 20, 1;
     point_in_direction 45;
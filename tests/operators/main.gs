@@ -1,27 +1,59 @@
+#!goboscript 1.0
 costumes "blank.svg";
 
+macro double(x) => x * 2;
+macro clamp(value, low, high) => (value < low) * low + (value > high) * high
+    + (not (value < low or value > high)) * value;
+macro swap(a, b) {
+    local t = a;
+    a = b;
+    b = t;
+}
+
 onflag {
     lhs = 1;
     rhs = 2;
     say lhs + rhs;
+    # Expands to `(lhs + 1) * 2`.
+    say double(lhs + 1);
+    say clamp(double(rhs), 0, 3);
+    # Expands to its statements, with `lhs` and `rhs` assigned to.
+    swap lhs, rhs;
+    reorder;
     say lhs - rhs;
     say lhs * rhs;
     say lhs / rhs;
     say random(lhs, rhs);
+    say random(lhs, rhs,);
     say lhs > rhs;
     say lhs >= rhs;
     say lhs < rhs;
     say lhs <= rhs;
     say lhs == rhs;
     say lhs != rhs;
+    say 0 < lhs < 10;
     say key_pressed("up arrow") and key_pressed("down arrow");
     say key_pressed("up arrow") or key_pressed("down arrow");
     say not key_pressed("up arrow");
     say lhs & rhs;
+    say "a" & "b" & lhs;
+    say lhs & "c" & "d";
+    say "e" & ("f" & rhs);
     say lhs[rhs];
     say length rhs;
     say rhs in lhs;
     say lhs % rhs;
+    say -5 % 3;
+    say lhs // rhs;
+    say true;
+    say false;
+    say true and key_pressed("space");
+    say not false;
+    if true {
+        say lhs;
+    }
+    say lhs & rhs & lhs & rhs & lhs & rhs & lhs & rhs & lhs
+        & rhs & lhs & rhs & lhs & rhs & lhs & rhs & lhs;
     say round lhs;
     say abs lhs;
     say floor lhs;
@@ -38,3 +70,10 @@ onflag {
     say antiln lhs;
     say antilog lhs;
 }
+
+proc reorder {
+    local t = "kept";
+    # `t` of each copy of `swap` is its own variable, so this `t` is not changed.
+    swap lhs, rhs;
+    say t;
+}
@@ -0,0 +1,12 @@
+costumes "blank.svg";
+
+onflag {
+    erase_all;
+    set_pen_color "#ff0000";
+    set_pen_size 2;
+    change_pen_size 1;
+    pen_down;
+    move 10;
+    pen_up;
+    stamp;
+}
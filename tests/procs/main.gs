@@ -2,4 +2,89 @@ costumes "blank.svg";
 
 onflag {
     say "Hello, World!";
+    sum 1, 2;
+    outer 3;
+    todo;
+    draw 1;
+    draw 1, 2;
+}
+
+proc todo {}
+
+proc sum x, y, {
+    say $x + $y;
+}
+
+proc outer n, {
+    repeat $n {
+        inner $n * 2, length("abc" & $n);
+    }
+}
+
+proc inner a, b, {
+    sum $a, $b + 1;
+}
+
+onclick {
+    twice "hi";
+    add_up;
+}
+
+nowarp inline proc twice message, {
+    local count = 2;
+    repeat count {
+        say $message;
+    }
+    sum count, 1;
+}
+
+proc add_up {
+    local i = 10;
+    total = 0;
+    count_up 3;
+    count_up i;
+    say total;
+}
+
+inline proc count_up n, {
+    repeat $n {
+        total += 1;
+    }
+}
+
+onkey "space" {
+    pause 1;
+}
+
+proc pause seconds, {
+    wait $seconds;
+}
+
+proc draw x, {
+    local size = 1;
+    goto $x, size;
+}
+
+proc draw x, y, {
+    local size = 2;
+    goto $x, $y + size;
+}
+
+onkey "space" {
+    clamp 150;
+    limit 150;
+}
+
+# Warns, `n` is an argument, so this sets a sprite variable named `n`.
+proc clamp n, {
+    n = 100;
+    say $n - n;
+}
+
+proc limit n, {
+    local n = $n;
+    if n > 100 {
+        n = 100;
+    }
+    say n;
 }
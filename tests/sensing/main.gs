@@ -3,12 +3,21 @@ costumes "blank.svg";
 onflag {
     ask "What is your name?";
     foo = answer();
+    say number(answer()) == 0;
+    say text(foo);
+    ask "What is your name?" into foo;
+    ask_number "How old are you?" into foo;
     say touching_mouse_pointer();
     say touching_edge();
     say touching("foo");
     say touching(foo);
     say touching_color("#ff0000");
     say color_is_touching_color("#ff0000", "#00ff00");
+    color = "#0000ff";
+    say touching_color(color);
+    if touching_color("#ff0000") and not color_is_touching_color(color, "#00ff00") {
+        say "touching";
+    }
     say key_pressed("space");
     say key_pressed(foo);
     say mouse_down();
@@ -16,6 +25,8 @@ onflag {
     say mouse_y();
     set_drag_mode_draggable;
     set_drag_mode_not_draggable;
+    set_drag_mode "draggable";
+    set_drag_mode "not draggable";
     say loudness();
     say timer();
     reset_timer;
@@ -29,3 +40,11 @@ onflag {
     say days_since_2000();
     say username();
 }
+
+onclick {
+    measure "update" {
+        repeat 10 {
+            say timer();
+        }
+    }
+}
@@ -0,0 +1,10 @@
+# Built with `goboscript build --single tests/single.gs`, as a project with this sprite
+# and an empty Stage, both with a blank costume.
+var count = 0;
+
+onflag {
+    repeat 3 {
+        count += 1;
+        say count;
+    }
+}
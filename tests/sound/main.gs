@@ -14,3 +14,10 @@ onflag {
     change_volume 25;
     set_volume 0;
 }
+
+onclick {
+    set_tempo 120;
+    change_tempo volume() / 10;
+    rest 0.5;
+    say tempo();
+}
@@ -1,9 +1,15 @@
 costumes "blank.svg";
 
+var count: number = 0;
+var title: text = "level ";
+var untyped = -1.5;
+
 proc main {
     var = 1;
     local local_var = 2;
     global_var = 3;
+    high_score = var;
+    say high_score;
     say var + local_var + global_var;
     var += local_var;
     var -= local_var;
@@ -16,3 +22,33 @@ proc main {
 onflag {
     main;
 }
+
+enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+enum KeyCode {
+    Left = 37,
+    Up,
+    Right,
+    Down,
+    None = -1,
+}
+
+onclick {
+    count += 1;
+    title &= count;
+    untyped = "anything";
+    count = "lots";
+    say title & untyped;
+    say Direction.Up + Direction.Down + Direction.Left + Direction.Right;
+    say KeyCode.Left + KeyCode.Up + KeyCode.Right + KeyCode.Down + KeyCode.None;
+}
+
+onkey "space" {
+    say player::health;
+    say player::global_var;
+}
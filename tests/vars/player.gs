@@ -0,0 +1,10 @@
+costumes "blank.svg";
+
+onflag {
+    health = 100;
+}
+
+# Warns with `--warn-shared-globals`, `global_var` is also set by the Stage and `main`.
+onclick {
+    global_var = "clicked";
+}
@@ -1,5 +1,7 @@
 costumes "blank.svg";
 
+cloud high_score;
+
 onflag {
     global_var = 0;
 }